@@ -0,0 +1,231 @@
+//! Simulates bouncing particles in a compute shader and draws them as quads
+//! with the regular colored-vertex pipeline.
+
+use std::{
+    sync::Arc,
+    time::Instant
+};
+
+use winit::{
+    application::ApplicationHandler,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, EventLoop},
+    window::WindowId
+};
+
+use vulkano::{
+    buffer::{BufferContents, BufferUsage, Subbuffer},
+    command_buffer::CommandBufferUsage,
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    sync::GpuFuture
+};
+
+use learn_vulkano::{
+    allocator::Allocator,
+    compute::{self, ComputePass},
+    config::Config,
+    debug,
+    framework::Framework,
+    model::ColoredVertex,
+    renderer::Renderer,
+    swapchain::SwapchainState
+};
+
+const PARTICLE_COUNT: u32 = 4096;
+const PARTICLE_SIZE: f32 = 0.006;
+const LOCAL_SIZE: u32 = 64;
+
+#[derive(Clone, BufferContents)]
+#[repr(C)]
+struct Particle {
+    position: [f32; 2],
+    velocity: [f32; 2],
+    color: [f32; 4]
+}
+
+#[derive(BufferContents)]
+#[repr(C)]
+struct PushConstants {
+    delta_time: f32,
+    particle_size: f32,
+    particle_count: u32
+}
+
+/// Small deterministic generator so the example needs no extra crates.
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+fn initial_particles() -> Vec<Particle> {
+    let mut random = Lcg(7);
+    (0..PARTICLE_COUNT)
+        .map(|_| Particle {
+            position: [random.next() * 0.2 - 0.1, random.next() * 0.2 - 0.9],
+            velocity: [random.next() * 1.2 - 0.6, -random.next() * 1.5],
+            color: [0.3 + 0.7 * random.next(), 0.4 + 0.4 * random.next(), 0.9, 1.0]
+        })
+        .collect()
+}
+
+fn quad_indices() -> Vec<u32> {
+    (0..PARTICLE_COUNT)
+        .flat_map(|particle| {
+            let first = particle * 4;
+            [first, first + 1, first + 2, first + 2, first + 1, first + 3]
+        })
+        .collect()
+}
+
+struct ParticleApp {
+    framework: Framework,
+    allocator: Allocator,
+    renderer: Renderer,
+    simulation: ComputePass,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    vertex_buffer: Subbuffer<[ColoredVertex]>,
+    index_buffer: Subbuffer<[u32]>,
+    index_count: u32,
+    last_frame: Instant
+}
+
+impl ParticleApp {
+    fn new(event_loop: &ActiveEventLoop) -> Self {
+        let config = Config::load();
+        let framework = Framework::new(event_loop, &config);
+        let allocator = Allocator::new(framework.device.clone()).with_sharing(framework.resource_sharing());
+        let renderer = Renderer::new(framework.device.clone(), framework.swapchain.image_format());
+        let simulation = ComputePass::new(framework.device.clone(), "particles_comp.spv", "particle simulation");
+
+        let particle_buffer = allocator.alloc_storage_buffer(
+            &initial_particles(),
+            BufferUsage::empty(),
+            "particle buffer"
+        );
+        let vertices = vec![ColoredVertex::new([0.0; 3], [0.0; 3]); PARTICLE_COUNT as usize * 4];
+        let vertex_buffer = allocator.alloc_storage_buffer(
+            &vertices,
+            BufferUsage::VERTEX_BUFFER,
+            "particle vertex buffer"
+        );
+        let indices = quad_indices();
+        let index_buffer = allocator.alloc_index_buffer(&indices);
+
+        let descriptor_set = simulation.descriptor_set(&allocator, 0, [
+            WriteDescriptorSet::buffer(0, particle_buffer),
+            WriteDescriptorSet::buffer(1, vertex_buffer.clone())
+        ]);
+
+        ParticleApp {
+            framework,
+            allocator,
+            renderer,
+            simulation,
+            descriptor_set,
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            last_frame: Instant::now()
+        }
+    }
+    fn draw_frame(&mut self) {
+        let framework = &mut self.framework;
+        let acquired = framework.acquire_next_image().expect("Device lost.");
+        let Some((image_index, image_available)) = acquired else {
+            // A minimized window is redrawn once it is resized again.
+            if framework.swapchain_lifecycle.state() != SwapchainState::Minimized {
+                framework.window.request_redraw();
+            }
+            return;
+        };
+
+        let now = Instant::now();
+        let delta_time = (now - self.last_frame).as_secs_f32().min(0.05);
+        self.last_frame = now;
+
+        let mut builder = self.allocator.alloc_primary_builder(
+            framework.compute_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        );
+        let push_constants = PushConstants {
+            delta_time,
+            particle_size: PARTICLE_SIZE,
+            particle_count: PARTICLE_COUNT
+        };
+        self.simulation.record_dispatch(
+            &mut builder,
+            self.descriptor_set.clone(),
+            push_constants,
+            [compute::group_count(PARTICLE_COUNT, LOCAL_SIZE), 1, 1]
+        );
+        let command_buffer = builder.build().expect("Fail to build compute command buffer.");
+        let simulated = framework.execute_compute_command_buffer(command_buffer)
+            .expect("Fail to flush compute future.");
+
+        let mut builder = self.allocator.alloc_primary_builder(
+            framework.graphics_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        );
+        self.renderer.record_main_pass(
+            &mut builder,
+            self.vertex_buffer.clone(),
+            self.index_buffer.clone(),
+            self.index_count,
+            framework.swapchain_image_views[image_index as usize].clone()
+        );
+        let command_buffer = builder.build().expect("Fail to build command buffer.");
+
+        let render_finished = framework.execute_command_buffer(image_available.join(simulated), command_buffer)
+            .then_signal_semaphore_and_flush()
+            .expect("Fail to flush render finished future.");
+        let presented = framework.present_image(render_finished, image_index)
+            .then_signal_fence_and_flush()
+            .and_then(|presented| presented.wait(None));
+        framework.handle_present_result(presented).expect("Device lost.");
+        framework.window.request_redraw();
+    }
+}
+
+#[derive(Default)]
+struct OptionParticleApp(Option<ParticleApp>);
+
+impl ApplicationHandler for OptionParticleApp {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        self.0 = Some(ParticleApp::new(event_loop));
+    }
+    fn window_event(&mut self, _event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => {
+                self.0.take();
+            }
+            WindowEvent::Resized(_) => {
+                if let Some(app) = self.0.as_mut() {
+                    app.framework.invalidate_swapchain();
+                    app.framework.window.request_redraw();
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                if let Some(app) = self.0.as_mut() {
+                    app.draw_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.0.is_none() {
+            event_loop.exit();
+        }
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(debug::DEFAULT_LOG_FILTER)).init();
+    let event_loop = EventLoop::new().unwrap();
+    let mut app = OptionParticleApp::default();
+    event_loop.run_app(&mut app).unwrap();
+}
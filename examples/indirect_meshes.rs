@@ -0,0 +1,219 @@
+//! Draws a grid of triangles and quads from one shared vertex and index
+//! buffer with GPU-written indirect draws.
+
+use std::time::Instant;
+
+use winit::{
+    application::ApplicationHandler,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, EventLoop},
+    window::WindowId
+};
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{CommandBufferUsage, DrawIndexedIndirectCommand},
+    sync::GpuFuture
+};
+
+use glam::{Mat4, Quat, Vec3};
+
+use learn_vulkano::{
+    allocator::Allocator,
+    config::Config,
+    debug,
+    framework::Framework,
+    indirect::{self, DrawCommandWriter, GpuMeshArena, IndirectDraw, MeshArena},
+    model::{ColoredInstance, ColoredVertex},
+    renderer::Renderer,
+    swapchain::SwapchainState
+};
+
+const GRID_SIZE: u32 = 40;
+
+/// A triangle and a quad packed into one arena.
+fn shapes() -> MeshArena<ColoredVertex> {
+    let mut arena = MeshArena::default();
+    arena.add(&[
+        ColoredVertex::new([-0.5, -0.5, 0.0], [0.9, 0.5, 0.65]),
+        ColoredVertex::new([0.0, 0.5, 0.0], [1.0, 1.0, 1.0]),
+        ColoredVertex::new([0.5, -0.5, 0.0], [0.9, 0.5, 0.65])
+    ], &[0, 1, 2]);
+    arena.add(&[
+        ColoredVertex::new([-0.5, -0.5, 0.0], [0.2, 0.6, 0.9]),
+        ColoredVertex::new([-0.5, 0.5, 0.0], [1.0, 1.0, 1.0]),
+        ColoredVertex::new([0.5, -0.5, 0.0], [0.2, 0.6, 0.9]),
+        ColoredVertex::new([0.5, 0.5, 0.0], [1.0, 1.0, 1.0])
+    ], &[0, 1, 2, 2, 1, 3]);
+    arena
+}
+
+/// Instances for the left half of the grid, then for the right half.
+fn grid_instances() -> Vec<ColoredInstance> {
+    let cell = 2.0 / GRID_SIZE as f32;
+    let half = GRID_SIZE / 2;
+    let cells = (0..2).flat_map(|side| (0..half * GRID_SIZE).map(move |i| (side * half + i % half, i / half)));
+    cells
+        .map(|(column, row)| {
+            let (column, row) = (column as f32, row as f32);
+            let center = Vec3::new(-1.0 + (column + 0.5) * cell, -1.0 + (row + 0.5) * cell, 0.0);
+            let transform = Mat4::from_scale_rotation_translation(
+                Vec3::splat(cell * 0.7),
+                Quat::from_rotation_z((column + row) * 0.1),
+                center
+            );
+            ColoredInstance::new(transform, [1.0; 4])
+        })
+        .collect()
+}
+
+struct IndirectApp {
+    framework: Framework,
+    allocator: Allocator,
+    renderer: Renderer,
+    arena: GpuMeshArena<ColoredVertex>,
+    instance_buffer: Subbuffer<[ColoredInstance]>,
+    draws: Vec<IndirectDraw>,
+    draw_buffer: Subbuffer<[IndirectDraw]>,
+    commands: Subbuffer<[DrawIndexedIndirectCommand]>,
+    writer: DrawCommandWriter,
+    start: Instant
+}
+
+impl IndirectApp {
+    fn new(event_loop: &ActiveEventLoop) -> Self {
+        let config = Config::load();
+        let framework = Framework::new(event_loop, &config);
+        let allocator = Allocator::new(framework.device.clone()).with_sharing(framework.resource_sharing());
+        let renderer = Renderer::new(framework.device.clone(), framework.swapchain.image_format());
+        if !renderer.supports_indirect() {
+            log::warn!("The device does not support draw_indirect_first_instance, drawing from the host instead.");
+        }
+
+        let arena = shapes().upload(&allocator);
+        let instances = grid_instances();
+        let per_shape = instances.len() as u32 / 2;
+        let draws = vec![arena.meshes[0].draw(0, per_shape), arena.meshes[1].draw(per_shape, per_shape)];
+
+        IndirectApp {
+            writer: DrawCommandWriter::new(framework.device.clone()),
+            instance_buffer: allocator.alloc_vertex_buffer(&instances),
+            draw_buffer: allocator.alloc_storage_slice(&draws),
+            commands: indirect::alloc_draw_commands(&allocator, &draws),
+            framework,
+            allocator,
+            renderer,
+            arena,
+            draws,
+            start: Instant::now()
+        }
+    }
+    fn draw_frame(&mut self) {
+        let framework = &mut self.framework;
+        let acquired = framework.acquire_next_image().expect("Device lost.");
+        let Some((image_index, image_available)) = acquired else {
+            if framework.swapchain_lifecycle.state() != SwapchainState::Minimized {
+                framework.window.request_redraw();
+            }
+            return;
+        };
+
+        let extent = framework.swapchain.image_extent();
+        let aspect = extent[1] as f32 / extent[0] as f32;
+        let view_projection = Mat4::from_scale(Vec3::new(aspect.min(1.0), (1.0 / aspect).min(1.0), 1.0))
+            * Mat4::from_rotation_z(self.start.elapsed().as_secs_f32() * 0.2);
+
+        let indirect = self.renderer.supports_indirect();
+        let written = indirect.then(|| {
+            let mut builder = self.allocator.alloc_primary_builder(
+                framework.compute_queue.queue_family_index(),
+                CommandBufferUsage::OneTimeSubmit
+            );
+            self.writer.record(&mut builder, &self.allocator, self.draw_buffer.clone(), self.commands.clone());
+            let command_buffer = builder.build().expect("Fail to build compute command buffer.");
+            framework.execute_compute_command_buffer(command_buffer)
+                .expect("Fail to flush compute future.")
+        });
+        let mut builder = self.allocator.alloc_primary_builder(
+            framework.graphics_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        );
+        self.renderer.record_main_pass_draws(
+            &mut builder,
+            framework.swapchain_image_views[image_index as usize].clone(),
+            |builder| {
+                if indirect {
+                    self.renderer.record_indirect(
+                        builder,
+                        view_projection,
+                        &self.arena,
+                        self.instance_buffer.clone(),
+                        self.commands.clone()
+                    );
+                } else {
+                    self.renderer.record_draws(
+                        builder,
+                        view_projection,
+                        &self.arena,
+                        self.instance_buffer.clone(),
+                        &self.draws
+                    );
+                }
+            }
+        );
+        let command_buffer = builder.build().expect("Fail to build command buffer.");
+
+        let before = match written {
+            Some(written) => image_available.join(written).boxed(),
+            None => image_available.boxed()
+        };
+        let render_finished = framework.execute_command_buffer(before, command_buffer)
+            .then_signal_semaphore_and_flush()
+            .expect("Fail to flush render finished future.");
+        let presented = framework.present_image(render_finished, image_index)
+            .then_signal_fence_and_flush()
+            .and_then(|presented| presented.wait(None));
+        framework.handle_present_result(presented).expect("Device lost.");
+        framework.window.request_redraw();
+    }
+}
+
+#[derive(Default)]
+struct OptionIndirectApp(Option<IndirectApp>);
+
+impl ApplicationHandler for OptionIndirectApp {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        self.0 = Some(IndirectApp::new(event_loop));
+    }
+    fn window_event(&mut self, _event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => {
+                self.0.take();
+            }
+            WindowEvent::Resized(_) => {
+                if let Some(app) = self.0.as_mut() {
+                    app.framework.invalidate_swapchain();
+                    app.framework.window.request_redraw();
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                if let Some(app) = self.0.as_mut() {
+                    app.draw_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.0.is_none() {
+            event_loop.exit();
+        }
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(debug::DEFAULT_LOG_FILTER)).init();
+    let event_loop = EventLoop::new().unwrap();
+    let mut app = OptionIndirectApp::default();
+    event_loop.run_app(&mut app).unwrap();
+}
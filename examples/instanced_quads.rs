@@ -0,0 +1,172 @@
+//! Draws a grid of ten thousand quads with one instanced draw call: the
+//! quad is uploaded once and every copy reads its transform and color from
+//! a per-instance vertex buffer.
+
+use std::time::Instant;
+
+use winit::{
+    application::ApplicationHandler,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, EventLoop},
+    window::WindowId
+};
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::CommandBufferUsage,
+    sync::GpuFuture
+};
+
+use glam::{Mat4, Quat, Vec3};
+
+use learn_vulkano::{
+    allocator::Allocator,
+    config::Config,
+    debug,
+    framework::Framework,
+    model::{ColoredInstance, ColoredVertex},
+    renderer::Renderer
+};
+
+const GRID_SIZE: u32 = 100;
+
+fn grid_instances() -> Vec<ColoredInstance> {
+    let cell = 2.0 / GRID_SIZE as f32;
+    (0..GRID_SIZE * GRID_SIZE)
+        .map(|i| {
+            let (column, row) = ((i % GRID_SIZE) as f32, (i / GRID_SIZE) as f32);
+            let center = Vec3::new(-1.0 + (column + 0.5) * cell, -1.0 + (row + 0.5) * cell, 0.0);
+            let transform = Mat4::from_scale_rotation_translation(
+                Vec3::splat(cell * 0.7),
+                Quat::from_rotation_z((column + row) * 0.1),
+                center
+            );
+            let color = [column / GRID_SIZE as f32, row / GRID_SIZE as f32, 0.8, 1.0];
+            ColoredInstance::new(transform, color)
+        })
+        .collect()
+}
+
+struct InstancedApp {
+    framework: Framework,
+    allocator: Allocator,
+    renderer: Renderer,
+    vertex_buffer: Subbuffer<[ColoredVertex]>,
+    instance_buffer: Subbuffer<[ColoredInstance]>,
+    index_buffer: Subbuffer<[u32]>,
+    index_count: u32,
+    start: Instant
+}
+
+impl InstancedApp {
+    fn new(event_loop: &ActiveEventLoop) -> Self {
+        let config = Config::load();
+        let framework = Framework::new(event_loop, &config);
+        let allocator = Allocator::new(framework.device.clone());
+        let renderer = Renderer::new(framework.device.clone(), framework.swapchain.image_format());
+
+        let vertices = vec![
+            ColoredVertex::new([-0.5, -0.5, 0.0], [0.2, 0.6, 0.9]),
+            ColoredVertex::new([-0.5, 0.5, 0.0], [0.9, 0.5, 0.65]),
+            ColoredVertex::new([0.5, -0.5, 0.0], [0.9, 0.5, 0.65]),
+            ColoredVertex::new([0.5, 0.5, 0.0], [1.0, 1.0, 1.0])
+        ];
+        let vertex_buffer = allocator.alloc_vertex_buffer(&vertices);
+        let instance_buffer = allocator.alloc_vertex_buffer(&grid_instances());
+        let indices = vec![0, 1, 2, 2, 1, 3];
+        let index_buffer = allocator.alloc_index_buffer(&indices);
+
+        InstancedApp {
+            framework,
+            allocator,
+            renderer,
+            vertex_buffer,
+            instance_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            start: Instant::now()
+        }
+    }
+    fn draw_frame(&mut self) {
+        let framework = &mut self.framework;
+        let acquired = framework.acquire_next_image().expect("Device lost.");
+        let Some((image_index, image_available)) = acquired else {
+            framework.window.request_redraw();
+            return;
+        };
+
+        let extent = framework.swapchain.image_extent();
+        let aspect = extent[1] as f32 / extent[0] as f32;
+        let view_projection = Mat4::from_scale(Vec3::new(aspect.min(1.0), (1.0 / aspect).min(1.0), 1.0))
+            * Mat4::from_rotation_z(self.start.elapsed().as_secs_f32() * 0.2);
+
+        let mut builder = self.allocator.alloc_primary_builder(
+            framework.graphics_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        );
+        self.renderer.record_main_pass_draws(
+            &mut builder,
+            framework.swapchain_image_views[image_index as usize].clone(),
+            |builder| {
+                self.renderer.record_instances(
+                    builder,
+                    view_projection,
+                    self.vertex_buffer.clone(),
+                    self.instance_buffer.clone(),
+                    self.index_buffer.clone(),
+                    self.index_count
+                );
+            }
+        );
+        let command_buffer = builder.build().expect("Fail to build command buffer.");
+
+        let render_finished = framework.execute_command_buffer(image_available, command_buffer)
+            .then_signal_semaphore_and_flush()
+            .expect("Fail to flush render finished future.");
+        let presented = framework.present_image(render_finished, image_index)
+            .then_signal_fence_and_flush()
+            .and_then(|presented| presented.wait(None));
+        framework.handle_present_result(presented).expect("Device lost.");
+        framework.window.request_redraw();
+    }
+}
+
+#[derive(Default)]
+struct OptionInstancedApp(Option<InstancedApp>);
+
+impl ApplicationHandler for OptionInstancedApp {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        self.0 = Some(InstancedApp::new(event_loop));
+    }
+    fn window_event(&mut self, _event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => {
+                self.0.take();
+            }
+            WindowEvent::Resized(_) => {
+                if let Some(app) = self.0.as_mut() {
+                    app.framework.invalidate_swapchain();
+                    app.framework.window.request_redraw();
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                if let Some(app) = self.0.as_mut() {
+                    app.draw_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.0.is_none() {
+            event_loop.exit();
+        }
+    }
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(debug::DEFAULT_LOG_FILTER)).init();
+    let event_loop = EventLoop::new().unwrap();
+    let mut app = OptionInstancedApp::default();
+    event_loop.run_app(&mut app).unwrap();
+}
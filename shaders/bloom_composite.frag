@@ -0,0 +1,24 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+layout(set = 0, binding = 2) uniform texture2D bloom;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+// Adds the bloom chain onto the HDR scene. params.x: intensity.
+
+void main()
+{
+    vec4 scene = texture(sampler2D(input_color, input_sampler), uv);
+    vec3 glow = texture(sampler2D(bloom, input_sampler), uv).rgb;
+    out_color = vec4(scene.rgb + glow * push.params.x, scene.a);
+}
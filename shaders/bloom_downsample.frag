@@ -0,0 +1,48 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+// Dual-filter downsample. texel_size is the size of an input texel.
+// params.x: threshold, params.y: soft knee, params.z: 1.0 to apply the
+// threshold (first level only).
+
+vec3 sample_color(vec2 position)
+{
+    return texture(sampler2D(input_color, input_sampler), position).rgb;
+}
+
+vec3 prefilter(vec3 color)
+{
+    float brightness = max(color.r, max(color.g, color.b));
+    float knee = push.params.y;
+    float soft = clamp(brightness - push.params.x + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.00001);
+    float contribution = max(soft, brightness - push.params.x) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+void main()
+{
+    vec2 offset = push.texel_size;
+    vec3 color = sample_color(uv) * 4.0;
+    color += sample_color(uv + vec2(-offset.x, -offset.y));
+    color += sample_color(uv + vec2(offset.x, -offset.y));
+    color += sample_color(uv + vec2(-offset.x, offset.y));
+    color += sample_color(uv + vec2(offset.x, offset.y));
+    color /= 8.0;
+    if (push.params.z > 0.5) {
+        color = prefilter(color);
+    }
+    out_color = vec4(color, 1.0);
+}
@@ -0,0 +1,40 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+layout(set = 0, binding = 2) uniform texture2D current_level;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+// Dual-filter upsample of the lower level, added to the current level.
+// texel_size is the size of a lower-level texel; params.x scales the
+// filter radius.
+
+vec3 sample_lower(vec2 position)
+{
+    return texture(sampler2D(input_color, input_sampler), position).rgb;
+}
+
+void main()
+{
+    vec2 offset = push.texel_size * push.params.x;
+    vec3 color = sample_lower(uv + vec2(-2.0 * offset.x, 0.0));
+    color += sample_lower(uv + vec2(-offset.x, offset.y)) * 2.0;
+    color += sample_lower(uv + vec2(0.0, 2.0 * offset.y));
+    color += sample_lower(uv + vec2(offset.x, offset.y)) * 2.0;
+    color += sample_lower(uv + vec2(2.0 * offset.x, 0.0));
+    color += sample_lower(uv + vec2(offset.x, -offset.y)) * 2.0;
+    color += sample_lower(uv + vec2(0.0, -2.0 * offset.y));
+    color += sample_lower(uv + vec2(-offset.x, -offset.y)) * 2.0;
+    color /= 12.0;
+    color += texture(sampler2D(current_level, input_sampler), uv).rgb;
+    out_color = vec4(color, 1.0);
+}
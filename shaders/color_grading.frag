@@ -0,0 +1,28 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+layout(set = 0, binding = 2) uniform texture3D lut;
+
+// params.x: strength, params.y: LUT size. Expects input in 0..1, so run
+// it after tone mapping.
+
+void main()
+{
+    vec4 color = texture(sampler2D(input_color, input_sampler), uv);
+    float size = push.params.y;
+    vec3 coordinates = clamp(color.rgb, 0.0, 1.0) * ((size - 1.0) / size) + 0.5 / size;
+    vec3 graded = texture(sampler3D(lut, input_sampler), coordinates).rgb;
+    out_color = vec4(mix(color.rgb, graded, push.params.x), color.a);
+}
@@ -1,3 +1,35 @@
 D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\shader.vert -o vert.spv
 D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\shader.frag -o frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\particles.comp -o particles_comp.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\particles_simulate.comp -o particles_simulate_comp.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\particles.vert -o particles_vert.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\particles.frag -o particles_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\fullscreen.vert -o fullscreen_vert.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\tonemap.frag -o tonemap_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\gamma.frag -o gamma_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\fxaa.frag -o fxaa_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\vignette.frag -o vignette_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\color_grading.frag -o color_grading_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\bloom_downsample.frag -o bloom_downsample_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\bloom_upsample.frag -o bloom_upsample_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\bloom_composite.frag -o bloom_composite_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\lit.vert -o lit_vert.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\lit.frag -o lit_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\shadow.vert -o shadow_vert.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\shadow_debug.frag -o shadow_debug_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ibl_equirect_to_cube.comp -o ibl_equirect_to_cube_comp.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ibl_downsample_cube.comp -o ibl_downsample_cube_comp.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ibl_irradiance.comp -o ibl_irradiance_comp.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ibl_prefilter.comp -o ibl_prefilter_comp.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ibl_brdf_lut.comp -o ibl_brdf_lut_comp.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\skybox.vert -o skybox_vert.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\skybox.frag -o skybox_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\gbuffer.frag -o gbuffer_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\deferred_light.vert -o deferred_light_vert.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\deferred_light.frag -o deferred_light_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ssao_prepass.frag -o ssao_prepass_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ssao.frag -o ssao_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\ssao_blur.frag -o ssao_blur_frag.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\instanced.vert -o instanced_vert.spv
+D:\VulkanSDK\1.3.283.0\Bin\glslc.exe .\draw_commands.comp -o draw_commands_comp.spv
 pause
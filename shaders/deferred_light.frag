@@ -0,0 +1,54 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// Lighting subpass of the deferred path: shades the G-buffer with the same
+// BRDF, shadows and image-based lighting as `lit.frag`, one light volume
+// at a time, and is additively blended into the scene color. Shadow
+// normal bias uses the shading normal, since the G-buffer does not keep
+// the geometric one.
+
+#include "lighting.glsl"
+
+layout(input_attachment_index = 0, set = 0, binding = 10) uniform subpassInput albedo_input;
+layout(input_attachment_index = 1, set = 0, binding = 11) uniform subpassInput normal_input;
+layout(input_attachment_index = 2, set = 0, binding = 12) uniform subpassInput material_input;
+layout(input_attachment_index = 3, set = 0, binding = 13) uniform subpassInput depth_input;
+
+layout(push_constant) uniform PushConstants
+{
+    mat4 inverse_view_projection;
+    // xy: framebuffer size.
+    vec4 viewport;
+} push;
+
+layout(location = 0) flat in int light_index;
+
+layout(location = 0) out vec4 out_color;
+
+void main()
+{
+    float depth = subpassLoad(depth_input).r;
+    if (depth >= 1.0) {
+        discard;
+    }
+    vec2 ndc = gl_FragCoord.xy / push.viewport.xy * 2.0 - 1.0;
+    vec4 world = push.inverse_view_projection * vec4(ndc, depth, 1.0);
+    vec3 world_position = world.xyz / world.w;
+
+    vec4 albedo_occlusion = subpassLoad(albedo_input);
+    vec3 albedo = albedo_occlusion.rgb;
+    float occlusion = albedo_occlusion.a;
+    vec3 n = normalize(subpassLoad(normal_input).xyz);
+    vec4 metallic_roughness = subpassLoad(material_input);
+    float metallic = metallic_roughness.x;
+    float roughness = max(metallic_roughness.y, MIN_ROUGHNESS);
+    vec3 v = normalize(frame.camera_position.xyz - world_position);
+
+    vec3 radiance;
+    if (light_index < 0) {
+        radiance = ambient_light(n, v, albedo, metallic, roughness, occlusion);
+    } else {
+        radiance = light_radiance(lights[light_index], world_position, n, v, n, albedo, metallic, roughness);
+    }
+    out_color = vec4(radiance, 0.0);
+}
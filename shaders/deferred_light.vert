@@ -0,0 +1,74 @@
+#version 450
+
+// Light volumes of the deferred path, drawn as a triangle strip of four
+// vertices per instance. Instance `i` below the light count covers the
+// screen rectangle bounding the sphere of light `i`, or the whole screen
+// for directional lights. The last instance covers the whole screen for
+// the ambient term.
+
+layout(set = 0, binding = 0) uniform FrameUniforms
+{
+    mat4 view_projection;
+    vec4 camera_position;
+    vec4 camera_forward;
+    vec4 ambient;
+    vec4 environment;
+    ivec4 counts;
+    vec4 ambient_occlusion;
+} frame;
+
+struct Light
+{
+    vec4 position_range;
+    vec4 direction_type;
+    vec4 color;
+    vec4 cone_shadow;
+};
+
+layout(set = 0, binding = 4) readonly buffer LightBuffer
+{
+    Light lights[];
+};
+
+// Light index, or -1 for the ambient term.
+layout(location = 0) flat out int out_light;
+
+// Bounds in normalized device coordinates of the box around a sphere, or
+// the whole screen when part of it lies behind the camera.
+vec4 sphere_rect(vec3 center, float radius)
+{
+    vec2 low = vec2(1.0);
+    vec2 high = vec2(-1.0);
+    for (int i = 0; i < 8; i++) {
+        vec3 corner = center + radius * vec3(
+            (i & 1) == 0 ? -1.0 : 1.0,
+            (i & 2) == 0 ? -1.0 : 1.0,
+            (i & 4) == 0 ? -1.0 : 1.0
+        );
+        vec4 clip = frame.view_projection * vec4(corner, 1.0);
+        if (clip.w <= 1e-4) {
+            return vec4(-1.0, -1.0, 1.0, 1.0);
+        }
+        low = min(low, clip.xy / clip.w);
+        high = max(high, clip.xy / clip.w);
+    }
+    return clamp(vec4(low, high), -1.0, 1.0);
+}
+
+void main()
+{
+    int light = int(gl_InstanceIndex);
+    vec4 rect = vec4(-1.0, -1.0, 1.0, 1.0);
+    if (light < frame.counts.x) {
+        Light volume = lights[light];
+        if (int(volume.direction_type.w) != 0) {
+            rect = sphere_rect(volume.position_range.xyz, volume.position_range.w);
+        }
+    } else {
+        light = -1;
+    }
+    int vertex = int(gl_VertexIndex);
+    vec2 corner = vec2(float(vertex & 1), float(vertex >> 1));
+    out_light = light;
+    gl_Position = vec4(mix(rect.xy, rect.zw, corner), 0.0, 1.0);
+}
@@ -0,0 +1,61 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+// Fast approximate anti-aliasing on tone-mapped input, after Timothy
+// Lottes' FXAA. Uses only push.texel_size.
+
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+const vec3 LUMA = vec3(0.299, 0.587, 0.114);
+
+vec3 sample_color(vec2 position)
+{
+    return texture(sampler2D(input_color, input_sampler), position).rgb;
+}
+
+void main()
+{
+    vec2 texel = push.texel_size;
+    float luma_nw = dot(sample_color(uv + vec2(-1.0, -1.0) * texel), LUMA);
+    float luma_ne = dot(sample_color(uv + vec2(1.0, -1.0) * texel), LUMA);
+    float luma_sw = dot(sample_color(uv + vec2(-1.0, 1.0) * texel), LUMA);
+    float luma_se = dot(sample_color(uv + vec2(1.0, 1.0) * texel), LUMA);
+    vec4 center = texture(sampler2D(input_color, input_sampler), uv);
+    float luma_m = dot(center.rgb, LUMA);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 direction = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel;
+
+    vec3 color_a = 0.5 * (
+        sample_color(uv + direction * (1.0 / 3.0 - 0.5))
+        + sample_color(uv + direction * (2.0 / 3.0 - 0.5))
+    );
+    vec3 color_b = color_a * 0.5 + 0.25 * (
+        sample_color(uv - direction * 0.5)
+        + sample_color(uv + direction * 0.5)
+    );
+    float luma_b = dot(color_b, LUMA);
+    vec3 color = (luma_b < luma_min || luma_b > luma_max) ? color_a : color_b;
+    out_color = vec4(color, center.a);
+}
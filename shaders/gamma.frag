@@ -0,0 +1,22 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+// params.x: gamma.
+
+void main()
+{
+    vec4 color = texture(sampler2D(input_color, input_sampler), uv);
+    out_color = vec4(pow(max(color.rgb, vec3(0.0)), vec3(1.0 / push.params.x)), color.a);
+}
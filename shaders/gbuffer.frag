@@ -0,0 +1,70 @@
+#version 450
+
+// Geometry pass of the deferred path: writes the material of the nearest
+// surface to the G-buffer for `deferred_light.frag` to shade, and its
+// emission straight to the scene color.
+
+const float MIN_ROUGHNESS = 0.045;
+
+layout(set = 0, binding = 0) uniform FrameUniforms
+{
+    mat4 view_projection;
+    vec4 camera_position;
+    vec4 camera_forward;
+} frame;
+
+layout(set = 1, binding = 0) uniform MaterialUniforms
+{
+    vec4 base_color;
+    vec4 emissive;
+    // x: metallic, y: roughness, z: normal scale, w: occlusion strength.
+    vec4 params;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler material_sampler;
+layout(set = 1, binding = 2) uniform texture2D base_color_texture;
+layout(set = 1, binding = 3) uniform texture2D normal_texture;
+layout(set = 1, binding = 4) uniform texture2D metallic_roughness_texture;
+layout(set = 1, binding = 5) uniform texture2D occlusion_texture;
+layout(set = 1, binding = 6) uniform texture2D emissive_texture;
+
+layout(location = 0) in vec3 world_position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in vec3 color;
+
+layout(location = 0) out vec4 out_emissive;
+// rgb: base color, a: occlusion.
+layout(location = 1) out vec4 out_albedo;
+// xyz: world-space shading normal, w: distance along the camera's forward
+// axis, as `ssao_prepass.frag` writes it.
+layout(location = 2) out vec4 out_normal;
+// x: metallic, y: roughness.
+layout(location = 3) out vec4 out_material;
+
+void main()
+{
+    vec4 base_color = material.base_color * texture(sampler2D(base_color_texture, material_sampler), uv) * vec4(color, 1.0);
+    vec4 metallic_roughness = texture(sampler2D(metallic_roughness_texture, material_sampler), uv);
+    float metallic = clamp(material.params.x * metallic_roughness.b, 0.0, 1.0);
+    float roughness = clamp(material.params.y * metallic_roughness.g, MIN_ROUGHNESS, 1.0);
+    float occlusion = mix(1.0, texture(sampler2D(occlusion_texture, material_sampler), uv).r, material.params.w);
+    vec3 emissive = material.emissive.rgb * texture(sampler2D(emissive_texture, material_sampler), uv).rgb;
+
+    vec3 surface_normal = normalize(normal);
+    vec3 n = surface_normal;
+    if (material.params.z != 0.0) {
+        vec3 t = normalize(tangent.xyz - surface_normal * dot(surface_normal, tangent.xyz));
+        vec3 b = cross(surface_normal, t) * tangent.w;
+        vec3 tangent_normal = texture(sampler2D(normal_texture, material_sampler), uv).xyz * 2.0 - 1.0;
+        tangent_normal.xy *= material.params.z;
+        n = normalize(t * tangent_normal.x + b * tangent_normal.y + surface_normal * tangent_normal.z);
+    }
+
+    out_emissive = vec4(emissive, base_color.a);
+    out_albedo = vec4(base_color.rgb, occlusion);
+    float depth = dot(world_position - frame.camera_position.xyz, frame.camera_forward.xyz);
+    out_normal = vec4(n, depth);
+    out_material = vec4(metallic, roughness, 0.0, 0.0);
+}
@@ -0,0 +1,31 @@
+#version 450
+
+// Instanced colored mesh: the vertex buffer at binding 0 advances per
+// vertex, the instance buffer at binding 1 per instance.
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 color;
+// Columns of the instance's model matrix.
+layout(location = 2) in vec4 transform_x;
+layout(location = 3) in vec4 transform_y;
+layout(location = 4) in vec4 transform_z;
+layout(location = 5) in vec4 transform_w;
+layout(location = 6) in vec4 instance_color;
+// xyz: world-space offset applied after the transform.
+layout(location = 7) in vec4 instance_custom;
+
+layout(push_constant) uniform PushConstants
+{
+    mat4 view_projection;
+} push;
+
+layout(location = 0) out vec3 frag_color;
+
+void main()
+{
+    mat4 instance_transform = mat4(transform_x, transform_y, transform_z, transform_w);
+    vec4 world_position = instance_transform * vec4(position, 1.0);
+    world_position.xyz += instance_custom.xyz;
+    gl_Position = push.view_projection * world_position;
+    frag_color = color * instance_color.rgb;
+}
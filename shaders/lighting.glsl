@@ -0,0 +1,213 @@
+// Shared by `lit.frag` and `deferred_light.frag`: the frame bindings of
+// set 0 and metallic-roughness PBR, i.e. a Cook-Torrance specular term
+// (GGX distribution, height-correlated Smith visibility, Schlick Fresnel)
+// over a Lambert diffuse term, percentage-closer filtered shadows read
+// from the shadow atlas and split-sum image-based lighting.
+
+const float PI = 3.14159265;
+const float MIN_ROUGHNESS = 0.045;
+
+layout(set = 0, binding = 0) uniform FrameUniforms
+{
+    mat4 view_projection;
+    vec4 camera_position;
+    vec4 camera_forward;
+    vec4 ambient;
+    // x: intensity, y: highest prefiltered mip, z: 1.0 when the environment
+    // replaces the ambient term.
+    vec4 environment;
+    // x: light count.
+    ivec4 counts;
+    // x: strength of the ambient occlusion map, yz: inverse framebuffer
+    // size.
+    vec4 ambient_occlusion;
+} frame;
+
+layout(set = 0, binding = 1) uniform ShadowUniforms
+{
+    mat4 view_projections[8];
+    vec4 atlas_rects[8];
+    vec4 cascade_splits;
+    // x: normal bias, y: PCF radius in texels, z: atlas texel size,
+    // w: cascade count.
+    vec4 params;
+} shadows;
+
+layout(set = 0, binding = 2) uniform texture2D shadow_atlas;
+layout(set = 0, binding = 3) uniform samplerShadow shadow_sampler;
+
+struct Light
+{
+    vec4 position_range;
+    // w: 0 directional, 1 point, 2 spot.
+    vec4 direction_type;
+    vec4 color;
+    // x, y: cosines of the inner and outer angles, z: shadow view.
+    vec4 cone_shadow;
+};
+
+layout(set = 0, binding = 4) readonly buffer LightBuffer
+{
+    Light lights[];
+};
+
+layout(set = 0, binding = 5) uniform textureCube irradiance_map;
+layout(set = 0, binding = 6) uniform textureCube prefiltered_map;
+layout(set = 0, binding = 7) uniform texture2D brdf_lut;
+layout(set = 0, binding = 8) uniform sampler environment_sampler;
+// Screen-space ambient occlusion, scaling the ambient term.
+layout(set = 0, binding = 9) uniform texture2D ambient_occlusion_map;
+
+float shadow_factor(int view, vec3 position, vec3 surface_normal)
+{
+    if (view < 0) {
+        return 1.0;
+    }
+    vec3 biased_position = position + surface_normal * shadows.params.x;
+    vec4 clip = shadows.view_projections[view] * vec4(biased_position, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+    if (ndc.z <= 0.0 || ndc.z >= 1.0 || abs(ndc.x) > 1.0 || abs(ndc.y) > 1.0) {
+        return 1.0;
+    }
+    vec4 rect = shadows.atlas_rects[view];
+    vec2 shadow_uv = rect.xy + (ndc.xy * 0.5 + 0.5) * rect.zw;
+    float texel = shadows.params.z;
+    vec2 uv_min = rect.xy + vec2(texel * 0.5);
+    vec2 uv_max = rect.xy + rect.zw - vec2(texel * 0.5);
+    int radius = int(shadows.params.y);
+    float lit = 0.0;
+    float taps = 0.0;
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            vec2 tap = clamp(shadow_uv + vec2(float(x), float(y)) * texel, uv_min, uv_max);
+            lit += texture(sampler2DShadow(shadow_atlas, shadow_sampler), vec3(tap, ndc.z));
+            taps += 1.0;
+        }
+    }
+    return lit / taps;
+}
+
+int cascade_view(int first_view, vec3 position)
+{
+    if (first_view < 0) {
+        return -1;
+    }
+    float depth = dot(position - frame.camera_position.xyz, frame.camera_forward.xyz);
+    int cascade_count = int(shadows.params.w);
+    for (int i = 0; i < cascade_count; i++) {
+        if (depth < shadows.cascade_splits[i]) {
+            return first_view + i;
+        }
+    }
+    return -1;
+}
+
+float distribution_ggx(float n_dot_h, float alpha)
+{
+    float alpha2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+float visibility_smith_ggx(float n_dot_v, float n_dot_l, float alpha)
+{
+    float alpha2 = alpha * alpha;
+    float view = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    float light = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    return 0.5 / max(view + light, 1e-5);
+}
+
+vec3 fresnel_schlick(float v_dot_h, vec3 f0)
+{
+    return f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+// Fresnel averaged over a rough lobe, for ambient light.
+vec3 fresnel_schlick_roughness(float n_dot_v, vec3 f0, float roughness)
+{
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+}
+
+vec3 environment_light(vec3 n, vec3 v, vec3 albedo, float metallic, float roughness)
+{
+    float n_dot_v = max(dot(n, v), 1e-4);
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    vec3 irradiance = textureLod(samplerCube(irradiance_map, environment_sampler), n, 0.0).rgb;
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo * irradiance;
+    vec3 r = reflect(-v, n);
+    float lod = roughness * frame.environment.y;
+    vec3 prefiltered = textureLod(samplerCube(prefiltered_map, environment_sampler), r, lod).rgb;
+    vec2 split_sum = textureLod(sampler2D(brdf_lut, environment_sampler), vec2(n_dot_v, roughness), 0.0).rg;
+    vec3 specular = prefiltered * (f0 * split_sum.x + split_sum.y);
+    return (diffuse + specular) * frame.environment.x;
+}
+
+// Reflected radiance towards `v` per unit of incoming radiance from `l`,
+// including the cosine term.
+vec3 brdf(vec3 n, vec3 v, vec3 l, vec3 albedo, float metallic, float roughness)
+{
+    vec3 h = normalize(v + l);
+    float n_dot_l = max(dot(n, l), 0.0);
+    float n_dot_v = max(dot(n, v), 1e-4);
+    float n_dot_h = max(dot(n, h), 0.0);
+    float v_dot_h = max(dot(v, h), 0.0);
+    float alpha = roughness * roughness;
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = fresnel_schlick(v_dot_h, f0);
+    vec3 specular = distribution_ggx(n_dot_h, alpha) * visibility_smith_ggx(n_dot_v, n_dot_l, alpha) * fresnel;
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// Fraction of ambient light reaching the pixel, from the screen-space
+// ambient occlusion map.
+float ambient_visibility()
+{
+    vec2 screen_uv = gl_FragCoord.xy * frame.ambient_occlusion.yz;
+    float visibility = texture(sampler2D(ambient_occlusion_map, environment_sampler), screen_uv).r;
+    return mix(1.0, visibility, frame.ambient_occlusion.x);
+}
+
+// Inverse-square falloff windowed to reach zero at `range`.
+float distance_attenuation(float distance, float range)
+{
+    float window = clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+    return window * window / max(distance * distance, 1e-4);
+}
+
+// Ambient light reaching the pixel, from the environment when there is
+// one, darkened by `occlusion` and the ambient occlusion map.
+vec3 ambient_light(vec3 n, vec3 v, vec3 albedo, float metallic, float roughness, float occlusion)
+{
+    occlusion *= ambient_visibility();
+    if (frame.environment.z > 0.5) {
+        return environment_light(n, v, albedo, metallic, roughness) * occlusion;
+    }
+    return frame.ambient.rgb * albedo * occlusion;
+}
+
+// Radiance reflected towards `v` from `light`, shadowed with a normal bias
+// along `surface_normal`.
+vec3 light_radiance(Light light, vec3 position, vec3 n, vec3 v, vec3 surface_normal, vec3 albedo, float metallic, float roughness)
+{
+    int type = int(light.direction_type.w);
+    vec3 l;
+    float attenuation = 1.0;
+    float shadow;
+    if (type == 0) {
+        l = -normalize(light.direction_type.xyz);
+        shadow = shadow_factor(cascade_view(int(light.cone_shadow.z), position), position, surface_normal);
+    } else {
+        vec3 to_light = light.position_range.xyz - position;
+        float distance = length(to_light);
+        l = to_light / distance;
+        attenuation = distance_attenuation(distance, light.position_range.w);
+        if (type == 2) {
+            float cone_angle = dot(-l, normalize(light.direction_type.xyz));
+            attenuation *= smoothstep(light.cone_shadow.y, light.cone_shadow.x, cone_angle);
+        }
+        shadow = shadow_factor(int(light.cone_shadow.z), position, surface_normal);
+    }
+    return brdf(n, v, l, albedo, metallic, roughness) * light.color.rgb * attenuation * shadow;
+}
@@ -0,0 +1,58 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+// Forward shading of a mesh with its material, every light of the light
+// buffer and the ambient or environment light.
+
+#include "lighting.glsl"
+
+layout(set = 1, binding = 0) uniform MaterialUniforms
+{
+    vec4 base_color;
+    vec4 emissive;
+    // x: metallic, y: roughness, z: normal scale, w: occlusion strength.
+    vec4 params;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler material_sampler;
+layout(set = 1, binding = 2) uniform texture2D base_color_texture;
+layout(set = 1, binding = 3) uniform texture2D normal_texture;
+layout(set = 1, binding = 4) uniform texture2D metallic_roughness_texture;
+layout(set = 1, binding = 5) uniform texture2D occlusion_texture;
+layout(set = 1, binding = 6) uniform texture2D emissive_texture;
+
+layout(location = 0) in vec3 world_position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in vec3 color;
+
+layout(location = 0) out vec4 out_color;
+
+void main()
+{
+    vec4 base_color = material.base_color * texture(sampler2D(base_color_texture, material_sampler), uv) * vec4(color, 1.0);
+    vec4 metallic_roughness = texture(sampler2D(metallic_roughness_texture, material_sampler), uv);
+    float metallic = clamp(material.params.x * metallic_roughness.b, 0.0, 1.0);
+    float roughness = clamp(material.params.y * metallic_roughness.g, MIN_ROUGHNESS, 1.0);
+    float occlusion = mix(1.0, texture(sampler2D(occlusion_texture, material_sampler), uv).r, material.params.w);
+    vec3 emissive = material.emissive.rgb * texture(sampler2D(emissive_texture, material_sampler), uv).rgb;
+
+    vec3 surface_normal = normalize(normal);
+    vec3 n = surface_normal;
+    if (material.params.z != 0.0) {
+        vec3 t = normalize(tangent.xyz - surface_normal * dot(surface_normal, tangent.xyz));
+        vec3 b = cross(surface_normal, t) * tangent.w;
+        vec3 tangent_normal = texture(sampler2D(normal_texture, material_sampler), uv).xyz * 2.0 - 1.0;
+        tangent_normal.xy *= material.params.z;
+        n = normalize(t * tangent_normal.x + b * tangent_normal.y + surface_normal * tangent_normal.z);
+    }
+    vec3 v = normalize(frame.camera_position.xyz - world_position);
+
+    vec3 radiance = ambient_light(n, v, base_color.rgb, metallic, roughness, occlusion);
+    for (int i = 0; i < frame.counts.x; i++) {
+        radiance += light_radiance(lights[i], world_position, n, v, surface_normal, base_color.rgb, metallic, roughness);
+    }
+
+    out_color = vec4(radiance + emissive, base_color.a);
+}
@@ -0,0 +1,43 @@
+#version 450
+
+// Forward-lit mesh. Normals and tangents are transformed by the upper 3x3
+// of the model matrix, so models must be scaled uniformly.
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in vec3 color;
+
+layout(set = 0, binding = 0) uniform FrameUniforms
+{
+    mat4 view_projection;
+    vec4 camera_position;
+    vec4 camera_forward;
+    vec4 ambient;
+    vec4 environment;
+    ivec4 counts;
+    vec4 ambient_occlusion;
+} frame;
+
+layout(push_constant) uniform PushConstants
+{
+    mat4 model;
+} push;
+
+layout(location = 0) out vec3 out_world_position;
+layout(location = 1) out vec3 out_normal;
+layout(location = 2) out vec4 out_tangent;
+layout(location = 3) out vec2 out_uv;
+layout(location = 4) out vec3 out_color;
+
+void main()
+{
+    vec4 world_position = push.model * vec4(position, 1.0);
+    out_world_position = world_position.xyz;
+    out_normal = mat3(push.model) * normal;
+    out_tangent = vec4(mat3(push.model) * tangent.xyz, tangent.w);
+    out_uv = uv;
+    out_color = color;
+    gl_Position = frame.view_projection * world_position;
+}
@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec4 in_color;
+layout(location = 1) in vec2 in_corner;
+
+layout(location = 0) out vec4 out_color;
+
+void main()
+{
+    float falloff = clamp(1.0 - dot(in_corner, in_corner), 0.0, 1.0);
+    out_color = vec4(in_color.rgb, in_color.a * falloff);
+}
@@ -0,0 +1,62 @@
+#version 450
+
+// Expands every particle into a camera-facing quad of six vertices. Dead
+// particles collapse to a degenerate triangle pair and are never rasterized.
+
+struct Particle
+{
+    vec4 position_age;
+    vec4 velocity_lifetime;
+};
+
+layout(set = 0, binding = 0) uniform ParticleUniforms
+{
+    mat4 view_projection;
+    vec4 camera_right;
+    vec4 camera_up;
+    vec4 color_curve[16];
+    vec4 size_curve[4];
+} uniforms;
+
+layout(set = 0, binding = 1) readonly buffer Particles
+{
+    Particle particles[];
+};
+
+layout(location = 0) out vec4 out_color;
+layout(location = 1) out vec2 out_corner;
+
+const vec2 corners[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(-1.0, 1.0),
+    vec2(-1.0, 1.0), vec2(1.0, -1.0), vec2(1.0, 1.0)
+);
+
+float size_sample(uint index)
+{
+    return uniforms.size_curve[index / 4u][index % 4u];
+}
+
+void main()
+{
+    Particle particle = particles[gl_VertexIndex / 6];
+    vec2 corner = corners[gl_VertexIndex % 6];
+    float age = particle.position_age.w;
+    float lifetime = particle.velocity_lifetime.w;
+    if (age >= lifetime) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        out_color = vec4(0.0);
+        out_corner = corner;
+        return;
+    }
+
+    float position = clamp(age / lifetime, 0.0, 1.0) * 15.0;
+    uint first = min(uint(position), 14u);
+    float blend = position - float(first);
+    vec4 color = mix(uniforms.color_curve[first], uniforms.color_curve[first + 1u], blend);
+    float size = mix(size_sample(first), size_sample(first + 1u), blend);
+
+    vec3 offset = (uniforms.camera_right.xyz * corner.x + uniforms.camera_up.xyz * corner.y) * size;
+    gl_Position = uniforms.view_projection * vec4(particle.position_age.xyz + offset, 1.0);
+    out_color = color;
+    out_corner = corner;
+}
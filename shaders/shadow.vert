@@ -0,0 +1,16 @@
+#version 450
+
+// Depth-only shadow pass: transforms meshes into a light's clip space.
+
+layout(location = 0) in vec3 position;
+
+layout(push_constant) uniform PushConstants
+{
+    mat4 view_projection;
+    mat4 model;
+} push;
+
+void main()
+{
+    gl_Position = push.view_projection * push.model * vec4(position, 1.0);
+}
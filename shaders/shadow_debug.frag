@@ -0,0 +1,16 @@
+#version 450
+
+// Shows the shadow atlas depth as grayscale, near surfaces dark.
+
+layout(set = 0, binding = 0) uniform texture2D shadow_atlas;
+layout(set = 0, binding = 1) uniform sampler atlas_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+void main()
+{
+    float depth = texture(sampler2D(shadow_atlas, atlas_sampler), uv).r;
+    out_color = vec4(vec3(depth), 1.0);
+}
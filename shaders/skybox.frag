@@ -0,0 +1,21 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform textureCube environment;
+layout(set = 0, binding = 1) uniform sampler environment_sampler;
+
+layout(push_constant) uniform PushConstants
+{
+    mat4 inverse_view_projection;
+    // x: mip level, y: intensity.
+    vec4 params;
+} push;
+
+layout(location = 0) in vec3 direction;
+
+layout(location = 0) out vec4 out_color;
+
+void main()
+{
+    vec3 color = textureLod(samplerCube(environment, environment_sampler), normalize(direction), push.params.x).rgb;
+    out_color = vec4(color * push.params.y, 1.0);
+}
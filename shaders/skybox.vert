@@ -0,0 +1,25 @@
+#version 450
+
+// A triangle covering the viewport on the far plane, passing on the world
+// direction through each corner. Draw with three vertices and no vertex
+// buffer.
+
+layout(push_constant) uniform PushConstants
+{
+    // Inverse of the projection times the view rotation, without the
+    // camera translation.
+    mat4 inverse_view_projection;
+    // x: mip level, y: intensity.
+    vec4 params;
+} push;
+
+layout(location = 0) out vec3 out_direction;
+
+void main()
+{
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    vec4 position = vec4(uv * 2.0 - 1.0, 1.0, 1.0);
+    vec4 world = push.inverse_view_projection * position;
+    out_direction = world.xyz / world.w;
+    gl_Position = position;
+}
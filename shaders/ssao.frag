@@ -0,0 +1,81 @@
+#version 450
+
+// Screen-space ambient occlusion: the fraction of a hemisphere kernel
+// around each pixel's normal that is not hidden behind the scene depth,
+// from the G-buffer or the prepass. A tiled noise texture rotates the
+// kernel per pixel; the blur pass removes the resulting pattern.
+
+const int MAX_SAMPLES = 64;
+
+layout(set = 0, binding = 0) uniform texture2D normal_depth;
+layout(set = 0, binding = 1) uniform sampler point_sampler;
+layout(set = 0, binding = 2) uniform texture2D noise;
+layout(set = 0, binding = 3) uniform sampler noise_sampler;
+
+layout(set = 0, binding = 4) uniform SsaoUniforms
+{
+    mat4 view_projection;
+    mat4 inverse_view_projection;
+    vec4 camera_position;
+    vec4 camera_forward;
+    // xy: noise tiles across the screen.
+    vec4 noise_scale;
+    vec4 kernel[MAX_SAMPLES];
+} ssao;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_occlusion;
+
+// params.x: radius, params.y: bias, params.z: sample count.
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+vec3 world_position_at(vec2 position, float depth)
+{
+    vec4 far = ssao.inverse_view_projection * vec4(position * 2.0 - 1.0, 1.0, 1.0);
+    vec3 direction = normalize(far.xyz / far.w - ssao.camera_position.xyz);
+    return ssao.camera_position.xyz + direction * (depth / dot(direction, ssao.camera_forward.xyz));
+}
+
+void main()
+{
+    vec4 center = texture(sampler2D(normal_depth, point_sampler), uv);
+    if (center.w <= 0.0) {
+        out_occlusion = vec4(1.0);
+        return;
+    }
+    vec3 n = normalize(center.xyz);
+    vec3 position = world_position_at(uv, center.w);
+    vec3 random = texture(sampler2D(noise, noise_sampler), uv * ssao.noise_scale.xy).xyz * 2.0 - 1.0;
+    vec3 t = normalize(random - n * dot(random, n));
+    if (any(isnan(t))) {
+        t = abs(n.x) < 0.9 ? normalize(cross(n, vec3(1.0, 0.0, 0.0))) : normalize(cross(n, vec3(0.0, 1.0, 0.0)));
+    }
+    vec3 b = cross(n, t);
+
+    float radius = push.params.x;
+    float bias = push.params.y;
+    int sample_count = min(int(push.params.z), MAX_SAMPLES);
+    float occlusion = 0.0;
+    for (int i = 0; i < sample_count; i++) {
+        vec3 offset = ssao.kernel[i].xyz;
+        vec3 sample_position = position + (t * offset.x + b * offset.y + n * offset.z) * radius;
+        vec4 clip = ssao.view_projection * vec4(sample_position, 1.0);
+        if (clip.w <= 0.0) {
+            continue;
+        }
+        vec2 sample_uv = clip.xy / clip.w * 0.5 + 0.5;
+        float scene_depth = texture(sampler2D(normal_depth, point_sampler), sample_uv).w;
+        if (scene_depth <= 0.0) {
+            continue;
+        }
+        float sample_depth = dot(sample_position - ssao.camera_position.xyz, ssao.camera_forward.xyz);
+        float range = smoothstep(0.0, 1.0, radius / max(abs(center.w - scene_depth), 1e-4));
+        occlusion += (scene_depth <= sample_depth - bias ? 1.0 : 0.0) * range;
+    }
+    out_occlusion = vec4(1.0 - occlusion / float(max(sample_count, 1)));
+}
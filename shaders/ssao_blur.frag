@@ -0,0 +1,44 @@
+#version 450
+
+// Bilateral blur of the ambient occlusion: a 5x5 box whose taps are
+// weighted down across depth and normal discontinuities, so occlusion
+// does not bleed across silhouettes.
+
+layout(set = 0, binding = 0) uniform texture2D input_occlusion;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+layout(set = 0, binding = 2) uniform texture2D normal_depth;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_occlusion;
+
+// params.x: depth sharpness. texel_size is the size of an occlusion
+// texel.
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+void main()
+{
+    vec4 center = texture(sampler2D(normal_depth, input_sampler), uv);
+    if (center.w <= 0.0) {
+        out_occlusion = vec4(1.0);
+        return;
+    }
+    float total = 0.0;
+    float weights = 0.0;
+    for (int y = -2; y <= 2; y++) {
+        for (int x = -2; x <= 2; x++) {
+            vec2 tap = uv + vec2(float(x), float(y)) * push.texel_size;
+            vec4 neighbor = texture(sampler2D(normal_depth, input_sampler), tap);
+            float depth_weight = exp(-abs(neighbor.w - center.w) / center.w * push.params.x);
+            float normal_weight = pow(max(dot(neighbor.xyz, center.xyz), 0.0), 8.0);
+            float weight = depth_weight * normal_weight + 1e-4;
+            total += texture(sampler2D(input_occlusion, input_sampler), tap).r * weight;
+            weights += weight;
+        }
+    }
+    out_occlusion = vec4(total / weights);
+}
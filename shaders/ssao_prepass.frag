@@ -0,0 +1,23 @@
+#version 450
+
+// Normal and depth prepass of the screen-space ambient occlusion, run with
+// `lit.vert`: stores the world-space normal and the distance along the
+// camera's forward axis. Background pixels keep the cleared depth of 0.
+
+layout(set = 0, binding = 0) uniform FrameUniforms
+{
+    mat4 view_projection;
+    vec4 camera_position;
+    vec4 camera_forward;
+} frame;
+
+layout(location = 0) in vec3 world_position;
+layout(location = 1) in vec3 normal;
+
+layout(location = 0) out vec4 out_normal_depth;
+
+void main()
+{
+    float depth = dot(world_position - frame.camera_position.xyz, frame.camera_forward.xyz);
+    out_normal_depth = vec4(normalize(normal), depth);
+}
@@ -0,0 +1,34 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+// params.x: exposure, params.y: operator (0 Reinhard, 1 ACES).
+
+vec3 aces(vec3 color)
+{
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+}
+
+void main()
+{
+    vec4 color = texture(sampler2D(input_color, input_sampler), uv);
+    vec3 exposed = color.rgb * push.params.x;
+    vec3 mapped = push.params.y < 0.5 ? exposed / (1.0 + exposed) : aces(exposed);
+    out_color = vec4(mapped, color.a);
+}
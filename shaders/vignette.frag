@@ -0,0 +1,25 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform texture2D input_color;
+layout(set = 0, binding = 1) uniform sampler input_sampler;
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants
+{
+    vec4 params;
+    vec2 texel_size;
+} push;
+
+// params.x: intensity, params.y: radius, params.z: smoothness. Distances
+// are normalized so the corners sit at 1.0.
+
+void main()
+{
+    vec4 color = texture(sampler2D(input_color, input_sampler), uv);
+    float distance_to_center = distance(uv, vec2(0.5)) * 1.41421356;
+    float vignette = smoothstep(push.params.y, push.params.y + push.params.z, distance_to_center);
+    out_color = vec4(color.rgb * (1.0 - push.params.x * vignette), color.a);
+}
@@ -8,20 +8,32 @@ use vulkano::{
         allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo}
     },
     buffer::{
-        BufferUsage, Subbuffer,
+        Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer,
         allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo}
     },
     memory::allocator::{
-        StandardMemoryAllocator, MemoryTypeFilter
+        StandardMemoryAllocator, MemoryTypeFilter, AllocationCreateInfo
     },
+    descriptor_set::allocator::{StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo},
+    sync::Sharing,
     pipeline::graphics::vertex_input::Vertex
 };
 
+use smallvec::SmallVec;
+
+use crate::debug;
+
 pub struct Allocator {
     pub command_buffer_allocator: StandardCommandBufferAllocator,
+    pub descriptor_set_allocator: StandardDescriptorSetAllocator,
     pub memory_allocator: Arc<StandardMemoryAllocator>,
     pub vertex_buffer_allocator: SubbufferAllocator,
-    pub index_buffer_allocator: SubbufferAllocator
+    pub index_buffer_allocator: SubbufferAllocator,
+    pub uniform_buffer_allocator: SubbufferAllocator,
+    pub storage_buffer_allocator: SubbufferAllocator,
+    /// Sharing mode of long-lived buffers and images, which may be used by
+    /// more than one queue family.
+    pub sharing: Sharing<SmallVec<[u32; 4]>>
 }
 
 impl Allocator {
@@ -43,6 +55,11 @@ impl Allocator {
             StandardCommandBufferAllocator::new(device.clone(), create_info)
         };
 
+        let descriptor_set_allocator = {
+            let create_info = StandardDescriptorSetAllocatorCreateInfo::default();
+            StandardDescriptorSetAllocator::new(device.clone(), create_info)
+        };
+
         let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
 
         let vertex_buffer_allocator = Self::new_subbuffer_allocator(
@@ -59,13 +76,36 @@ impl Allocator {
                 | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
         );
 
+        let uniform_buffer_allocator = Self::new_subbuffer_allocator(
+            memory_allocator.clone(),
+            BufferUsage::UNIFORM_BUFFER,
+            MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+
+        let storage_buffer_allocator = Self::new_subbuffer_allocator(
+            memory_allocator.clone(),
+            BufferUsage::STORAGE_BUFFER,
+            MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        );
+
         Allocator {
             command_buffer_allocator,
+            descriptor_set_allocator,
             memory_allocator,
             vertex_buffer_allocator,
-            index_buffer_allocator
+            index_buffer_allocator,
+            uniform_buffer_allocator,
+            storage_buffer_allocator,
+            sharing: Sharing::Exclusive
         }
     }
+    /// Shares long-lived resources as `sharing`, usually
+    /// `Framework::resource_sharing`.
+    pub fn with_sharing(self, sharing: Sharing<SmallVec<[u32; 4]>>) -> Self {
+        Allocator { sharing, ..self }
+    }
     pub fn alloc_primary_builder(
         &self,
         queue_family_index: u32,
@@ -95,4 +135,50 @@ impl Allocator {
         drop(write_guard);
         index_buffer
     }
-}
\ No newline at end of file
+    pub fn alloc_uniform_buffer<T: BufferContents>(&self, data: T) -> Subbuffer<T> {
+        let uniform_buffer = self.uniform_buffer_allocator.allocate_sized()
+            .expect("Fail to allocate uniform buffer");
+        *uniform_buffer.write()
+            .expect("Fail to obtain write guard of uniform buffer.") = data;
+        uniform_buffer
+    }
+    /// Allocates a per-frame storage buffer holding `data`, which must not
+    /// be empty. Use [`Self::alloc_storage_buffer`] for long-lived buffers.
+    pub fn alloc_storage_slice<T: BufferContents + Clone>(&self, data: &[T]) -> Subbuffer<[T]> {
+        let storage_buffer = self.storage_buffer_allocator.allocate_slice(data.len() as DeviceSize)
+            .expect("Fail to allocate storage buffer");
+        storage_buffer.write()
+            .expect("Fail to obtain write guard of storage buffer.")
+            .clone_from_slice(data);
+        storage_buffer
+    }
+    /// Allocates a storage buffer filled with `data`. `extra_usage` adds
+    /// usages on top of `STORAGE_BUFFER`, e.g. `VERTEX_BUFFER` when graphics
+    /// reads what compute writes. The buffer is shared as [`Self::sharing`]
+    /// says.
+    pub fn alloc_storage_buffer<T: BufferContents + Clone>(
+        &self,
+        data: &[T],
+        extra_usage: BufferUsage,
+        name: &str
+    ) -> Subbuffer<[T]> {
+        let create_info = BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | extra_usage,
+            sharing: self.sharing.clone(),
+            ..Default::default()
+        };
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let storage_buffer = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            create_info,
+            allocation_info,
+            data.iter().cloned()
+        ).expect("Fail to allocate storage buffer.");
+        debug::set_object_name(&**storage_buffer.buffer(), name);
+        storage_buffer
+    }
+}
@@ -3,84 +3,595 @@ use winit::{
     event_loop::ActiveEventLoop,
     window::WindowId,
     dpi::PhysicalSize,
-    event::WindowEvent
+    event::{WindowEvent, KeyEvent, ElementState},
+    keyboard::{PhysicalKey, KeyCode}
 };
 
-use vulkano::sync::GpuFuture;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Instant
+};
+
+use glam::Vec3;
+
+use vulkano::{
+    command_buffer::CommandBufferUsage,
+    sync::GpuFuture
+};
 
 use crate::{
-    framework::Framework,
+    config::Config,
+    framework::{Framework, DeviceLost, check_device_lost},
     allocator::Allocator,
-    model::ColoredVertex,
-    renderer::Renderer
+    bounds::CullStats,
+    camera::Camera,
+    capture::{self, CaptureWriter, FrameSequence},
+    debug,
+    ibl::ImageBasedLighting,
+    lighting::LitScene,
+    particles::{EmitterConfig, ParticleSystem},
+    post_process::{PostProcessor, PostStack},
+    renderer::{Renderer, RenderPath, RenderStage},
+    render_target::RenderTarget,
+    scene::Scene,
+    scene_file::{FileWatch, RenderSettings, SceneFile},
+    stats::FrameStats,
+    swapchain::SwapchainState,
+    texture
 };
 
+/// Called after the GPU context was rebuilt, so that device resources such
+/// as meshes and textures can be uploaded again.
+pub type DeviceRestoredCallback = Box<dyn FnMut(&mut App)>;
+
+/// Where the scene is saved when it was not loaded from a file.
+const DEFAULT_SCENE_PATH: &str = "scene.ron";
+/// Loaded when no scene file is given and `demo_scene` is enabled.
+const DEMO_SCENE_PATH: &str = "assets/scenes/demo.ron";
+
 pub struct App {
+    pub config: Config,
     pub framework: Framework,
     pub allocator: Allocator,
     pub renderer: Renderer,
-    pub minimized: bool
+    /// Present when the scene renders through the post-processing stack.
+    pub post_processor: Option<PostProcessor>,
+    pub post_stack: PostStack,
+    pub particles: Option<ParticleSystem>,
+    /// Present when there is a scene to draw.
+    pub lit_scene: Option<LitScene>,
+    /// The meshes and lights `lit_scene` draws. It holds device
+    /// resources, so it is rebuilt with the device.
+    pub scene: Scene,
+    /// Polls `config.scene` so that edits to the file are reloaded.
+    pub scene_watch: Option<FileWatch>,
+    /// Overlays the shadow atlas on the scene.
+    pub show_shadow_maps: bool,
+    /// Overlays the bounding boxes of the visible meshes.
+    pub show_bounds: bool,
+    /// Shown in the window title.
+    pub frame_stats: FrameStats,
+    pub camera: Camera,
+    pub last_frame: Instant,
+    pub capture_writer: CaptureWriter,
+    pub screenshot_requested: bool,
+    pub frame_sequence: Option<FrameSequence>,
+    pub minimized: bool,
+    pub device_restored_callbacks: Vec<DeviceRestoredCallback>
 }
 impl App {
-    fn new(event_loop: &ActiveEventLoop) -> Self {
-        let framework = Framework::new(event_loop);
+    fn new_particles(framework: &Framework, allocator: &Allocator, renderer: &Renderer, config: &Config) -> Option<ParticleSystem> {
+        let emitter_config = EmitterConfig::load(config.particle_emitter.as_ref()?)?;
+        Some(ParticleSystem::new(framework.device.clone(), allocator, renderer.main_subpass(), emitter_config))
+    }
+    fn new_post_processor(framework: &Framework, allocator: &Allocator, config: &Config) -> Option<PostProcessor> {
+        let deferred_warning = || if config.render_path == RenderPath::Deferred {
+            log::warn!("Deferred shading needs post-processing, falling back to forward shading.");
+        };
+        if !config.post_processing {
+            deferred_warning();
+            return None;
+        }
+        if !framework.can_blit() {
+            log::warn!("Swapchain cannot be blitted into, post-processing is disabled.");
+            deferred_warning();
+            return None;
+        }
+        let mut post_processor = PostProcessor::new(allocator, framework.swapchain.image_extent(), config.render_path);
+        post_processor.set_bloom(allocator, config.bloom);
+        Some(post_processor)
+    }
+    /// Reads the scene file at `path`, logging a warning and returning
+    /// `None` when it is missing or malformed.
+    fn read_scene_file(path: &str) -> Option<SceneFile> {
+        match SceneFile::load(Path::new(path)) {
+            Ok(file) => Some(file),
+            Err(error) => {
+                log::warn!("Fail to load scene {path}: {error}.");
+                None
+            }
+        }
+    }
+    /// Builds `file`, read from `config.scene`, or else an empty scene.
+    fn new_scene(framework: &Framework, allocator: &Allocator, config: &Config, file: Option<&SceneFile>) -> Scene {
+        if let Some((path, file)) = config.scene.as_deref().zip(file) {
+            let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+            match file.instantiate(allocator, &framework.transfer_queue, directory) {
+                Ok(scene) => return scene,
+                Err(error) => log::warn!("Fail to load scene {path}: {error}.")
+            }
+        }
+        Scene::default()
+    }
+    fn new_lit_scene(framework: &Framework, allocator: &Allocator, renderer: &Renderer, config: &Config) -> Option<LitScene> {
+        config.scene.as_ref()?;
+        let mut scene = LitScene::new(
+            allocator,
+            &framework.transfer_queue,
+            renderer,
+            config.shadows,
+            config.ssao,
+            Vec::new()
+        );
+        if let Some(path) = &config.environment_map {
+            let cache_directory = config.ibl_cache_directory.as_deref().map(Path::new);
+            let (transfer_queue, compute_queue) = (&framework.transfer_queue, &framework.compute_queue);
+            match ImageBasedLighting::load(allocator, transfer_queue, compute_queue, Path::new(path), cache_directory) {
+                Ok(environment) => scene.renderer.environment = Some(ImageBasedLighting {
+                    intensity: config.environment_intensity,
+                    ..environment
+                }),
+                Err(error) => log::warn!("Fail to load environment map {path}: {error}.")
+            }
+        }
+        if let Some(path) = &config.skybox_cube {
+            match texture::load_cube(Path::new(path)) {
+                Ok(cube) => scene.sky = Some(texture::upload_now(allocator, &framework.transfer_queue, |builder| {
+                    texture::record_cube_upload(builder, allocator, &cube, "skybox cube")
+                })),
+                Err(error) => log::warn!("Fail to load skybox cube {path}: {error}.")
+            }
+        }
+        scene.show_skybox = config.skybox;
+        Some(scene)
+    }
+    /// Everything that depends on the render pass of the scene, and thus
+    /// on the render path.
+    fn new_scene_resources(
+        framework: &Framework,
+        allocator: &Allocator,
+        config: &Config
+    ) -> (Renderer, Option<PostProcessor>, Option<ParticleSystem>, Option<LitScene>) {
         let format = framework.swapchain.image_format();
-        let allocator = Allocator::new(framework.device.clone());
-        let renderer = Renderer::new(framework.device.clone(), format);
+        let post_processor = Self::new_post_processor(framework, allocator, config);
+        let renderer = match &post_processor {
+            Some(post_processor) => Renderer::for_target(framework.device.clone(), &post_processor.scene),
+            None => Renderer::new(framework.device.clone(), format)
+        };
+        let particles = Self::new_particles(framework, allocator, &renderer, config);
+        let lit_scene = Self::new_lit_scene(framework, allocator, &renderer, config);
+        (renderer, post_processor, particles, lit_scene)
+    }
+    fn new_device_resources(
+        framework: &Framework,
+        config: &Config,
+        scene_file: Option<&SceneFile>
+    ) -> (Allocator, Scene, Renderer, Option<PostProcessor>, Option<ParticleSystem>, Option<LitScene>) {
+        let allocator = Allocator::new(framework.device.clone()).with_sharing(framework.resource_sharing());
+        let scene = Self::new_scene(framework, &allocator, config, scene_file);
+        let (renderer, post_processor, particles, lit_scene) = Self::new_scene_resources(framework, &allocator, config);
+        (allocator, scene, renderer, post_processor, particles, lit_scene)
+    }
+    fn new(event_loop: &ActiveEventLoop) -> Self {
+        let mut config = Config::load();
+        if let Some(path) = env::args().nth(1) {
+            config.scene = Some(path);
+        }
+        if config.scene.is_none() && config.demo_scene {
+            config.scene = Some(String::from(DEMO_SCENE_PATH));
+        }
+        let mut camera = Camera {
+            position: Vec3::new(2.5, 1.5, 3.5),
+            ..Default::default()
+        };
+        let scene_file = config.scene.as_deref().and_then(Self::read_scene_file);
+        if let Some(file) = &scene_file {
+            file.settings.apply(&mut config);
+            camera = file.camera.into();
+        }
+        let scene_watch = config.scene.as_ref().map(|path| FileWatch::new(PathBuf::from(path)));
+        let framework = Framework::new(event_loop, &config);
+        let (allocator, scene, renderer, post_processor, particles, lit_scene) =
+            Self::new_device_resources(&framework, &config, scene_file.as_ref());
+        let post_stack = PostStack::default_for(framework.swapchain.image_format());
         App {
+            config,
+            framework,
+            allocator,
+            renderer,
+            post_processor,
+            post_stack,
+            particles,
+            lit_scene,
+            scene,
+            scene_watch,
+            show_shadow_maps: false,
+            show_bounds: false,
+            frame_stats: FrameStats::default(),
+            camera,
+            last_frame: Instant::now(),
+            capture_writer: CaptureWriter::new(),
+            screenshot_requested: false,
+            frame_sequence: None,
+            minimized: false,
+            device_restored_callbacks: Vec::new()
+        }
+    }
+    pub fn on_device_restored(&mut self, callback: impl FnMut(&mut App) + 'static) {
+        self.device_restored_callbacks.push(Box::new(callback));
+    }
+    /// Tears down everything created from the lost device and builds it
+    /// again, then lets the registered callbacks re-upload their resources.
+    fn recover_device(self) -> Self {
+        let App {
+            config,
             framework,
             allocator,
             renderer,
-            minimized: false
+            post_processor,
+            post_stack,
+            particles,
+            lit_scene,
+            scene,
+            scene_watch,
+            show_shadow_maps,
+            show_bounds,
+            frame_stats,
+            camera,
+            last_frame,
+            capture_writer,
+            screenshot_requested,
+            frame_sequence,
+            minimized,
+            device_restored_callbacks
+        } = self;
+        drop(lit_scene);
+        drop(scene);
+        drop(particles);
+        drop(renderer);
+        drop(post_processor);
+        drop(allocator);
+        let framework = framework.recover_device();
+        let scene_file = config.scene.as_deref().and_then(Self::read_scene_file);
+        // Runtime toggles such as bloom, SSAO and the render path write to
+        // the config, so rebuilding from it keeps them.
+        let (allocator, scene, renderer, post_processor, particles, lit_scene) =
+            Self::new_device_resources(&framework, &config, scene_file.as_ref());
+        let mut app = App {
+            config,
+            framework,
+            allocator,
+            renderer,
+            post_processor,
+            post_stack,
+            particles,
+            lit_scene,
+            scene,
+            scene_watch,
+            show_shadow_maps,
+            show_bounds,
+            frame_stats,
+            camera,
+            last_frame,
+            capture_writer,
+            screenshot_requested,
+            frame_sequence,
+            minimized,
+            device_restored_callbacks: Vec::new()
+        };
+        let mut callbacks = device_restored_callbacks;
+        for callback in callbacks.iter_mut() {
+            callback(&mut app);
+        }
+        app.device_restored_callbacks = callbacks;
+        app.framework.window.request_redraw();
+        app
+    }
+    /// Saves the next presented frame to the capture directory.
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+    /// Starts or stops writing every frame to numbered files. While active,
+    /// each frame advances the simulation by the configured timestep.
+    pub fn toggle_frame_sequence(&mut self) {
+        self.frame_sequence = match self.frame_sequence.take() {
+            Some(sequence) => {
+                log::info!("Stopped frame capture after {} frames.", sequence.frame);
+                None
+            }
+            None => {
+                let directory = Path::new(&self.config.capture_directory)
+                    .join(format!("sequence_{}", capture::timestamp()));
+                log::info!("Capturing frames to {}.", directory.display());
+                Some(FrameSequence::new(directory, self.config.capture_timestep))
+            }
+        };
+    }
+    /// Copies `target` to the host and saves it as a PNG in the background.
+    /// Blocks until the GPU has finished the copy.
+    pub fn save_render_target(&self, target: &RenderTarget, path: PathBuf) -> Result<(), DeviceLost> {
+        let queue = &self.framework.graphics_queue;
+        let mut builder = self.allocator.alloc_primary_builder(
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit
+        );
+        let readback = capture::record_readback(&mut builder, &self.allocator, target.color.image().clone());
+        let command_buffer = check_device_lost(builder.build(), "Fail to build readback command buffer.")?;
+        check_device_lost(
+            vulkano::sync::now(self.framework.device.clone())
+                .then_execute(queue.clone(), command_buffer)
+                .expect("Fail to execute readback.")
+                .then_signal_fence_and_flush()
+                .and_then(|copied| copied.wait(None)),
+            "Fail to wait for readback."
+        )?;
+        match readback.to_rgba8() {
+            Some(rgba) => self.capture_writer.save(path, readback.extent, rgba),
+            None => log::warn!("Cannot capture {} in format {:?}.", target.name, readback.format)
+        }
+        Ok(())
+    }
+    /// Enables or disables the post-processing pass at `index`.
+    pub fn toggle_post_pass(&mut self, index: usize) {
+        if let Some(enabled) = self.post_stack.toggle(index) {
+            let name = self.post_stack.passes[index].effect.name();
+            log::info!("Post-processing pass {name} {}.", if enabled { "enabled" } else { "disabled" });
+        }
+    }
+    /// Turns bloom on or off.
+    pub fn toggle_bloom(&mut self) {
+        self.config.bloom.enabled = !self.config.bloom.enabled;
+        if let Some(post_processor) = self.post_processor.as_mut() {
+            post_processor.set_bloom(&self.allocator, self.config.bloom);
+        }
+        log::info!("Bloom {}.", if self.config.bloom.enabled { "enabled" } else { "disabled" });
+    }
+    /// Switches the scene between forward and deferred shading.
+    pub fn toggle_render_path(&mut self) {
+        self.config.render_path = match self.config.render_path {
+            RenderPath::Forward => RenderPath::Deferred,
+            RenderPath::Deferred => RenderPath::Forward
+        };
+        self.rebuild_scene_resources();
+        log::info!("Render path {:?}.", self.renderer.path);
+    }
+    /// Rebuilds the renderer, post processor, particles and lit scene from
+    /// the config.
+    fn rebuild_scene_resources(&mut self) {
+        self.lit_scene = None;
+        self.particles = None;
+        self.post_processor = None;
+        let (renderer, post_processor, particles, lit_scene) =
+            Self::new_scene_resources(&self.framework, &self.allocator, &self.config);
+        self.renderer = renderer;
+        self.post_processor = post_processor;
+        self.particles = particles;
+        self.lit_scene = lit_scene;
+    }
+    /// Reloads the scene file once it changes on disk, along with its
+    /// camera and render settings. A file that fails to load is reported
+    /// and the current scene kept.
+    pub fn reload_changed_scene(&mut self) {
+        let Some(watch) = self.scene_watch.as_mut() else {
+            return;
+        };
+        if !watch.changed() {
+            return;
         }
+        let path = watch.path.to_string_lossy().into_owned();
+        let Some(file) = Self::read_scene_file(&path) else {
+            return;
+        };
+        let directory = watch.path.parent().unwrap_or(Path::new(""));
+        self.scene = match file.instantiate(&self.allocator, &self.framework.transfer_queue, directory) {
+            Ok(scene) => scene,
+            Err(error) => {
+                log::warn!("Fail to load scene {path}: {error}.");
+                return;
+            }
+        };
+        self.camera = file.camera.into();
+        if file.settings != RenderSettings::from_config(&self.config) {
+            file.settings.apply(&mut self.config);
+            self.rebuild_scene_resources();
+        }
+        log::info!("Reloaded scene {path}.");
     }
-    fn draw_frame(&mut self) -> bool {
+    /// Writes the scene, camera and render settings to the scene file, or
+    /// to `scene.ron` when none was given.
+    pub fn save_scene(&mut self) {
+        let path = self.config.scene.clone().unwrap_or_else(|| String::from(DEFAULT_SCENE_PATH));
+        let file = SceneFile::capture(&self.scene, &self.camera, &self.config);
+        match file.save(Path::new(&path)) {
+            Ok(()) => log::info!("Saved scene to {path}."),
+            Err(error) => log::warn!("Fail to save scene {path}: {error}.")
+        }
+        if let Some(watch) = self.scene_watch.as_mut() {
+            watch.reset();
+        }
+    }
+    /// Turns screen-space ambient occlusion on or off.
+    pub fn toggle_ssao(&mut self) {
+        self.config.ssao.enabled = !self.config.ssao.enabled;
+        if let Some(scene) = self.lit_scene.as_mut() {
+            scene.renderer.ssao.set_config(&self.allocator, self.config.ssao);
+        }
+        log::info!("Ambient occlusion {}.", if self.config.ssao.enabled { "enabled" } else { "disabled" });
+    }
+    fn handle_key(&mut self, key_code: KeyCode) {
+        match key_code {
+            KeyCode::Digit1 => self.toggle_post_pass(0),
+            KeyCode::Digit2 => self.toggle_post_pass(1),
+            KeyCode::Digit3 => self.toggle_post_pass(2),
+            KeyCode::Digit4 => self.toggle_post_pass(3),
+            KeyCode::Digit5 => self.toggle_post_pass(4),
+            KeyCode::KeyB => self.toggle_bloom(),
+            KeyCode::KeyM => self.show_shadow_maps = !self.show_shadow_maps,
+            KeyCode::KeyK => self.show_bounds = !self.show_bounds,
+            KeyCode::KeyG => self.toggle_render_path(),
+            KeyCode::KeyO => self.toggle_ssao(),
+            KeyCode::F5 => self.save_scene(),
+            KeyCode::F12 => self.request_screenshot(),
+            KeyCode::F10 => self.toggle_frame_sequence(),
+            KeyCode::KeyV => {
+                let vsync = !self.framework.swapchain_config.vsync;
+                self.framework.set_vsync(vsync);
+            }
+            #[cfg(debug_assertions)]
+            KeyCode::F9 => self.framework.simulate_device_lost(),
+            _ => {}
+        }
+    }
+    fn log_window_event(&self, event: &WindowEvent) {
+        if !self.config.log_window_events {
+            return;
+        }
+        let motion = matches!(event, WindowEvent::CursorMoved { .. } | WindowEvent::AxisMotion { .. });
+        if motion && !self.config.log_motion_events {
+            return;
+        }
+        log::debug!(target: "window_event", "{event:?}");
+    }
+    fn draw_frame(&mut self) -> Result<bool, DeviceLost> {
+        self.reload_changed_scene();
         let framework = &mut self.framework;
         let allocator = &self.allocator;
         let renderer = &self.renderer;
-        let (image_index, image_available) = {
-            let mut current_info = framework.acquire_next_image();
-            if current_info.is_none() {
-                if framework.recreate_swapchain() {
-                    current_info = framework.acquire_next_image();
-                }
+        let camera = &self.camera;
+        let Some((image_index, image_available)) = framework.acquire_next_image()? else {
+            if framework.swapchain_lifecycle.state() == SwapchainState::Minimized {
+                return Ok(false);
             }
-            if current_info.is_some() { current_info.unwrap() }
-            else { return false; }
+            framework.window.request_redraw();
+            return Ok(true);
         };
 
-        let vertices = vec![
-            ColoredVertex::new([-0.5, -0.5, 0.0], [0.2, 0.6, 0.9]),
-            ColoredVertex::new([-0.5, 0.5, 0.0], [0.9, 0.5, 0.65]),
-            ColoredVertex::new([0.5, -0.5, 0.0], [0.9, 0.5, 0.65]),
-            ColoredVertex::new([0.5, 0.5, 0.0], [1.0, 1.0, 1.0])
-        ];
-        let vertex_buffer = allocator.alloc_vertex_buffer(&vertices);
-        let indices = vec![0, 1, 2, 2, 1, 3];
-        let index_buffer = allocator.alloc_index_buffer(&indices);
+        let now = Instant::now();
+        let delta_time = match &self.frame_sequence {
+            Some(sequence) => sequence.timestep,
+            None => (now - self.last_frame).as_secs_f32().min(0.05)
+        };
+        self.last_frame = now;
 
-        let command_buffer = renderer.record_command_buffer(
-            allocator,
+        let simulated = match self.particles.as_mut() {
+            Some(particles) => {
+                let mut builder = allocator.alloc_primary_builder(
+                    framework.compute_queue.queue_family_index(),
+                    CommandBufferUsage::OneTimeSubmit
+                );
+                particles.record_simulation(&mut builder, delta_time);
+                let command_buffer = check_device_lost(builder.build(), "Fail to build compute command buffer.")?;
+                debug::set_object_name(&*command_buffer, "compute command buffer");
+                Some(check_device_lost(
+                    framework.execute_compute_command_buffer(command_buffer),
+                    "Fail to flush compute future."
+                )?)
+            }
+            None => None
+        };
+        let mut builder = allocator.alloc_primary_builder(
             framework.graphics_queue.queue_family_index(),
-            vertex_buffer,
-            index_buffer,
-            indices.len() as u32,
-            framework.swapchain_image_views[image_index as usize].clone()
+            CommandBufferUsage::OneTimeSubmit
         );
-        
-        let render_finished = framework.execute_command_buffer(image_available, command_buffer)
-            .then_signal_semaphore_and_flush()
-            .expect("Fail to flush render finished future.");
+        let extent = framework.swapchain.image_extent();
+        let lights = &self.scene.lights();
+        let mut culling = CullStats::default();
+        if let Some(scene) = self.lit_scene.as_mut() {
+            scene.meshes = self.scene.draw_list();
+            culling = scene.cull(camera, extent);
+            scene.record_ssao(&mut builder, allocator, camera, extent);
+        }
+        let lit_scene = self.lit_scene.as_ref();
+        let shadow_plan = lit_scene.map(|scene| scene.record_shadows(&mut builder, lights, camera, extent));
+        let particles = self.particles.as_ref();
+        let show_shadow_maps = self.show_shadow_maps;
+        let show_bounds = self.show_bounds;
+        let draw = |builder: &mut _, stage: RenderStage| {
+            if let Some((scene, plan)) = lit_scene.zip(shadow_plan.as_ref()) {
+                scene.record_draw(builder, allocator, stage, camera, extent, lights, plan);
+            }
+            if !matches!(stage, RenderStage::Forward) {
+                return;
+            }
+            if let Some(particles) = particles {
+                particles.record_draw(builder, allocator, camera, extent);
+            }
+            if let Some(scene) = lit_scene.filter(|_| show_bounds) {
+                scene.record_bounds(builder, allocator, camera, extent);
+            }
+            if let Some(scene) = lit_scene.filter(|_| show_shadow_maps) {
+                scene.debug_view.record(builder, allocator, &scene.shadows, extent);
+            }
+        };
+        match self.post_processor.as_mut() {
+            Some(post_processor) => {
+                post_processor.resize(allocator, extent);
+                renderer.record_target_pass_stages(&mut builder, &post_processor.scene, draw);
+                let output = framework.swapchain_images[image_index as usize].clone();
+                post_processor.record(&mut builder, allocator, &self.post_stack, output);
+            }
+            None => renderer.record_main_pass_draws(
+                &mut builder,
+                framework.swapchain_image_views[image_index as usize].clone(),
+                |builder| draw(builder, RenderStage::Forward)
+            )
+        }
+        let capturing = self.screenshot_requested || self.frame_sequence.is_some();
+        let readback = if capturing && framework.can_capture() {
+            let image = framework.swapchain_images[image_index as usize].clone();
+            Some(capture::record_readback(&mut builder, allocator, image))
+        }
+        else {
+            None
+        };
+        let command_buffer = check_device_lost(builder.build(), "Fail to build command buffer.")?;
+        debug::set_object_name(&*command_buffer, "main command buffer");
+
+        let before = match simulated {
+            Some(simulated) => image_available.join(simulated).boxed(),
+            None => image_available.boxed()
+        };
+        let render_finished = check_device_lost(
+            framework.execute_command_buffer(before, command_buffer)
+                .then_signal_semaphore_and_flush(),
+            "Fail to flush render finished future."
+        )?;
 
         let presented = framework.present_image(render_finished, image_index)
             .then_signal_fence_and_flush()
-            .expect("Fail to flush presented future.");
+            .and_then(|presented| presented.wait(None));
+        framework.handle_present_result(presented)?;
+
+        if capturing {
+            self.screenshot_requested = false;
+            match readback.and_then(|readback| Some((readback.extent, readback.to_rgba8()?))) {
+                Some((extent, rgba)) => {
+                    let path = match self.frame_sequence.as_mut() {
+                        Some(sequence) => sequence.next_path(),
+                        None => capture::screenshot_path(Path::new(&self.config.capture_directory))
+                    };
+                    self.capture_writer.save(path, extent, rgba);
+                }
+                None => log::warn!("Cannot capture swapchain images of format {:?}.", framework.swapchain.image_format())
+            }
+        }
 
-        presented.wait(None)
-            .expect("Fail to wait for presenting.");
-        
+        if self.frame_stats.end_frame(delta_time, culling) {
+            framework.window.set_title(&format!("learn-vulkano: {}", self.frame_stats));
+        }
         framework.window.request_redraw();
-        true
+        Ok(true)
     }
 }
 
@@ -97,7 +608,9 @@ impl ApplicationHandler for OptionApp {
             _window_id: WindowId,
             event: WindowEvent,
         ) {
-        eprintln!("new event: {event:?}");
+        if let Some(app) = self.0.as_ref() {
+            app.log_window_event(&event);
+        }
         use WindowEvent::*;
         match event {
             CloseRequested => {
@@ -109,14 +622,35 @@ impl ApplicationHandler for OptionApp {
                     app.minimized = true;
                 }
                 else {
-                    app.framework.recreate_swapchain();
+                    app.framework.invalidate_swapchain();
                     app.minimized = false;
                 }   
             }
+            KeyboardInput {
+                event: KeyEvent {
+                    physical_key: PhysicalKey::Code(key_code),
+                    state: ElementState::Pressed,
+                    repeat: false,
+                    ..
+                },
+                ..
+            } => {
+                let app = self.0.as_mut().unwrap();
+                app.handle_key(key_code);
+                app.framework.window.request_redraw();
+            }
             RedrawRequested => {
                 let app = self.0.as_mut().unwrap();
-                if !app.minimized && !app.draw_frame() {
-                    app.minimized = true;
+                if app.minimized {
+                    return;
+                }
+                match app.draw_frame() {
+                    Ok(true) => {}
+                    Ok(false) => app.minimized = true,
+                    Err(DeviceLost) => {
+                        let app = self.0.take().unwrap();
+                        self.0 = Some(app.recover_device());
+                    }
                 }
             }
             _ => {}
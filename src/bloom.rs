@@ -0,0 +1,262 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::DeviceOwned,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::WriteDescriptorSet,
+    image::{sampler::Sampler, view::ImageView},
+    pipeline::graphics::GraphicsPipeline,
+    render_pass::Subpass
+};
+
+use crate::{
+    allocator::Allocator,
+    render_target::RenderTarget,
+    post_process::{self, PassConstants, HDR_FORMAT}
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BloomConfig {
+    pub enabled: bool,
+    /// How much of the blurred chain is added onto the scene.
+    pub intensity: f32,
+    /// Brightness above which pixels start to bloom.
+    pub threshold: f32,
+    /// Width of the soft transition below `threshold`.
+    pub knee: f32,
+    /// Spread of the upsampling filter, in texels of the lower level.
+    pub radius: f32,
+    /// Number of half-size levels; fewer are used for small extents.
+    pub mip_count: u32
+}
+
+impl Default for BloomConfig {
+    fn default() -> Self {
+        BloomConfig {
+            enabled: true,
+            intensity: 0.05,
+            threshold: 1.0,
+            knee: 0.5,
+            radius: 1.0,
+            mip_count: 6
+        }
+    }
+}
+
+/// Extents of the bloom chain for a scene of `extent`, halving per level.
+pub fn mip_extents(extent: [u32; 2], mip_count: u32) -> Vec<[u32; 2]> {
+    let mut extents = Vec::new();
+    let mut level = extent;
+    for _ in 0..mip_count.max(1) {
+        level = level.map(|size| (size / 2).max(1));
+        extents.push(level);
+        if level == [1, 1] {
+            break;
+        }
+    }
+    extents
+}
+
+/// Dual-filter bloom over an HDR image: a thresholded downsample chain,
+/// upsampled back and added to the scene in a separate target.
+pub struct Bloom {
+    pub config: BloomConfig,
+    down: Vec<RenderTarget>,
+    up: Vec<RenderTarget>,
+    composite: RenderTarget,
+    downsample_pipeline: Arc<GraphicsPipeline>,
+    upsample_pipeline: Arc<GraphicsPipeline>,
+    composite_pipeline: Arc<GraphicsPipeline>
+}
+
+impl Bloom {
+    fn new_chain(allocator: &Allocator, extent: [u32; 2], mip_count: u32) -> (Vec<RenderTarget>, Vec<RenderTarget>) {
+        let extents = mip_extents(extent, mip_count);
+        let down = extents.iter()
+            .enumerate()
+            .map(|(i, extent)| RenderTarget::new(allocator, *extent, HDR_FORMAT, None, &format!("bloom down {i}")))
+            .collect();
+        let up = extents[..extents.len() - 1].iter()
+            .enumerate()
+            .map(|(i, extent)| RenderTarget::new(allocator, *extent, HDR_FORMAT, None, &format!("bloom up {i}")))
+            .collect();
+        (down, up)
+    }
+    pub fn new(allocator: &Allocator, extent: [u32; 2], config: BloomConfig) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let composite = RenderTarget::new(allocator, extent, HDR_FORMAT, None, "bloom composite");
+        let subpass = Subpass::from(composite.render_pass.clone(), 0).unwrap();
+        let new_pipeline = |shader_file| post_process::new_fullscreen_pipeline(device.clone(), subpass.clone(), shader_file, None);
+        let (down, up) = Self::new_chain(allocator, extent, config.mip_count);
+        Bloom {
+            config,
+            down,
+            up,
+            composite,
+            downsample_pipeline: new_pipeline("bloom_downsample_frag.spv"),
+            upsample_pipeline: new_pipeline("bloom_upsample_frag.spv"),
+            composite_pipeline: new_pipeline("bloom_composite_frag.spv")
+        }
+    }
+    /// Recreates the chain when `extent` changed.
+    pub fn resize(&mut self, allocator: &Allocator, extent: [u32; 2]) {
+        if extent == self.composite.extent() {
+            return;
+        }
+        self.composite.resize(allocator, extent);
+        (self.down, self.up) = Self::new_chain(allocator, extent, self.config.mip_count);
+    }
+    /// Replaces the parameters, rebuilding the chain if needed.
+    pub fn set_config(&mut self, allocator: &Allocator, config: BloomConfig) {
+        let rebuild = config.mip_count != self.config.mip_count;
+        self.config = config;
+        if rebuild {
+            (self.down, self.up) = Self::new_chain(allocator, self.composite.extent(), config.mip_count);
+        }
+    }
+    pub fn mip_count(&self) -> usize {
+        self.down.len()
+    }
+    fn texel_size(image: &ImageView) -> [f32; 2] {
+        let extent = image.image().extent();
+        [1.0 / extent[0] as f32, 1.0 / extent[1] as f32]
+    }
+    /// Records the chain over `scene` and returns the scene with bloom.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        sampler: &Arc<Sampler>,
+        scene: Arc<ImageView>
+    ) -> Arc<ImageView> {
+        let config = &self.config;
+        let input_writes = |input: Arc<ImageView>| vec![
+            WriteDescriptorSet::image_view(0, input),
+            WriteDescriptorSet::sampler(1, sampler.clone())
+        ];
+
+        let mut input = scene.clone();
+        for (i, target) in self.down.iter().enumerate() {
+            let prefilter = if i == 0 { 1.0 } else { 0.0 };
+            let push_constants = PassConstants {
+                params: [config.threshold, config.knee, prefilter, 0.0],
+                texel_size: Self::texel_size(&input)
+            };
+            post_process::record_fullscreen_pass(
+                builder,
+                allocator,
+                &self.downsample_pipeline,
+                target,
+                input_writes(input),
+                push_constants,
+                &target.name
+            );
+            input = target.color.clone();
+        }
+
+        for (target, current) in self.up.iter().zip(&self.down).rev() {
+            let push_constants = PassConstants {
+                params: [config.radius, 0.0, 0.0, 0.0],
+                texel_size: Self::texel_size(&input)
+            };
+            let mut writes = input_writes(input);
+            writes.push(WriteDescriptorSet::image_view(2, current.color.clone()));
+            post_process::record_fullscreen_pass(
+                builder,
+                allocator,
+                &self.upsample_pipeline,
+                target,
+                writes,
+                push_constants,
+                &target.name
+            );
+            input = target.color.clone();
+        }
+
+        let push_constants = PassConstants {
+            params: [config.intensity, 0.0, 0.0, 0.0],
+            texel_size: Self::texel_size(&scene)
+        };
+        let mut writes = input_writes(scene);
+        writes.push(WriteDescriptorSet::image_view(2, input));
+        post_process::record_fullscreen_pass(
+            builder,
+            allocator,
+            &self.composite_pipeline,
+            &self.composite,
+            writes,
+            push_constants,
+            &self.composite.name
+        );
+        self.composite.color.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+
+    use vulkano::{
+        command_buffer::{CommandBufferUsage, PrimaryCommandBufferAbstract},
+        sync::GpuFuture
+    };
+
+    use crate::{
+        capture,
+        model::ColoredVertex,
+        post_process::PostProcessor,
+        renderer::{Renderer, RenderPath},
+        test_support
+    };
+
+    use super::*;
+
+    #[test]
+    fn chain_halves_until_one_texel() {
+        assert_eq!(mip_extents([1280, 720], 3), vec![[640, 360], [320, 180], [160, 90]]);
+        assert_eq!(mip_extents([8, 3], 10), vec![[4, 1], [2, 1], [1, 1]]);
+        assert_eq!(mip_extents([8, 8], 0), vec![[4, 4]]);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn uniform_scene_gains_every_level() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let mut post_processor = PostProcessor::new(&allocator, [16, 16], RenderPath::Forward);
+        post_processor.scene.clear_color = [2.0, 2.0, 2.0, 1.0];
+        let config = BloomConfig {
+            intensity: 0.25,
+            threshold: 1.0,
+            knee: 0.0,
+            mip_count: 3,
+            ..Default::default()
+        };
+        let bloom = Bloom::new(&allocator, [16, 16], config);
+        let renderer = Renderer::for_target(device, &post_processor.scene);
+
+        let vertices = vec![ColoredVertex::new([0.0; 3], [0.0; 3]); 3];
+        let indices = vec![0, 1, 2];
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        renderer.record_target_pass(
+            &mut builder,
+            &post_processor.scene,
+            allocator.alloc_vertex_buffer(&vertices),
+            allocator.alloc_index_buffer(&indices),
+            3,
+            |_| {}
+        );
+        let output = bloom.record(&mut builder, &allocator, &post_processor.sampler, post_processor.scene.color.clone());
+        let readback = capture::record_readback(&mut builder, &allocator, output.image().clone());
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        // Every level holds 2.0 past the threshold, i.e. 1.0; the three
+        // levels sum to 3.0, scaled by the intensity.
+        let data = readback.buffer.read().unwrap();
+        let red = f16::from_le_bytes([data[0], data[1]]).to_f32();
+        assert!((red - 2.75).abs() < 0.01, "{red}");
+    }
+}
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    pipeline::{
+        Pipeline,
+        graphics::{GraphicsPipeline, input_assembly::PrimitiveTopology}
+    },
+    device::DeviceOwned,
+    render_pass::Subpass
+};
+
+use crate::{
+    debug,
+    allocator::Allocator,
+    model::{ColoredInstance, ColoredVertex, MeshInstance},
+    renderer
+};
+
+const BOUNDS_LABEL_COLOR: [f32; 4] = [0.3, 0.95, 0.3, 1.0];
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Aabb {
+    /// The smallest box around `points`, or an empty box at the origin.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let (min, max) = points.into_iter().fold(
+            (Vec3::INFINITY, Vec3::NEG_INFINITY),
+            |(min, max), point| (min.min(point), max.max(point))
+        );
+        if min.cmpgt(max).any() {
+            return Aabb::default();
+        }
+        Aabb { min, max }
+    }
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+    /// The box around this one once moved by `transform`.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let center = transform.transform_point3(self.center());
+        let half = self.half_extents();
+        let half_extents = transform.x_axis.truncate().abs() * half.x
+            + transform.y_axis.truncate().abs() * half.y
+            + transform.z_axis.truncate().abs() * half.z;
+        Aabb { min: center - half_extents, max: center + half_extents }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32
+}
+
+impl BoundingSphere {
+    /// The sphere around this one once moved by `transform`.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let scale = transform.x_axis.truncate().length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale
+        }
+    }
+}
+
+/// The bounding sphere and box of a mesh.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere
+}
+
+impl Bounds {
+    /// The sphere is centered on the box and reaches the farthest point.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Self {
+        let aabb = Aabb::from_points(points.clone());
+        let center = aabb.center();
+        let radius = points.into_iter()
+            .map(|point| point.distance(center))
+            .fold(0.0, f32::max);
+        Bounds { aabb, sphere: BoundingSphere { center, radius } }
+    }
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        Bounds {
+            aabb: self.aabb.transformed(transform),
+            sphere: self.sphere.transformed(transform)
+        }
+    }
+}
+
+/// The six planes of a view volume, with normals pointing inside.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vec4; 6]
+}
+
+impl Frustum {
+    /// Extracts the planes of a Vulkan `view_projection`.
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let row = |i| view_projection.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2)
+        ].map(|plane| plane / plane.truncate().length());
+        Frustum { planes }
+    }
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+    /// Conservative near the corners of the frustum.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let farthest = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(farthest) + plane.w >= 0.0
+        })
+    }
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+/// How many draws a culling pass kept and skipped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub visible: usize,
+    pub culled: usize
+}
+
+/// The meshes whose world bounds intersect `frustum`.
+pub fn cull(frustum: &Frustum, meshes: &[MeshInstance]) -> (Vec<MeshInstance>, CullStats) {
+    let visible: Vec<_> = meshes.iter()
+        .filter(|instance| frustum.intersects(&instance.world_bounds()))
+        .cloned()
+        .collect();
+    let stats = CullStats { visible: visible.len(), culled: meshes.len() - visible.len() };
+    (visible, stats)
+}
+
+/// Draws bounding boxes as wireframes.
+pub struct BoundsOverlay {
+    pipeline: Arc<GraphicsPipeline>,
+    cube_vertices: Subbuffer<[ColoredVertex]>,
+    cube_edges: Subbuffer<[u32]>
+}
+
+impl BoundsOverlay {
+    pub fn new(allocator: &Allocator, subpass: Subpass) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let pipeline = renderer::new_instanced_pipeline(device, subpass, PrimitiveTopology::LineList);
+        let corners = (0..8)
+            .map(|i| {
+                let corner = |bit| if i & bit == 0 { -1.0 } else { 1.0 };
+                ColoredVertex::new([corner(1), corner(2), corner(4)], [1.0; 3])
+            })
+            .collect();
+        let edges = (0..8u32)
+            .flat_map(|i| [1, 2, 4].into_iter().filter(move |bit| i & bit == 0).flat_map(move |bit| [i, i | bit]))
+            .collect();
+        BoundsOverlay {
+            pipeline,
+            cube_vertices: allocator.alloc_vertex_buffer(&corners),
+            cube_edges: allocator.alloc_index_buffer(&edges)
+        }
+    }
+    /// Records one box per element of `boxes`, tinted by its color.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        view_projection: Mat4,
+        boxes: impl IntoIterator<Item = (Aabb, [f32; 4])>
+    ) {
+        let instances: Vec<_> = boxes.into_iter()
+            .map(|(aabb, color)| {
+                let transform = Mat4::from_scale_rotation_translation(aabb.half_extents(), Quat::IDENTITY, aabb.center());
+                ColoredInstance::new(transform, color)
+            })
+            .collect();
+        if instances.is_empty() {
+            return;
+        }
+        let instance_count = instances.len() as u32;
+        let instance_buffer = allocator.alloc_vertex_buffer(&instances);
+
+        debug::with_label(builder, "bounds overlay", BOUNDS_LABEL_COLOR, |builder| {
+            builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .expect("Fail to bind bounds pipeline.")
+            .push_constants(self.pipeline.layout().clone(), 0, view_projection.to_cols_array_2d())
+            .expect("Fail to push view projection.")
+            .bind_vertex_buffers(0, (self.cube_vertices.clone(), instance_buffer))
+            .expect("Fail to bind vertex buffers")
+            .bind_index_buffer(self.cube_edges.clone())
+            .expect("Fail to bind index buffer")
+            .draw_indexed(self.cube_edges.len() as u32, instance_count, 0, 0, 0)
+            .expect("Fail to draw bounds.");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{camera::Camera, model::Mesh};
+
+    use super::*;
+
+    fn frustum() -> Frustum {
+        Frustum::from_view_projection(Camera::default().view_projection([800, 600]))
+    }
+
+    #[test]
+    fn mesh_bounds_enclose_the_vertices() {
+        let bounds = Mesh::sphere(0.5, 16, 8, [1.0; 3]).bounds();
+        assert!((bounds.aabb.max - Vec3::splat(0.5)).abs().max_element() < 1e-6);
+        assert!((bounds.aabb.min + Vec3::splat(0.5)).abs().max_element() < 1e-6);
+        assert!(bounds.sphere.center.length() < 1e-6);
+        assert!((bounds.sphere.radius - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rotated_box_grows_to_fit() {
+        let unit = Aabb { min: Vec3::splat(-1.0), max: Vec3::ONE };
+        let transform = Mat4::from_translation(Vec3::X * 2.0) * Mat4::from_rotation_y(std::f32::consts::FRAC_PI_4);
+        let moved = unit.transformed(&transform);
+        let half = std::f32::consts::SQRT_2;
+        assert!((moved.max - Vec3::new(2.0 + half, 1.0, half)).abs().max_element() < 1e-5);
+        assert!((moved.min - Vec3::new(2.0 - half, -1.0, -half)).abs().max_element() < 1e-5);
+    }
+
+    #[test]
+    fn frustum_keeps_the_target_and_culls_behind_and_beyond() {
+        let frustum = frustum();
+        let at = |center: Vec3| Bounds::from_points([center - Vec3::splat(0.1), center + Vec3::splat(0.1)]);
+        assert!(frustum.intersects(&at(Vec3::ZERO)));
+        assert!(!frustum.intersects(&at(Vec3::new(0.0, 0.0, 5.0))));
+        assert!(!frustum.intersects(&at(Vec3::new(0.0, 0.0, -200.0))));
+        assert!(!frustum.intersects(&at(Vec3::new(10.0, 0.0, 0.0))));
+        // Straddling the left plane.
+        assert!(frustum.intersects(&at(Vec3::new(-2.3, 0.0, 0.0))));
+    }
+}
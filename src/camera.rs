@@ -0,0 +1,80 @@
+use glam::{
+    Mat4, Vec3,
+    camera::rh::{proj::vulkan, view}
+};
+
+/// A perspective camera looking from `position` towards `target`, with
+/// Vulkan clip space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Vertical field of view in radians.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 3.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fov_y: 60f32.to_radians(),
+            near: 0.1,
+            far: 100.0
+        }
+    }
+}
+
+impl Camera {
+    pub fn view(&self) -> Mat4 {
+        view::look_at_mat4(self.position, self.target, self.up)
+    }
+    pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+        vulkan::perspective(self.fov_y, aspect_ratio, self.near, self.far)
+    }
+    pub fn view_projection(&self, extent: [u32; 2]) -> Mat4 {
+        let aspect_ratio = extent[0] as f32 / extent[1].max(1) as f32;
+        self.projection(aspect_ratio) * self.view()
+    }
+    /// World-space right and up axes of the view.
+    pub fn billboard_axes(&self) -> (Vec3, Vec3) {
+        let view = self.view();
+        let right = Vec3::new(view.x_axis.x, view.y_axis.x, view.z_axis.x);
+        let up = Vec3::new(view.x_axis.y, view.y_axis.y, view.z_axis.y);
+        (right, up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+
+    use super::*;
+
+    #[test]
+    fn target_projects_to_screen_center() {
+        let camera = Camera::default();
+        let clip = camera.view_projection([800, 600]) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let ndc = clip / clip.w;
+        assert!(ndc.x.abs() < 1e-6 && ndc.y.abs() < 1e-6);
+        assert!(ndc.z > 0.0 && ndc.z < 1.0);
+    }
+
+    #[test]
+    fn world_up_points_down_in_clip_space() {
+        let camera = Camera::default();
+        let clip = camera.view_projection([800, 600]) * Vec4::new(0.0, 1.0, 0.0, 1.0);
+        assert!(clip.y / clip.w < 0.0);
+    }
+
+    #[test]
+    fn billboard_axes_match_default_orientation() {
+        let (right, up) = Camera::default().billboard_axes();
+        assert!((right - Vec3::X).length() < 1e-6);
+        assert!((up - Vec3::Y).length() < 1e-6);
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, mpsc::{self, Sender}},
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use half::f16;
+
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyImageToBufferInfo, PrimaryAutoCommandBuffer},
+    format::Format,
+    image::Image,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}
+};
+
+use crate::{
+    debug,
+    allocator::Allocator
+};
+
+/// Whether [`to_rgba8`] can convert images of `format`.
+pub fn supports_format(format: Format) -> bool {
+    matches!(
+        format,
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB
+            | Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB
+            | Format::R16G16B16A16_SFLOAT
+    )
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 };
+    (encoded * 255.0).round() as u8
+}
+
+/// Converts tightly packed texels of `format` to RGBA8, sRGB-encoding HDR
+/// values.
+pub fn to_rgba8(format: Format, data: &[u8]) -> Option<Vec<u8>> {
+    let rgba = match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => data.to_vec(),
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => data.chunks_exact(4)
+            .flat_map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+            .collect(),
+        Format::R16G16B16A16_SFLOAT => data.chunks_exact(8)
+            .flat_map(|texel| {
+                let channel = |i: usize| f16::from_le_bytes([texel[2 * i], texel[2 * i + 1]]).to_f32();
+                let alpha = (channel(3).clamp(0.0, 1.0) * 255.0).round() as u8;
+                [linear_to_srgb(channel(0)), linear_to_srgb(channel(1)), linear_to_srgb(channel(2)), alpha]
+            })
+            .collect(),
+        _ => return None
+    };
+    Some(rgba)
+}
+
+/// A host-visible copy of an image.
+pub struct Readback {
+    pub buffer: Subbuffer<[u8]>,
+    pub format: Format,
+    pub extent: [u32; 2]
+}
+
+impl Readback {
+    /// The copied image as RGBA8, once the copy has finished.
+    pub fn to_rgba8(&self) -> Option<Vec<u8>> {
+        let data = match self.buffer.read() {
+            Ok(data) => data,
+            Err(error) => {
+                log::warn!("Fail to read captured image: {error}.");
+                return None;
+            }
+        };
+        to_rgba8(self.format, &data)
+    }
+}
+
+/// Records a copy of the first layer of `image`, which needs
+/// `TRANSFER_SRC` usage, into a new host-visible buffer.
+pub fn record_readback(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    allocator: &Allocator,
+    image: Arc<Image>
+) -> Readback {
+    let format = image.format();
+    let extent = [image.extent()[0], image.extent()[1]];
+    let texel_size = format.block_size();
+    let create_info = BufferCreateInfo {
+        usage: BufferUsage::TRANSFER_DST,
+        ..Default::default()
+    };
+    let allocation_info = AllocationCreateInfo {
+        memory_type_filter: MemoryTypeFilter::PREFER_HOST
+            | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+        ..Default::default()
+    };
+    let buffer = Buffer::new_slice::<u8>(
+        allocator.memory_allocator.clone(),
+        create_info,
+        allocation_info,
+        extent[0] as DeviceSize * extent[1] as DeviceSize * texel_size
+    ).expect("Fail to allocate readback buffer.");
+    debug::set_object_name(&**buffer.buffer(), "readback buffer");
+
+    builder
+    .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))
+    .expect("Fail to copy image to readback buffer.");
+    Readback {
+        buffer,
+        format,
+        extent
+    }
+}
+
+pub fn write_png(path: &Path, extent: [u32; 2], rgba: &[u8]) -> Result<(), png::EncodingError> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, extent[0], extent[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    encoder.write_header()?.write_image_data(rgba)
+}
+
+struct CaptureJob {
+    path: PathBuf,
+    extent: [u32; 2],
+    rgba: Vec<u8>
+}
+
+/// Writes PNG files on a background thread. Dropping it waits for them.
+pub struct CaptureWriter {
+    sender: Option<Sender<CaptureJob>>,
+    worker: Option<JoinHandle<()>>
+}
+
+impl CaptureWriter {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel::<CaptureJob>();
+        let worker = thread::Builder::new()
+            .name(String::from("capture writer"))
+            .spawn(move || {
+                for job in receiver {
+                    match write_png(&job.path, job.extent, &job.rgba) {
+                        Ok(()) => log::info!("Saved {}.", job.path.display()),
+                        Err(error) => log::warn!("Fail to save {}: {error}.", job.path.display())
+                    }
+                }
+            })
+            .expect("Fail to spawn capture writer thread.");
+        CaptureWriter {
+            sender: Some(sender),
+            worker: Some(worker)
+        }
+    }
+    pub fn save(&self, path: PathBuf, extent: [u32; 2], rgba: Vec<u8>) {
+        let job = CaptureJob { path, extent, rgba };
+        if let Some(sender) = &self.sender {
+            sender.send(job).expect("Capture writer thread stopped.");
+        }
+    }
+}
+
+impl Default for CaptureWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default()
+}
+
+pub fn screenshot_path(directory: &Path) -> PathBuf {
+    directory.join(format!("screenshot_{}.png", timestamp()))
+}
+
+/// Captures every frame to numbered files at a fixed `timestep`.
+#[derive(Clone, Debug)]
+pub struct FrameSequence {
+    pub directory: PathBuf,
+    pub timestep: f32,
+    pub frame: u32
+}
+
+impl FrameSequence {
+    pub fn new(directory: PathBuf, timestep: f32) -> Self {
+        FrameSequence {
+            directory,
+            timestep,
+            frame: 0
+        }
+    }
+    pub fn next_path(&mut self) -> PathBuf {
+        let path = self.directory.join(format!("frame_{:05}.png", self.frame));
+        self.frame += 1;
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bgra_is_swizzled() {
+        let rgba = to_rgba8(Format::B8G8R8A8_SRGB, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(rgba, vec![3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn hdr_is_clamped_and_srgb_encoded() {
+        let texel: Vec<u8> = [0.0, 0.2140, 4.0, 1.0]
+            .iter()
+            .flat_map(|value| f16::from_f32(*value).to_le_bytes())
+            .collect();
+        let rgba = to_rgba8(Format::R16G16B16A16_SFLOAT, &texel).unwrap();
+        assert_eq!(rgba[0], 0);
+        assert!((i32::from(rgba[1]) - 128).abs() <= 1);
+        assert_eq!(&rgba[2..], &[255, 255]);
+    }
+
+    #[test]
+    fn unsupported_formats_are_rejected() {
+        assert!(!supports_format(Format::D32_SFLOAT));
+        assert!(to_rgba8(Format::D32_SFLOAT, &[0; 4]).is_none());
+    }
+
+    #[test]
+    fn frame_sequence_numbers_files() {
+        let mut sequence = FrameSequence::new(PathBuf::from("captures"), 1.0 / 60.0);
+        assert_eq!(sequence.next_path(), Path::new("captures/frame_00000.png"));
+        assert_eq!(sequence.next_path(), Path::new("captures/frame_00001.png"));
+    }
+
+    #[test]
+    fn writer_saves_png() {
+        let directory = std::env::temp_dir().join(format!("learn-vulkano-capture-{}", std::process::id()));
+        let path = directory.join("pixel.png");
+        let writer = CaptureWriter::new();
+        writer.save(path.clone(), [2, 1], vec![255, 0, 0, 255, 0, 0, 255, 128]);
+        drop(writer);
+
+        let decoder = png::Decoder::new(std::io::BufReader::new(File::open(&path).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, vec![255, 0, 0, 255, 0, 0, 255, 128]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::Device,
+    buffer::BufferContents,
+    pipeline::{
+        Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
+        compute::{ComputePipeline, ComputePipelineCreateInfo},
+        layout::PipelineDescriptorSetLayoutCreateInfo
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer}
+};
+
+use crate::{
+    debug,
+    shader,
+    allocator::Allocator
+};
+
+const DISPATCH_LABEL_COLOR: [f32; 4] = [0.4, 0.9, 0.5, 1.0];
+
+/// A compute shader together with the pipeline built from it.
+///
+/// The pipeline layout is reflected from the shader, so descriptor sets and
+/// push constants only have to match what the shader declares.
+pub struct ComputePass {
+    pub name: String,
+    pub pipeline_layout: Arc<PipelineLayout>,
+    pub pipeline: Arc<ComputePipeline>
+}
+
+impl ComputePass {
+    fn new_pipeline_layout(device: Arc<Device>, stage: &PipelineShaderStageCreateInfo) -> Arc<PipelineLayout> {
+        let create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages([stage])
+            .into_pipeline_layout_create_info(device.clone())
+            .expect("Fail to reflect compute pipeline layout.");
+        PipelineLayout::new(device, create_info).expect("Fail to create compute pipeline layout.")
+    }
+    /// Loads `shader_file` from the shaders directory and builds a compute
+    /// pipeline for its `main` entry point.
+    pub fn new(device: Arc<Device>, shader_file: &str, name: &str) -> Self {
+        let compute_shader = shader::load_shader(device.clone(), shader_file);
+        let stage = PipelineShaderStageCreateInfo::new(
+            compute_shader.entry_point("main").expect("Fail to find entry point")
+        );
+        let pipeline_layout = Self::new_pipeline_layout(device.clone(), &stage);
+        debug::set_object_name(&*pipeline_layout, &format!("{name} pipeline layout"));
+
+        let create_info = ComputePipelineCreateInfo::stage_layout(stage, pipeline_layout.clone());
+        let pipeline = ComputePipeline::new(device, None, create_info)
+            .expect("Fail to create compute pipeline.");
+        debug::set_object_name(&*pipeline, &format!("{name} pipeline"));
+
+        ComputePass {
+            name: String::from(name),
+            pipeline_layout,
+            pipeline
+        }
+    }
+    pub fn descriptor_set(
+        &self,
+        allocator: &Allocator,
+        set: usize,
+        writes: impl IntoIterator<Item = WriteDescriptorSet>
+    ) -> Arc<PersistentDescriptorSet> {
+        let layout = self.pipeline.layout().set_layouts()[set].clone();
+        PersistentDescriptorSet::new(&allocator.descriptor_set_allocator, layout, writes, [])
+            .expect("Fail to create compute descriptor set.")
+    }
+    /// Records a dispatch of `group_counts` workgroups. vulkano inserts the
+    /// barriers between this dispatch and any later command in the same
+    /// command buffer that reads what it wrote, including vertex input.
+    pub fn record_dispatch<P: BufferContents>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        descriptor_set: Arc<PersistentDescriptorSet>,
+        push_constants: P,
+        group_counts: [u32; 3]
+    ) {
+        debug::with_label(builder, &self.name, DISPATCH_LABEL_COLOR, |builder| {
+            builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .expect("Fail to bind compute pipeline.")
+            .bind_descriptor_sets(PipelineBindPoint::Compute, self.pipeline_layout.clone(), 0, descriptor_set)
+            .expect("Fail to bind compute descriptor set.")
+            .push_constants(self.pipeline_layout.clone(), 0, push_constants)
+            .expect("Fail to push compute constants.")
+            .dispatch(group_counts)
+            .expect("Fail to dispatch compute work.");
+        });
+    }
+}
+
+/// Number of workgroups of `local_size` invocations covering `count` items.
+pub fn group_count(count: u32, local_size: u32) -> u32 {
+    count.div_ceil(local_size)
+}
@@ -0,0 +1,435 @@
+use std::{
+    env,
+    fs
+};
+
+use ahash::HashMap;
+
+use vulkano::instance::debug::{DebugUtilsMessageSeverity, DebugUtilsMessageType};
+
+use crate::{
+    debug::MessageCapture,
+    bloom::BloomConfig,
+    renderer::RenderPath,
+    shadow::{ShadowConfig, MAX_CASCADES},
+    ssao::{SsaoConfig, MAX_SAMPLES},
+    swapchain::SwapchainConfig
+};
+
+const CONFIG_PATH_VARIABLE: &str = "LEARN_VULKANO_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "learn-vulkano.conf";
+const VARIABLE_PREFIX: &str = "LEARN_VULKANO_";
+
+/// Which dedicated queues the framework may create. Disabled or missing
+/// queues fall back to the graphics queue.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueConfig {
+    pub dedicated_compute: bool,
+    pub dedicated_transfer: bool
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            dedicated_compute: true,
+            dedicated_transfer: true
+        }
+    }
+}
+
+/// Runtime settings, read from a `key = value` file and overridden by
+/// `LEARN_VULKANO_<KEY>` environment variables.
+///
+/// The file is `learn-vulkano.conf` in the working directory unless
+/// `LEARN_VULKANO_CONFIG` points somewhere else. Lines starting with `#`
+/// are comments; a missing file simply leaves every setting at its default.
+///
+/// Validation layers are enabled by default only in debug builds;
+/// `validation = true|false` (or `LEARN_VULKANO_VALIDATION`) overrides that.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub validation: bool,
+    pub validation_severity: DebugUtilsMessageSeverity,
+    pub validation_type: DebugUtilsMessageType,
+    pub log_window_events: bool,
+    pub log_motion_events: bool,
+    pub swapchain: SwapchainConfig,
+    pub queues: QueueConfig,
+    /// RON file describing a particle emitter to show, if any.
+    pub particle_emitter: Option<String>,
+    /// Render the scene to an HDR target and run the post-processing stack
+    /// over it, when the swapchain supports being blitted into.
+    pub post_processing: bool,
+    pub bloom: BloomConfig,
+    /// How the scene is shaded. The deferred path renders into the
+    /// post-processing scene target, so it needs `post_processing`.
+    pub render_path: RenderPath,
+    /// Scene file to draw, see [`SceneFile`](crate::scene_file::SceneFile).
+    /// The first command-line argument overrides it.
+    pub scene: Option<String>,
+    /// Load `assets/scenes/demo.ron` when no scene file is given.
+    pub demo_scene: bool,
+    pub shadows: ShadowConfig,
+    pub ssao: SsaoConfig,
+    /// Radiance `.hdr` file lighting the scene, if any.
+    pub environment_map: Option<String>,
+    /// Scales the light of `environment_map`.
+    pub environment_intensity: f32,
+    /// Where baked environments are cached, or `None` to bake every run.
+    pub ibl_cache_directory: Option<String>,
+    /// Draw `skybox_cube`, or else `environment_map`, behind the scene.
+    pub skybox: bool,
+    /// A `.ktx2` cube file or a directory of six face images to draw as the
+    /// skybox.
+    pub skybox_cube: Option<String>,
+    /// Where screenshots and frame sequences are written.
+    pub capture_directory: String,
+    /// Seconds simulated per frame while capturing a frame sequence.
+    pub capture_timestep: f32,
+    /// Collects validation messages instead of logging them. Only set from
+    /// code (tests), never from the file or environment.
+    pub message_capture: Option<MessageCapture>
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            validation: cfg!(debug_assertions),
+            validation_severity: DebugUtilsMessageSeverity::ERROR
+                | DebugUtilsMessageSeverity::WARNING,
+            validation_type: DebugUtilsMessageType::GENERAL
+                | DebugUtilsMessageType::VALIDATION
+                | DebugUtilsMessageType::PERFORMANCE,
+            log_window_events: false,
+            log_motion_events: false,
+            swapchain: SwapchainConfig::default(),
+            queues: QueueConfig::default(),
+            particle_emitter: None,
+            post_processing: true,
+            bloom: BloomConfig::default(),
+            render_path: RenderPath::Forward,
+            scene: None,
+            demo_scene: true,
+            shadows: ShadowConfig::default(),
+            ssao: SsaoConfig::default(),
+            environment_map: None,
+            environment_intensity: 1.0,
+            ibl_cache_directory: Some(String::from("ibl_cache")),
+            skybox: true,
+            skybox_cube: None,
+            capture_directory: String::from("captures"),
+            capture_timestep: 1.0 / 60.0,
+            message_capture: None
+        }
+    }
+}
+
+impl Config {
+    fn parse_file(text: &str) -> HashMap<String, String> {
+        let mut values = HashMap::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => {
+                    values.insert(key.trim().to_lowercase(), value.trim().to_string());
+                }
+                None => log::warn!("Ignoring malformed config line: {line:?}")
+            }
+        }
+        values
+    }
+    fn read_values() -> HashMap<String, String> {
+        let path = env::var(CONFIG_PATH_VARIABLE).unwrap_or(String::from(DEFAULT_CONFIG_PATH));
+        let mut values = match fs::read_to_string(&path) {
+            Ok(text) => Self::parse_file(&text),
+            Err(_) => HashMap::default()
+        };
+        for (variable, value) in env::vars() {
+            if variable == CONFIG_PATH_VARIABLE {
+                continue;
+            }
+            if let Some(key) = variable.strip_prefix(VARIABLE_PREFIX) {
+                values.insert(key.to_lowercase(), value);
+            }
+        }
+        values
+    }
+    fn parse_bool(key: &str, value: &str) -> Option<bool> {
+        match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(true),
+            "0" | "false" | "no" | "off" => Some(false),
+            _ => {
+                log::warn!("Invalid boolean {value:?} for config key {key}.");
+                None
+            }
+        }
+    }
+    fn parse_u32(key: &str, value: &str) -> Option<u32> {
+        let parsed = value.parse().ok();
+        if parsed.is_none() {
+            log::warn!("Invalid integer {value:?} for config key {key}.");
+        }
+        parsed
+    }
+    fn parse_f32(key: &str, value: &str) -> Option<f32> {
+        let parsed = value.parse().ok().filter(|value: &f32| value.is_finite());
+        if parsed.is_none() {
+            log::warn!("Invalid number {value:?} for config key {key}.");
+        }
+        parsed
+    }
+    fn parse_severity(key: &str, value: &str) -> Option<DebugUtilsMessageSeverity> {
+        let mut severity = DebugUtilsMessageSeverity::empty();
+        for flag in value.split(',').map(str::trim).filter(|flag| !flag.is_empty()) {
+            severity |= match flag.to_lowercase().as_str() {
+                "error" => DebugUtilsMessageSeverity::ERROR,
+                "warning" | "warn" => DebugUtilsMessageSeverity::WARNING,
+                "info" => DebugUtilsMessageSeverity::INFO,
+                "verbose" => DebugUtilsMessageSeverity::VERBOSE,
+                "all" => DebugUtilsMessageSeverity::ERROR
+                    | DebugUtilsMessageSeverity::WARNING
+                    | DebugUtilsMessageSeverity::INFO
+                    | DebugUtilsMessageSeverity::VERBOSE,
+                _ => {
+                    log::warn!("Invalid severity {flag:?} for config key {key}.");
+                    return None;
+                }
+            };
+        }
+        Some(severity)
+    }
+    fn parse_type(key: &str, value: &str) -> Option<DebugUtilsMessageType> {
+        let mut message_type = DebugUtilsMessageType::empty();
+        for flag in value.split(',').map(str::trim).filter(|flag| !flag.is_empty()) {
+            message_type |= match flag.to_lowercase().as_str() {
+                "general" => DebugUtilsMessageType::GENERAL,
+                "validation" => DebugUtilsMessageType::VALIDATION,
+                "performance" => DebugUtilsMessageType::PERFORMANCE,
+                "all" => DebugUtilsMessageType::GENERAL
+                    | DebugUtilsMessageType::VALIDATION
+                    | DebugUtilsMessageType::PERFORMANCE,
+                _ => {
+                    log::warn!("Invalid message type {flag:?} for config key {key}.");
+                    return None;
+                }
+            };
+        }
+        Some(message_type)
+    }
+    pub fn load() -> Self {
+        Self::from_values(&Self::read_values())
+    }
+    fn from_values(values: &HashMap<String, String>) -> Self {
+        let mut config = Config::default();
+        for (key, value) in values.iter() {
+            match key.as_str() {
+                "validation" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.validation = enabled;
+                },
+                "validation_severity" => if let Some(severity) = Self::parse_severity(key, value) {
+                    config.validation_severity = severity;
+                },
+                "validation_type" => if let Some(message_type) = Self::parse_type(key, value) {
+                    config.validation_type = message_type;
+                },
+                "log_window_events" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.log_window_events = enabled;
+                },
+                "log_motion_events" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.log_motion_events = enabled;
+                },
+                "present_modes" => {
+                    let present_modes: Option<Vec<_>> = value.split(',')
+                        .map(SwapchainConfig::parse_present_mode)
+                        .collect();
+                    match present_modes {
+                        Some(present_modes) => config.swapchain.present_modes = present_modes,
+                        None => log::warn!("Invalid present modes {value:?} for config key {key}.")
+                    }
+                },
+                "surface_formats" => {
+                    let surface_formats: Option<Vec<_>> = value.split(',')
+                        .map(SwapchainConfig::parse_surface_format)
+                        .collect();
+                    match surface_formats {
+                        Some(surface_formats) => config.swapchain.surface_formats = surface_formats,
+                        None => log::warn!("Invalid surface formats {value:?} for config key {key}.")
+                    }
+                },
+                "swapchain_image_count" => if let Some(image_count) = Self::parse_u32(key, value) {
+                    config.swapchain.image_count = image_count;
+                },
+                "vsync" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.swapchain.vsync = enabled;
+                },
+                "dedicated_compute_queue" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.queues.dedicated_compute = enabled;
+                },
+                "dedicated_transfer_queue" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.queues.dedicated_transfer = enabled;
+                },
+                "particle_emitter" => {
+                    config.particle_emitter = Some(value.clone()).filter(|path| !path.is_empty());
+                },
+                "post_processing" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.post_processing = enabled;
+                },
+                "bloom" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.bloom.enabled = enabled;
+                },
+                "bloom_intensity" => if let Some(intensity) = Self::parse_f32(key, value) {
+                    config.bloom.intensity = intensity.max(0.0);
+                },
+                "bloom_threshold" => if let Some(threshold) = Self::parse_f32(key, value) {
+                    config.bloom.threshold = threshold.max(0.0);
+                },
+                "bloom_knee" => if let Some(knee) = Self::parse_f32(key, value) {
+                    config.bloom.knee = knee.max(0.0);
+                },
+                "bloom_radius" => if let Some(radius) = Self::parse_f32(key, value) {
+                    config.bloom.radius = radius.max(0.0);
+                },
+                "bloom_mip_count" => if let Some(mip_count) = Self::parse_u32(key, value).filter(|count| *count > 0) {
+                    config.bloom.mip_count = mip_count;
+                },
+                "scene" => {
+                    config.scene = Some(value.clone()).filter(|path| !path.is_empty());
+                },
+                "demo_scene" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.demo_scene = enabled;
+                },
+                "shadows" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.shadows.enabled = enabled;
+                },
+                "shadow_atlas_size" => if let Some(size) = Self::parse_u32(key, value).filter(|size| *size > 0) {
+                    config.shadows.atlas_size = size;
+                },
+                "shadow_cascades" => if let Some(count) = Self::parse_u32(key, value) {
+                    config.shadows.cascade_count = count.clamp(1, MAX_CASCADES as u32);
+                },
+                "shadow_split_lambda" => if let Some(lambda) = Self::parse_f32(key, value) {
+                    config.shadows.cascade_split_lambda = lambda.clamp(0.0, 1.0);
+                },
+                "shadow_distance" => if let Some(distance) = Self::parse_f32(key, value).filter(|distance| *distance > 0.0) {
+                    config.shadows.max_distance = distance;
+                },
+                "shadow_depth_bias" => if let Some(bias) = Self::parse_f32(key, value) {
+                    config.shadows.depth_bias_constant = bias;
+                },
+                "shadow_slope_bias" => if let Some(bias) = Self::parse_f32(key, value) {
+                    config.shadows.depth_bias_slope = bias;
+                },
+                "shadow_normal_bias" => if let Some(bias) = Self::parse_f32(key, value) {
+                    config.shadows.normal_bias = bias;
+                },
+                "shadow_pcf_radius" => if let Some(radius) = Self::parse_u32(key, value) {
+                    config.shadows.pcf_radius = radius;
+                },
+                "ssao" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.ssao.enabled = enabled;
+                },
+                "ssao_radius" => if let Some(radius) = Self::parse_f32(key, value).filter(|radius| *radius > 0.0) {
+                    config.ssao.radius = radius;
+                },
+                "ssao_bias" => if let Some(bias) = Self::parse_f32(key, value) {
+                    config.ssao.bias = bias;
+                },
+                "ssao_samples" => if let Some(sample_count) = Self::parse_u32(key, value) {
+                    config.ssao.sample_count = sample_count.clamp(1, MAX_SAMPLES as u32);
+                },
+                "ssao_half_resolution" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.ssao.half_resolution = enabled;
+                },
+                "ssao_intensity" => if let Some(intensity) = Self::parse_f32(key, value) {
+                    config.ssao.intensity = intensity.clamp(0.0, 1.0);
+                },
+                "environment_map" => {
+                    config.environment_map = Some(value.clone()).filter(|path| !path.is_empty());
+                },
+                "environment_intensity" => if let Some(intensity) = Self::parse_f32(key, value) {
+                    config.environment_intensity = intensity.max(0.0);
+                },
+                "ibl_cache_directory" => {
+                    config.ibl_cache_directory = Some(value.clone()).filter(|path| !path.is_empty());
+                },
+                "render_path" => match RenderPath::parse(value) {
+                    Some(path) => config.render_path = path,
+                    None => log::warn!("Invalid render path {value:?} for config key {key}.")
+                },
+                "skybox" => if let Some(enabled) = Self::parse_bool(key, value) {
+                    config.skybox = enabled;
+                },
+                "skybox_cube" => {
+                    config.skybox_cube = Some(value.clone()).filter(|path| !path.is_empty());
+                },
+                "capture_directory" => config.capture_directory = value.clone(),
+                "capture_timestep" => if let Some(timestep) = Self::parse_f32(key, value).filter(|timestep| *timestep > 0.0) {
+                    config.capture_timestep = timestep;
+                },
+                _ => log::debug!("Ignoring unknown config key {key}.")
+            }
+        }
+        if values.contains_key("present_modes") && !values.contains_key("vsync") {
+            config.swapchain.vsync = false;
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{format::Format, swapchain::{ColorSpace, PresentMode}};
+
+    use super::*;
+
+    fn parse(text: &str) -> Config {
+        Config::from_values(&Config::parse_file(text))
+    }
+
+    #[test]
+    fn file_lines_are_trimmed_and_comments_skipped() {
+        let values = Config::parse_file("# comment\n\n  Bloom = off  \nmalformed\nscene = a=b.ron\n");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["bloom"], "off");
+        assert_eq!(values["scene"], "a=b.ron");
+    }
+
+    #[test]
+    fn values_override_the_defaults() {
+        let config = parse("validation_severity = error, verbose\nssao_samples = 1000\nrender_path = Deferred\nparticle_emitter =\n");
+        assert_eq!(config.validation_severity, DebugUtilsMessageSeverity::ERROR | DebugUtilsMessageSeverity::VERBOSE);
+        assert_eq!(config.ssao.sample_count, MAX_SAMPLES as u32);
+        assert_eq!(config.render_path, RenderPath::Deferred);
+        assert_eq!(config.particle_emitter, None);
+    }
+
+    #[test]
+    fn explicit_present_modes_turn_vsync_off() {
+        let config = parse("present_modes = immediate, fifo\nsurface_formats = r8g8b8a8_srgb\n");
+        assert!(!config.swapchain.vsync);
+        assert_eq!(config.swapchain.present_modes, [PresentMode::Immediate, PresentMode::Fifo]);
+        assert_eq!(config.swapchain.surface_formats, [(Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear)]);
+        assert!(parse("present_modes = immediate\nvsync = on\n").swapchain.vsync);
+        assert!(parse("").swapchain.vsync);
+    }
+
+    #[test]
+    fn dedicated_queues_can_be_turned_off() {
+        let config = parse("dedicated_compute_queue = off\ndedicated_transfer_queue = off\n");
+        assert!(!config.queues.dedicated_compute);
+        assert!(!config.queues.dedicated_transfer);
+        assert!(parse("").queues.dedicated_compute);
+    }
+
+    #[test]
+    fn invalid_values_keep_the_defaults() {
+        let config = parse("bloom = maybe\nvalidation_type = general, bogus\ncapture_timestep = -1\n");
+        let defaults = Config::default();
+        assert_eq!(config.bloom.enabled, defaults.bloom.enabled);
+        assert_eq!(config.validation_type, defaults.validation_type);
+        assert_eq!(config.capture_timestep, defaults.capture_timestep);
+    }
+}
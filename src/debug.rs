@@ -1,22 +1,331 @@
-use vulkano::instance::debug::{
-    DebugUtilsMessengerCreateInfo, DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessengerCallback,
-    DebugUtilsMessengerCallbackData
+use std::sync::{Arc, Mutex};
+
+use vulkano::{
+    VulkanObject,
+    device::DeviceOwned,
+    instance::debug::{
+        DebugUtilsMessengerCreateInfo, DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessengerCallback,
+        DebugUtilsMessengerCallbackData, DebugUtilsLabel
+    },
+    command_buffer::{
+        AutoCommandBufferBuilder,
+        allocator::CommandBufferAllocator
+    }
 };
 
-pub fn debug_printing_messenger() -> DebugUtilsMessengerCreateInfo {
-    let print = |_, _, callback_data: DebugUtilsMessengerCallbackData| {
-        eprintln!("validation layer: {:?}", callback_data.message);
-    };
-    let message_severity = DebugUtilsMessageSeverity::ERROR
-        | DebugUtilsMessageSeverity::WARNING
-        | DebugUtilsMessageSeverity::VERBOSE;
-    let message_type = DebugUtilsMessageType::GENERAL
-        | DebugUtilsMessageType::VALIDATION
-        | DebugUtilsMessageType::PERFORMANCE;
-    let user_callback = unsafe { DebugUtilsMessengerCallback::new(print) };
+use log::Level;
+
+use crate::config::Config;
+
+const VALIDATION_TARGET: &str = "validation";
+
+/// `env_logger` filter used unless `RUST_LOG` is set. Window events and
+/// validation messages are already filtered by the config, so every level
+/// of their targets passes.
+pub const DEFAULT_LOG_FILTER: &str = "info,window_event=trace,validation=trace";
+
+fn severity_level(severity: DebugUtilsMessageSeverity) -> Level {
+    if severity.intersects(DebugUtilsMessageSeverity::ERROR) { Level::Error }
+    else if severity.intersects(DebugUtilsMessageSeverity::WARNING) { Level::Warn }
+    else if severity.intersects(DebugUtilsMessageSeverity::INFO) { Level::Info }
+    else { Level::Trace }
+}
+
+fn type_name(message_type: DebugUtilsMessageType) -> &'static str {
+    if message_type.intersects(DebugUtilsMessageType::VALIDATION) { "validation" }
+    else if message_type.intersects(DebugUtilsMessageType::PERFORMANCE) { "performance" }
+    else { "general" }
+}
+
+fn related_objects(callback_data: &DebugUtilsMessengerCallbackData) -> String {
+    let objects: Vec<String> = callback_data.objects
+        .clone()
+        .map(|object| match object.object_name {
+            Some(name) => format!("{:?}({:#x}, {name:?})", object.object_type, object.object_handle),
+            None => format!("{:?}({:#x})", object.object_type, object.object_handle)
+        })
+        .collect();
+    objects.join(", ")
+}
+
+pub fn log_validation_message(
+    severity: DebugUtilsMessageSeverity,
+    message_type: DebugUtilsMessageType,
+    callback_data: DebugUtilsMessengerCallbackData
+) {
+    let level = severity_level(severity);
+    if !log::log_enabled!(target: VALIDATION_TARGET, level) {
+        return;
+    }
+    log::log!(
+        target: VALIDATION_TARGET,
+        level,
+        message_type = type_name(message_type),
+        message_id = callback_data.message_id_name.unwrap_or(""),
+        message_id_number = callback_data.message_id_number,
+        objects = related_objects(&callback_data);
+        "{}", callback_data.message
+    );
+}
+
+/// A validation message recorded by a [`MessageCapture`].
+#[derive(Clone, Debug)]
+pub struct CapturedMessage {
+    pub severity: DebugUtilsMessageSeverity,
+    pub message_type: DebugUtilsMessageType,
+    pub message_id: Option<String>,
+    pub message_id_number: i32,
+    pub message: String
+}
+
+impl CapturedMessage {
+    pub fn is_issue(&self) -> bool {
+        self.severity.intersects(DebugUtilsMessageSeverity::ERROR | DebugUtilsMessageSeverity::WARNING)
+    }
+}
+
+/// Thread-safe buffer that collects validation messages instead of logging
+/// them, so tests can assert on what the layers reported.
+///
+/// Clones share the same buffer; install one through
+/// [`Config::message_capture`] and keep a clone for the assertions.
+#[derive(Clone, Debug, Default)]
+pub struct MessageCapture(Arc<Mutex<Vec<CapturedMessage>>>);
+
+impl MessageCapture {
+    pub fn new() -> Self {
+        MessageCapture::default()
+    }
+    pub fn push(&self, message: CapturedMessage) {
+        self.0.lock().unwrap().push(message);
+    }
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+    pub fn messages(&self) -> Vec<CapturedMessage> {
+        self.0.lock().unwrap().clone()
+    }
+    pub fn take(&self) -> Vec<CapturedMessage> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+    pub fn issues(&self) -> Vec<CapturedMessage> {
+        self.messages().into_iter().filter(CapturedMessage::is_issue).collect()
+    }
+    pub fn contains_message_id(&self, message_id: &str) -> bool {
+        self.0.lock().unwrap()
+            .iter()
+            .any(|message| message.message_id.as_deref() == Some(message_id))
+    }
+    /// Runs `block` and panics if it produced any warning or error. Messages
+    /// captured before the call are discarded first.
+    pub fn assert_clean<R>(&self, block: impl FnOnce() -> R) -> R {
+        self.clear();
+        let result = block();
+        let issues = self.issues();
+        if !issues.is_empty() {
+            let report: Vec<String> = issues.iter()
+                .map(|issue| format!(
+                    "[{:?}] {}: {}",
+                    issue.severity,
+                    issue.message_id.as_deref().unwrap_or("<no id>"),
+                    issue.message
+                ))
+                .collect();
+            panic!("Validation reported {} issue(s):\n{}", issues.len(), report.join("\n"));
+        }
+        result
+    }
+    pub fn messenger(&self, config: &Config) -> DebugUtilsMessengerCreateInfo {
+        let capture = self.clone();
+        let record = move |severity, message_type, callback_data: DebugUtilsMessengerCallbackData| {
+            capture.push(CapturedMessage {
+                severity,
+                message_type,
+                message_id: callback_data.message_id_name.map(String::from),
+                message_id_number: callback_data.message_id_number,
+                message: String::from(callback_data.message)
+            });
+        };
+        let user_callback = unsafe { DebugUtilsMessengerCallback::new(record) };
+        DebugUtilsMessengerCreateInfo {
+            message_severity: config.validation_severity,
+            message_type: config.validation_type,
+            ..DebugUtilsMessengerCreateInfo::user_callback(user_callback)
+        }
+    }
+}
+
+/// Picks the capturing messenger when the config carries a
+/// [`MessageCapture`], and the logging one otherwise.
+pub fn debug_messenger(config: &Config) -> DebugUtilsMessengerCreateInfo {
+    match &config.message_capture {
+        Some(capture) => capture.messenger(config),
+        None => debug_logging_messenger(config)
+    }
+}
+
+pub fn debug_logging_messenger(config: &Config) -> DebugUtilsMessengerCreateInfo {
+    let user_callback = unsafe { DebugUtilsMessengerCallback::new(log_validation_message) };
     DebugUtilsMessengerCreateInfo {
-        message_severity,
-        message_type,
+        message_severity: config.validation_severity,
+        message_type: config.validation_type,
         ..DebugUtilsMessengerCreateInfo::user_callback(user_callback)
     }
-}
\ No newline at end of file
+}
+
+fn debug_utils_enabled<T: DeviceOwned>(object: &T) -> bool {
+    object.device().instance().enabled_extensions().ext_debug_utils
+}
+
+/// Attaches `name` to `object` so it shows up in validation messages and
+/// frame captures. Does nothing when `ext_debug_utils` is not enabled.
+pub fn set_object_name<T: VulkanObject + DeviceOwned>(object: &T, name: &str) {
+    if !debug_utils_enabled(object) {
+        return;
+    }
+    if let Err(error) = object.device().set_debug_utils_object_name(object, Some(name)) {
+        log::warn!("Fail to name {name}: {error}.");
+    }
+}
+
+/// Records the commands issued by `record` inside a debug label region
+/// named `label_name`. The region is skipped entirely when `ext_debug_utils`
+/// is not enabled.
+pub fn with_label<L, A, R>(
+    builder: &mut AutoCommandBufferBuilder<L, A>,
+    label_name: &str,
+    color: [f32; 4],
+    record: impl FnOnce(&mut AutoCommandBufferBuilder<L, A>) -> R
+) -> R
+where
+    A: CommandBufferAllocator
+{
+    if !debug_utils_enabled(builder) {
+        return record(builder);
+    }
+    let label_info = DebugUtilsLabel {
+        label_name: String::from(label_name),
+        color,
+        ..Default::default()
+    };
+    builder.begin_debug_utils_label(label_info)
+        .expect("Fail to begin debug label.");
+    let result = record(builder);
+    // The region was opened in this same command buffer just above.
+    unsafe {
+        builder.end_debug_utils_label()
+            .expect("Fail to end debug label.");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use vulkano::{
+        VulkanLibrary,
+        instance::{Instance, InstanceCreateInfo, InstanceExtensions},
+        device::{Device, DeviceCreateInfo, QueueCreateInfo},
+        buffer::{BufferUsage, sys::{BufferCreateInfo, RawBuffer}}
+    };
+
+    use super::*;
+
+    fn message(severity: DebugUtilsMessageSeverity, message_id: &str) -> CapturedMessage {
+        CapturedMessage {
+            severity,
+            message_type: DebugUtilsMessageType::VALIDATION,
+            message_id: Some(String::from(message_id)),
+            message_id_number: 0,
+            message: String::from("test message")
+        }
+    }
+
+    /// Creates an instance and device with validation routed into `capture`,
+    /// or `None` when the machine has no Vulkan driver or validation layer.
+    fn validated_device(capture: &MessageCapture) -> Option<Arc<Device>> {
+        let library = VulkanLibrary::new().ok()?;
+        let layer = String::from("VK_LAYER_KHRONOS_validation");
+        let has_layer = library.layer_properties().ok()?.any(|property| property.name() == layer);
+        if !has_layer || !library.supported_extensions().ext_debug_utils {
+            return None;
+        }
+        let config = Config {
+            validation_severity: DebugUtilsMessageSeverity::ERROR | DebugUtilsMessageSeverity::WARNING,
+            ..Config::default()
+        };
+        let create_info = InstanceCreateInfo {
+            enabled_layers: vec![layer],
+            enabled_extensions: InstanceExtensions { ext_debug_utils: true, ..Default::default() },
+            debug_utils_messengers: vec![capture.messenger(&config)],
+            ..Default::default()
+        };
+        let instance = Instance::new(library, create_info).ok()?;
+        let physical_device = instance.enumerate_physical_devices().ok()?.next()?;
+        let create_info = DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo::default()],
+            ..Default::default()
+        };
+        Device::new(physical_device, create_info).ok().map(|(device, _)| device)
+    }
+
+    #[test]
+    fn assert_clean_ignores_informational_messages() {
+        let capture = MessageCapture::new();
+        capture.assert_clean(|| {
+            capture.push(message(DebugUtilsMessageSeverity::INFO, "info"));
+            capture.push(message(DebugUtilsMessageSeverity::VERBOSE, "verbose"));
+        });
+        assert_eq!(capture.messages().len(), 2);
+        assert!(capture.issues().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "VUID-test-warning")]
+    fn assert_clean_panics_on_warning() {
+        let capture = MessageCapture::new();
+        capture.assert_clean(|| {
+            capture.push(message(DebugUtilsMessageSeverity::WARNING, "VUID-test-warning"));
+        });
+    }
+
+    #[test]
+    fn assert_clean_discards_earlier_messages() {
+        let capture = MessageCapture::new();
+        capture.push(message(DebugUtilsMessageSeverity::ERROR, "VUID-earlier"));
+        capture.assert_clean(|| {});
+        assert!(!capture.contains_message_id("VUID-earlier"));
+    }
+
+    #[test]
+    fn clones_share_the_buffer() {
+        let capture = MessageCapture::new();
+        capture.clone().push(message(DebugUtilsMessageSeverity::ERROR, "VUID-shared"));
+        assert!(capture.contains_message_id("VUID-shared"));
+        assert_eq!(capture.take().len(), 1);
+        assert!(capture.messages().is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device and the validation layer"]
+    fn validation_detects_zero_sized_buffer() {
+        let capture = MessageCapture::new();
+        let device = validated_device(&capture).expect("No Vulkan device with the validation layer available.");
+        capture.assert_clean(|| {
+            let create_info = BufferCreateInfo {
+                size: 16,
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            };
+            RawBuffer::new(device.clone(), create_info).expect("Fail to create buffer.");
+        });
+        let create_info = BufferCreateInfo {
+            size: 0,
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        };
+        let _ = unsafe { RawBuffer::new_unchecked(device, create_info) };
+        assert!(capture.contains_message_id("VUID-VkBufferCreateInfo-size-00912"));
+    }
+}
@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use ahash::HashSet;
+
+use vulkano::{
+    device::Device,
+    format::Format,
+    buffer::BufferContents,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{DescriptorImageViewInfo, PersistentDescriptorSet, WriteDescriptorSet},
+    image::{ImageLayout, view::ImageView},
+    render_pass::{
+        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+        RenderPass, RenderPassCreateInfo, Subpass, SubpassDependency, SubpassDescription
+    },
+    sync::{AccessFlags, DependencyFlags, PipelineStages},
+    pipeline::{
+        Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo, DynamicState,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        graphics::{
+            GraphicsPipeline, GraphicsPipelineCreateInfo,
+            vertex_input::VertexInputState,
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            viewport::ViewportState,
+            rasterization::{RasterizationState, CullMode},
+            multisample::MultisampleState,
+            color_blend::{ColorBlendState, ColorBlendAttachmentState, AttachmentBlend},
+            subpass::PipelineSubpassType
+        }
+    }
+};
+
+use smallvec::SmallVec;
+
+use crate::{
+    debug,
+    shader,
+    allocator::Allocator,
+    camera::Camera,
+    lighting::{FrameUniforms, Lights, LitRenderer},
+    model::MeshInstance,
+    render_target::RenderTarget,
+    renderer,
+    shadow::{ShadowMaps, ShadowPlan}
+};
+
+const LIGHTING_LABEL_COLOR: [f32; 4] = [0.95, 0.7, 0.3, 1.0];
+
+/// Subpass of the geometry pass filling the G-buffer.
+pub const GEOMETRY_SUBPASS: u32 = 0;
+/// Subpass of the shading pass adding the light of every light volume.
+pub const LIGHTING_SUBPASS: u32 = 0;
+/// Subpass of the shading pass for forward draws.
+pub const FORWARD_SUBPASS: u32 = 1;
+
+/// Formats of the G-buffer attachments: albedo and occlusion, normal and
+/// depth, then metallic and roughness.
+pub const GBUFFER_FORMATS: [Format; 3] = [
+    Format::R8G8B8A8_SRGB,
+    Format::R16G16B16A16_SFLOAT,
+    Format::R8G8B8A8_UNORM
+];
+/// Index of the normal and depth in the G-buffer.
+pub const NORMAL_DEPTH_GBUFFER: usize = 1;
+
+/// Attachment index of the depth image; the G-buffer follows it.
+const DEPTH_ATTACHMENT: u32 = 1;
+/// Binding of the first input attachment of `deferred_light.frag`.
+const FIRST_INPUT_BINDING: u32 = 10;
+
+/// The geometry and shading render passes of the deferred path, split so
+/// that the G-buffer can be sampled in between. Their attachments are the
+/// color, the depth, then the G-buffer.
+pub fn new_render_passes(device: Arc<Device>, color_format: Format, depth_format: Format) -> (Arc<RenderPass>, Arc<RenderPass>) {
+    // Initial and final layouts of the color, depth and G-buffer images.
+    let attachments = |load_op, store_op, [color, depth, gbuffer]: [[ImageLayout; 2]; 3]| {
+        let attachment = |format, [initial_layout, final_layout]: [ImageLayout; 2], store_op| AttachmentDescription {
+            format,
+            load_op,
+            store_op,
+            initial_layout,
+            final_layout,
+            ..Default::default()
+        };
+        let mut attachments = vec![
+            attachment(color_format, color, AttachmentStoreOp::Store),
+            attachment(depth_format, depth, store_op)
+        ];
+        attachments.extend(GBUFFER_FORMATS.map(|format| attachment(format, gbuffer, store_op)));
+        attachments
+    };
+    let gbuffer_attachments = || (0..GBUFFER_FORMATS.len() as u32).map(|i| DEPTH_ATTACHMENT + 1 + i);
+    let color_reference = |attachment| Some(AttachmentReference {
+        attachment,
+        layout: ImageLayout::ColorAttachmentOptimal,
+        ..Default::default()
+    });
+    let depth_reference = || Some(AttachmentReference {
+        attachment: DEPTH_ATTACHMENT,
+        layout: ImageLayout::DepthStencilAttachmentOptimal,
+        ..Default::default()
+    });
+
+    let geometry = SubpassDescription {
+        color_attachments: [0].into_iter().chain(gbuffer_attachments()).map(color_reference).collect(),
+        depth_stencil_attachment: depth_reference(),
+        ..Default::default()
+    };
+    let create_info = RenderPassCreateInfo {
+        attachments: attachments(
+            AttachmentLoadOp::Clear,
+            AttachmentStoreOp::Store,
+            [
+                [ImageLayout::Undefined, ImageLayout::ColorAttachmentOptimal],
+                [ImageLayout::Undefined, ImageLayout::DepthStencilAttachmentOptimal],
+                [ImageLayout::Undefined, ImageLayout::ShaderReadOnlyOptimal]
+            ]
+        ),
+        subpasses: vec![geometry],
+        ..Default::default()
+    };
+    let geometry_pass = RenderPass::new(device.clone(), create_info).expect("Fail to create deferred geometry pass.");
+
+    let mut input_attachments: Vec<_> = gbuffer_attachments()
+        .map(|attachment| Some(AttachmentReference {
+            attachment,
+            layout: ImageLayout::ShaderReadOnlyOptimal,
+            ..Default::default()
+        }))
+        .collect();
+    input_attachments.push(Some(AttachmentReference {
+        attachment: DEPTH_ATTACHMENT,
+        layout: ImageLayout::DepthStencilReadOnlyOptimal,
+        ..Default::default()
+    }));
+    let lighting = SubpassDescription {
+        input_attachments,
+        color_attachments: vec![color_reference(0)],
+        ..Default::default()
+    };
+    let forward = SubpassDescription {
+        color_attachments: vec![color_reference(0)],
+        depth_stencil_attachment: depth_reference(),
+        ..Default::default()
+    };
+    let dependency = SubpassDependency {
+        src_subpass: Some(LIGHTING_SUBPASS),
+        dst_subpass: Some(FORWARD_SUBPASS),
+        src_stages: PipelineStages::COLOR_ATTACHMENT_OUTPUT | PipelineStages::FRAGMENT_SHADER,
+        dst_stages: PipelineStages::COLOR_ATTACHMENT_OUTPUT | PipelineStages::EARLY_FRAGMENT_TESTS
+            | PipelineStages::LATE_FRAGMENT_TESTS,
+        src_access: AccessFlags::COLOR_ATTACHMENT_WRITE | AccessFlags::INPUT_ATTACHMENT_READ,
+        dst_access: AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE
+            | AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dependency_flags: DependencyFlags::BY_REGION,
+        ..Default::default()
+    };
+    let create_info = RenderPassCreateInfo {
+        attachments: attachments(
+            AttachmentLoadOp::Load,
+            AttachmentStoreOp::DontCare,
+            [
+                [ImageLayout::ColorAttachmentOptimal, ImageLayout::ShaderReadOnlyOptimal],
+                [ImageLayout::DepthStencilAttachmentOptimal; 2],
+                [ImageLayout::ShaderReadOnlyOptimal; 2]
+            ]
+        ),
+        subpasses: vec![lighting, forward],
+        dependencies: vec![dependency],
+        ..Default::default()
+    };
+    let shading_pass = RenderPass::new(device, create_info).expect("Fail to create deferred shading pass.");
+    (geometry_pass, shading_pass)
+}
+
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+struct LightingPushConstants {
+    inverse_view_projection: [[f32; 4]; 4],
+    /// Framebuffer size in `xy`.
+    viewport: [f32; 4]
+}
+
+/// Shades meshes in two passes: `gbuffer.frag` stores their materials,
+/// then `deferred_light.frag` adds the light of each light volume.
+pub struct DeferredRenderer {
+    pub geometry_pipeline: Arc<GraphicsPipeline>,
+    pub lighting_pipeline: Arc<GraphicsPipeline>
+}
+
+impl DeferredRenderer {
+    /// The subpasses usually come from [`renderer::Renderer::deferred_subpasses`].
+    pub fn new(device: Arc<Device>, geometry_subpass: Subpass, lighting_subpass: Subpass) -> Self {
+        let geometry_pipeline = renderer::new_mesh_pipeline(
+            device.clone(),
+            geometry_subpass,
+            "lit_vert.spv",
+            Some("gbuffer_frag.spv"),
+            CullMode::Back,
+            false
+        );
+        DeferredRenderer {
+            geometry_pipeline,
+            lighting_pipeline: Self::new_lighting_pipeline(device, lighting_subpass)
+        }
+    }
+    fn new_lighting_pipeline(device: Arc<Device>, subpass: Subpass) -> Arc<GraphicsPipeline> {
+        let vertex_module = shader::load_shader(device.clone(), "deferred_light_vert.spv");
+        let fragment_module = shader::load_shader(device.clone(), "deferred_light_frag.spv");
+        let stages: SmallVec<[PipelineShaderStageCreateInfo; 5]> = SmallVec::from_vec(vec![
+            PipelineShaderStageCreateInfo::new(vertex_module.entry_point("main").expect("Fail to find entry point")),
+            PipelineShaderStageCreateInfo::new(fragment_module.entry_point("main").expect("Fail to find entry point"))
+        ]);
+        let pipeline_layout = {
+            let create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .expect("Fail to reflect deferred lighting pipeline layout.");
+            PipelineLayout::new(device.clone(), create_info).expect("Fail to create deferred lighting pipeline layout.")
+        };
+        debug::set_object_name(&*pipeline_layout, "deferred lighting pipeline layout");
+
+        let color_blend_state = ColorBlendState::with_attachment_states(
+            subpass.num_color_attachments(),
+            ColorBlendAttachmentState {
+                blend: Some(AttachmentBlend::additive()),
+                ..Default::default()
+            }
+        );
+        let mut dynamic_state = HashSet::default();
+        dynamic_state.insert(DynamicState::Viewport);
+
+        let create_info = GraphicsPipelineCreateInfo {
+            stages,
+            vertex_input_state: Some(VertexInputState::new()),
+            input_assembly_state: Some(InputAssemblyState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            }),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState {
+                cull_mode: CullMode::None,
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState::default()),
+            color_blend_state: Some(color_blend_state),
+            dynamic_state,
+            subpass: Some(PipelineSubpassType::BeginRenderPass(subpass)),
+            ..GraphicsPipelineCreateInfo::layout(pipeline_layout)
+        };
+        let pipeline = GraphicsPipeline::new(device, None, create_info)
+            .expect("Fail to create deferred lighting pipeline.");
+        debug::set_object_name(&*pipeline, "deferred lighting pipeline");
+        pipeline
+    }
+    /// Records the meshes into the G-buffer, in the geometry subpass.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_geometry(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        lit: &LitRenderer,
+        camera: &Camera,
+        extent: [u32; 2],
+        lights: &Lights,
+        meshes: &[MeshInstance]
+    ) {
+        let uniforms = FrameUniforms::new(camera, extent, lights, 0, lit.environment.as_ref());
+        let frame_set = PersistentDescriptorSet::new(
+            &allocator.descriptor_set_allocator,
+            self.geometry_pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(0, allocator.alloc_uniform_buffer(uniforms))],
+            []
+        ).expect("Fail to create G-buffer descriptor set.");
+        lit.record_meshes(builder, allocator, &self.geometry_pipeline, frame_set, meshes, "G-buffer");
+    }
+    /// Shades the G-buffer of `target` into its color image, in the
+    /// lighting subpass.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_lighting(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        lit: &LitRenderer,
+        target: &RenderTarget,
+        camera: &Camera,
+        lights: &Lights,
+        shadows: &ShadowMaps,
+        plan: &ShadowPlan
+    ) {
+        let extent = target.framebuffer.extent();
+        let (mut writes, light_count) = lit.frame_writes(allocator, camera, extent, lights, shadows, plan);
+        let input = |binding, image_view: &Arc<ImageView>, image_layout| WriteDescriptorSet::image_view_with_layout(
+            binding,
+            DescriptorImageViewInfo { image_view: image_view.clone(), image_layout }
+        );
+        writes.extend(target.gbuffer.iter().enumerate().map(|(i, view)| {
+            input(FIRST_INPUT_BINDING + i as u32, view, ImageLayout::ShaderReadOnlyOptimal)
+        }));
+        let depth = target.depth.as_ref().expect("Deferred targets have a depth image.");
+        writes.push(input(FIRST_INPUT_BINDING + target.gbuffer.len() as u32, depth, ImageLayout::DepthStencilReadOnlyOptimal));
+
+        let layout = self.lighting_pipeline.layout().clone();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &allocator.descriptor_set_allocator,
+            layout.set_layouts()[0].clone(),
+            writes,
+            []
+        ).expect("Fail to create deferred lighting descriptor set.");
+        let push_constants = LightingPushConstants {
+            inverse_view_projection: camera.view_projection(extent).inverse().to_cols_array_2d(),
+            viewport: [extent[0] as f32, extent[1] as f32, 0.0, 0.0]
+        };
+
+        debug::with_label(builder, "deferred lighting", LIGHTING_LABEL_COLOR, |builder| {
+            builder
+            .bind_pipeline_graphics(self.lighting_pipeline.clone())
+            .expect("Fail to bind deferred lighting pipeline.")
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, descriptor_set)
+            .expect("Fail to bind deferred lighting descriptor set.")
+            .push_constants(layout, 0, push_constants)
+            .expect("Fail to push deferred lighting constants.")
+            // One volume per light, then the ambient term.
+            .draw(4, light_count as u32 + 1, 0, 0)
+            .expect("Fail to draw light volumes.");
+        });
+    }
+}
@@ -1,7 +1,6 @@
 use std::{
     sync::Arc,
-    collections::HashSet,
-    cmp::max
+    collections::HashSet
 };
 
 use winit::{
@@ -10,6 +9,7 @@ use winit::{
 };
 
 use vulkano::{
+    Validated, VulkanError,
     library::VulkanLibrary,
     instance::{
         Instance, InstanceExtensions, InstanceCreateInfo,
@@ -25,18 +25,40 @@ use vulkano::{
         SurfaceInfo, Swapchain, SwapchainCreateInfo, SwapchainAcquireFuture,
         acquire_next_image, SwapchainPresentInfo, PresentFuture
     },
-    format::Format,
+    format::{Format, FormatFeatures},
     image::{
-        Image, ImageUsage,ImageSubresourceRange,
+        Image, ImageUsage,
         view::{ImageView, ImageViewCreateInfo}
     },
-    sync::GpuFuture,
+    sync::{
+        self, GpuFuture, Sharing,
+        future::{NowFuture, SemaphoreSignalFuture}
+    },
     command_buffer::{
         PrimaryCommandBufferAbstract, CommandBufferExecFuture
     }
 };
 
-use crate::debug;
+use smallvec::SmallVec;
+
+use crate::{
+    debug,
+    texture,
+    config::{Config, QueueConfig},
+    swapchain::{
+        SwapchainConfig, SwapchainLifecycle, SwapchainBackend,
+        resolve_extent
+    }
+};
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+/// Enabled when the device supports them, for
+/// [`Renderer::record_indirect`](crate::renderer::Renderer::record_indirect).
+const OPTIONAL_FEATURES: Features = Features {
+    multi_draw_indirect: true,
+    draw_indirect_first_instance: true,
+    ..Features::empty()
+};
 
 pub struct Framework {
     pub window: Arc<Window>,
@@ -46,9 +68,34 @@ pub struct Framework {
     pub device: Arc<Device>,
     pub graphics_queue: Arc<Queue>,
     pub present_queue: Arc<Queue>,
+    /// A compute-only queue when one exists and is enabled, otherwise the
+    /// graphics queue.
+    pub compute_queue: Arc<Queue>,
+    /// A transfer-only queue when one exists and is enabled, otherwise the
+    /// graphics queue.
+    pub transfer_queue: Arc<Queue>,
+    pub queue_config: QueueConfig,
+    pub swapchain_config: SwapchainConfig,
+    pub swapchain_lifecycle: SwapchainLifecycle,
     pub swapchain: Arc<Swapchain>,
     pub swapchain_images: Vec<Arc<Image>>,
-    pub swapchain_image_views: Vec<Arc<ImageView>>
+    pub swapchain_image_views: Vec<Arc<ImageView>>,
+    pub device_lost_simulated: bool
+}
+
+/// The device was lost and the GPU context has to be rebuilt with
+/// [`Framework::recover_device`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceLost;
+
+/// Turns a device loss into [`DeviceLost`] and panics on any other error,
+/// which would be a bug rather than something to recover from.
+pub fn check_device_lost<T>(result: Result<T, Validated<VulkanError>>, context: &str) -> Result<T, DeviceLost> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(Validated::Error(VulkanError::DeviceLost)) => Err(DeviceLost),
+        Err(error) => panic!("{context}: {error:?}")
+    }
 }
 
 impl Framework {
@@ -62,7 +109,7 @@ impl Framework {
     }
     fn library_support(
         library: &Arc<VulkanLibrary>, 
-        enabled_layers: &Vec<String>,
+        enabled_layers: &[String],
         enabled_extensions: &InstanceExtensions,
     ) -> bool {
         let layer_properties = library.layer_properties()
@@ -85,6 +132,28 @@ impl Framework {
         }
         true
     }
+    fn select_validation(library: &Arc<VulkanLibrary>, requested: bool) -> (Vec<String>, InstanceExtensions) {
+        if !requested {
+            return (Vec::new(), InstanceExtensions::empty());
+        }
+        let validation_layers = vec![String::from(VALIDATION_LAYER)];
+        let enabled_layers = if Self::library_support(library, &validation_layers, &InstanceExtensions::empty()) {
+            validation_layers
+        }
+        else {
+            log::warn!("{VALIDATION_LAYER} is not available, running without validation.");
+            Vec::new()
+        };
+        let debug_extensions = InstanceExtensions { ext_debug_utils: true, ..Default::default() };
+        let enabled_extensions = if Self::library_support(library, &[], &debug_extensions) {
+            debug_extensions
+        }
+        else {
+            log::warn!("VK_EXT_debug_utils is not available, running without debug messengers.");
+            InstanceExtensions::empty()
+        };
+        (enabled_layers, enabled_extensions)
+    }
     fn new_instance(
         library: Arc<VulkanLibrary>,
         enabled_layers: Vec<String>,
@@ -114,49 +183,60 @@ impl Framework {
     }
     fn select_graphics_queue_family(physical_device: &Arc<PhysicalDevice>) -> Option<u32> {
         let queue_family_properties = physical_device.queue_family_properties();
-        for i in 0..queue_family_properties.len() {
-            let property = &queue_family_properties[i];
+        for (i, property) in queue_family_properties.iter().enumerate() {
             if property.queue_flags.contains(QueueFlags::GRAPHICS | QueueFlags::TRANSFER) {
                 return Some(i as u32);
             }
         }
         None
     }
+    /// A compute-capable family without graphics support, which lets
+    /// compute work run asynchronously next to rendering.
+    fn select_dedicated_compute_queue_family(queue_flags: &[QueueFlags]) -> Option<u32> {
+        queue_flags.iter()
+            .position(|flags| flags.contains(QueueFlags::COMPUTE) && !flags.intersects(QueueFlags::GRAPHICS))
+            .map(|i| i as u32)
+    }
+    /// A transfer-only family, usually backed by a DMA engine.
+    fn select_dedicated_transfer_queue_family(queue_flags: &[QueueFlags]) -> Option<u32> {
+        queue_flags.iter()
+            .position(|flags| {
+                flags.contains(QueueFlags::TRANSFER)
+                    && !flags.intersects(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+            })
+            .map(|i| i as u32)
+    }
     fn select_present_queue_family(physical_device: &Arc<PhysicalDevice>, surface: &Arc<Surface>) -> Option<u32> {
         let queue_family_properties = physical_device.queue_family_properties();
         for i in 0..queue_family_properties.len() {
-            if physical_device.surface_support(i as u32, &*surface).unwrap() {
+            if physical_device.surface_support(i as u32, surface).unwrap() {
                 return Some(i as u32);
             }
         }
         None
     }
     fn get_swapchain_capabilities(physical_device: &Arc<PhysicalDevice>, surface: &Arc<Surface>) -> SurfaceCapabilities {
-        physical_device.surface_capabilities(&*surface, SurfaceInfo::default())
+        physical_device.surface_capabilities(surface, SurfaceInfo::default())
             .expect("Fail to get surface capabilities.")
     }
-    fn select_swapchain_format(physical_device: &Arc<PhysicalDevice>, surface: &Arc<Surface>) -> Option<(Format, ColorSpace)> {
-        let formats = physical_device.surface_formats(&*surface, SurfaceInfo::default())
+    fn select_swapchain_format(
+        physical_device: &Arc<PhysicalDevice>,
+        surface: &Arc<Surface>,
+        swapchain_config: &SwapchainConfig
+    ) -> Option<(Format, ColorSpace)> {
+        let formats = physical_device.surface_formats(surface, SurfaceInfo::default())
             .expect("Fail to get available formats");
-        for format in formats.iter() {
-            if let Format::B8G8R8A8_SRGB = format.0 {
-                if let ColorSpace::SrgbNonLinear = format.1 {
-                    return Some(*format);
-                }
-            }
-        }
-        if formats.is_empty() { None }
-        else { Some(formats[0]) }
-    }
-    fn select_swapchain_present_mode(physical_device: &Arc<PhysicalDevice>, surface: &Arc<Surface>) -> Option<PresentMode> {
-        let present_modes = physical_device.surface_present_modes(&*surface, SurfaceInfo::default())
-            .expect("Fail to get available presend modes.");
-        for mode in present_modes {
-            if let PresentMode::Fifo = mode {
-                return Some(mode);
-            }
-        }
-        None
+        swapchain_config.select_surface_format(&formats)
+    }
+    fn select_swapchain_present_mode(
+        physical_device: &Arc<PhysicalDevice>,
+        surface: &Arc<Surface>,
+        swapchain_config: &SwapchainConfig
+    ) -> Option<PresentMode> {
+        let present_modes: Vec<PresentMode> = physical_device.surface_present_modes(surface, SurfaceInfo::default())
+            .expect("Fail to get available presend modes.")
+            .collect();
+        swapchain_config.select_present_mode(&present_modes)
     }
     fn physical_device_support(
         physical_device: &Arc<PhysicalDevice>,
@@ -197,26 +277,44 @@ impl Framework {
         format: (Format, ColorSpace),
         present_mode: PresentMode,
         extent: [u32; 2],
-        image_count: u32
+        image_count: u32,
+        image_sharing: Sharing<SmallVec<[u32; 4]>>
     ) -> (Arc<Swapchain>, Vec<Arc<Image>>) {
+        // Transfer source lets screenshots copy the presented image out, and
+        // transfer destination lets post-processing blit its result in.
+        let supported_usage = device.physical_device()
+            .surface_capabilities(&surface, SurfaceInfo::default())
+            .expect("Fail to get surface capabilities.")
+            .supported_usage_flags;
+        let image_usage = ImageUsage::COLOR_ATTACHMENT
+            | (supported_usage & (ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST));
         let create_info = SwapchainCreateInfo {
             image_format: format.0,
             image_color_space: format.1,
-            present_mode: present_mode,
+            present_mode,
             image_extent: extent,
             min_image_count: image_count,
-            image_usage: ImageUsage::COLOR_ATTACHMENT,
+            image_usage,
+            image_sharing,
             ..Default::default()
         };
         Swapchain::new(device, surface, create_info).expect("Fail to create swapchain.")
     }
-    fn new_swapchain_image_views(format: Format, swapchain_images: &Vec<Arc<Image>>) -> Vec<Arc<ImageView>> {
+    fn name_swapchain_objects(swapchain: &Arc<Swapchain>, images: &[Arc<Image>], image_views: &[Arc<ImageView>]) {
+        debug::set_object_name(&**swapchain, "swapchain");
+        for (i, (image, image_view)) in images.iter().zip(image_views).enumerate() {
+            debug::set_object_name(&**image, &format!("swapchain image {i}"));
+            debug::set_object_name(&**image_view, &format!("swapchain image view {i}"));
+        }
+    }
+    fn new_swapchain_image_views(format: Format, swapchain_images: &[Arc<Image>]) -> Vec<Arc<ImageView>> {
         swapchain_images.iter()
             .map(|image| {
-                let subresource_range = ImageSubresourceRange::from_parameters(format, 1, 1);
+                // Stereo swapchains have several layers, viewed as an array.
                 let create_info = ImageViewCreateInfo {
                     format,
-                    subresource_range,
+                    view_type: texture::full_view_type(image),
+                    subresource_range: image.subresource_range(),
                     ..Default::default()
                 };
                 ImageView::new(image.clone(), create_info)
@@ -224,21 +322,34 @@ impl Framework {
             })
             .collect()
     }
-    pub fn new(event_loop: &ActiveEventLoop) -> Self {
+    pub fn new(event_loop: &ActiveEventLoop, config: &Config) -> Self {
         let window = Self::new_window(event_loop);
 
         let instance = {
             let library = Self::new_library();
 
-            let enabled_layers = vec![String::from("VK_LAYER_KHRONOS_validation")];
-            let enabled_extensions = InstanceExtensions { ext_debug_utils: true, ..Surface::required_extensions(event_loop) };
-            let debug_utils_messengers = vec![debug::debug_printing_messenger()];
+            let (enabled_layers, debug_extensions) = Self::select_validation(&library, config.validation);
+            let enabled_extensions = debug_extensions.union(&Surface::required_extensions(event_loop));
+            let debug_utils_messengers = if enabled_extensions.ext_debug_utils {
+                vec![debug::debug_messenger(config)]
+            }
+            else {
+                Vec::new()
+            };
             Self::new_instance(library, enabled_layers, enabled_extensions, debug_utils_messengers)
         };
 
         let surface = Self::new_surface(instance.clone(), window.clone());
 
-        
+        Self::from_surface(window, instance, surface, config.swapchain.clone(), config.queues)
+    }
+    fn from_surface(
+        window: Arc<Window>,
+        instance: Arc<Instance>,
+        surface: Arc<Surface>,
+        swapchain_config: SwapchainConfig,
+        queue_config: QueueConfig
+    ) -> Self {
         let enabled_extensions = DeviceExtensions {
             khr_swapchain: true,
             ..Default::default()
@@ -251,22 +362,38 @@ impl Framework {
             |physical_device| -> bool {
                 Self::select_graphics_queue_family(physical_device).is_some()
                 && Self::select_present_queue_family(physical_device, &surface).is_some()
-                && Self::select_swapchain_format(physical_device, &surface).is_some()
-                && Self::select_swapchain_present_mode(physical_device, &surface).is_some()
+                && Self::select_swapchain_format(physical_device, &surface, &swapchain_config).is_some()
+                && Self::select_swapchain_present_mode(physical_device, &surface, &swapchain_config).is_some()
                 && Self::physical_device_support(physical_device, &enabled_extensions, &enabled_features)
             }
         );
 
-        let (device, graphics_queue, present_queue) = {
+        let (device, graphics_queue, present_queue, compute_queue, transfer_queue) = {
             let graphics_queue_family_index = Self::select_graphics_queue_family(&physical_device)
                 .expect("[?]Fail to find graphics family index.");
             let present_queue_family_index = Self::select_present_queue_family(&physical_device, &surface)
                 .expect("[?]Fail to find present family index.");
-            let unique_indices = HashSet::from([graphics_queue_family_index, present_queue_family_index]);
+            let queue_flags: Vec<_> = physical_device.queue_family_properties().iter()
+                .map(|property| property.queue_flags)
+                .collect();
+            let compute_queue_family_index = Self::select_dedicated_compute_queue_family(&queue_flags)
+                .filter(|_| queue_config.dedicated_compute)
+                .unwrap_or(graphics_queue_family_index);
+            let transfer_queue_family_index = Self::select_dedicated_transfer_queue_family(&queue_flags)
+                .filter(|_| queue_config.dedicated_transfer)
+                .unwrap_or(graphics_queue_family_index);
+            let unique_indices = HashSet::from([
+                graphics_queue_family_index,
+                present_queue_family_index,
+                compute_queue_family_index,
+                transfer_queue_family_index
+            ]);
             let queue_create_infos = unique_indices
                 .iter()
                 .map(|index| QueueCreateInfo { queue_family_index: *index, ..Default::default() })
                 .collect();
+            let enabled_features = enabled_features
+                .union(&physical_device.supported_features().intersection(&OPTIONAL_FEATURES));
             let (device, queues) = Self::new_device(physical_device.clone(), queue_create_infos, enabled_extensions, enabled_features);
             let queues = queues.collect::<Vec<_>>();
             let retrieve_queue = |index: u32| -> Arc<Queue> {
@@ -279,26 +406,38 @@ impl Framework {
             };
             let graphics_queue = retrieve_queue(graphics_queue_family_index);
             let present_queue = retrieve_queue(present_queue_family_index);
-            (device, graphics_queue, present_queue)
+            let compute_queue = retrieve_queue(compute_queue_family_index);
+            let transfer_queue = retrieve_queue(transfer_queue_family_index);
+            log::info!(
+                "Queue families: graphics {graphics_queue_family_index}, present {present_queue_family_index}, \
+                compute {compute_queue_family_index}, transfer {transfer_queue_family_index}."
+            );
+            debug::set_object_name(&*transfer_queue, "transfer queue");
+            debug::set_object_name(&*compute_queue, "compute queue");
+            debug::set_object_name(&*present_queue, "present queue");
+            debug::set_object_name(&*graphics_queue, "graphics queue");
+            (device, graphics_queue, present_queue, compute_queue, transfer_queue)
         };
 
         let (swapchain, swapchain_images) = {
-            let format = Self::select_swapchain_format(&physical_device, &surface)
+            let format = Self::select_swapchain_format(&physical_device, &surface, &swapchain_config)
                 .expect("[?]Fail to select format");
-            let present_mode = Self::select_swapchain_present_mode(&physical_device, &surface)
+            let present_mode = Self::select_swapchain_present_mode(&physical_device, &surface, &swapchain_config)
                 .expect("[?]Fail to select present mode");
             let capabilities = Self::get_swapchain_capabilities(&physical_device, &surface);
-            let extent = capabilities.current_extent.unwrap();
-            let image_count = if let Some(max_image_count) = capabilities.max_image_count {
-                max(max_image_count, capabilities.min_image_count + 1)
-            }
-            else {
-                capabilities.min_image_count + 1
-            };
-            Self::new_swapchain(device.clone(), surface.clone(), format, present_mode, extent, image_count)
+            let extent = resolve_extent(
+                capabilities.current_extent,
+                window.inner_size().into(),
+                capabilities.min_image_extent,
+                capabilities.max_image_extent
+            );
+            let image_count = swapchain_config.select_image_count(&capabilities);
+            let image_sharing = Self::sharing(&[&graphics_queue, &present_queue]);
+            Self::new_swapchain(device.clone(), surface.clone(), format, present_mode, extent, image_count, image_sharing)
         };
 
         let swapchain_image_views = Self::new_swapchain_image_views(swapchain.image_format(), &swapchain_images);
+        Self::name_swapchain_objects(&swapchain, &swapchain_images, &swapchain_image_views);
 
         Framework {
             window,
@@ -308,36 +447,120 @@ impl Framework {
             device,
             graphics_queue,
             present_queue,
+            compute_queue,
+            transfer_queue,
+            queue_config,
+            swapchain_config,
+            swapchain_lifecycle: SwapchainLifecycle::default(),
             swapchain,
             swapchain_images,
-            swapchain_image_views
+            swapchain_image_views,
+            device_lost_simulated: false
         }
     }
-    pub fn recreate_swapchain(&mut self) -> bool {
-        let (swapchain, swapchain_images) = {
-            let capabilities = Self::get_swapchain_capabilities(&self.physical_device, &self.surface);
-            let extent = capabilities.current_extent.unwrap();
-            if extent[0] == 0 || extent[1] == 0 {
-                return false;
-            }
-            let create_info = SwapchainCreateInfo {
-                image_extent: extent,
-                ..self.swapchain.create_info()
-            };
-            self.swapchain.recreate(create_info).expect("Fail to recreate swapchain.")
-        };
+    /// Rebuilds the device, queues and swapchain after the device was lost.
+    /// The window, instance and surface survive; everything created from
+    /// the old device, including allocators and pipelines held elsewhere,
+    /// must be dropped and recreated by the caller.
+    pub fn recover_device(self) -> Self {
+        log::warn!("Device lost, recreating the GPU context.");
+        let Framework {
+            window,
+            instance,
+            surface,
+            physical_device,
+            device,
+            graphics_queue,
+            present_queue,
+            compute_queue,
+            transfer_queue,
+            queue_config,
+            swapchain_config,
+            swapchain_lifecycle: _,
+            swapchain,
+            swapchain_images,
+            swapchain_image_views,
+            device_lost_simulated: _
+        } = self;
+        // The surface can only own one swapchain, so the old one has to be
+        // destroyed before the new one is created.
+        drop(swapchain_image_views);
+        drop(swapchain_images);
+        drop(swapchain);
+        drop((graphics_queue, present_queue, compute_queue, transfer_queue, device, physical_device));
+        Self::from_surface(window, instance, surface, swapchain_config, queue_config)
+    }
+    /// Makes the next acquire report `DeviceLost`, to exercise the recovery
+    /// path without a real GPU reset.
+    #[cfg(debug_assertions)]
+    pub fn simulate_device_lost(&mut self) {
+        log::warn!("Simulating device loss.");
+        self.device_lost_simulated = true;
+    }
+    fn window_extent(&self) -> [u32; 2] {
+        self.window.inner_size().into()
+    }
+    fn replace_swapchain(&mut self, swapchain: Arc<Swapchain>, swapchain_images: Vec<Arc<Image>>) {
         let swapchain_image_views = Self::new_swapchain_image_views(swapchain.image_format(), &swapchain_images);
+        Self::name_swapchain_objects(&swapchain, &swapchain_images, &swapchain_image_views);
 
         self.swapchain_image_views = swapchain_image_views;
         self.swapchain_images = swapchain_images;
         self.swapchain = swapchain;
-        true
     }
-    pub fn acquire_next_image(&self) -> Option<(u32, SwapchainAcquireFuture)> {
-        let (image_index, suboptimal, image_available) = acquire_next_image(self.swapchain.clone(), None)
-            .expect("Fail to acquire next image.");
-        if suboptimal { None }
-        else { Some((image_index, image_available)) }
+    /// Schedules a swapchain rebuild before the next acquire, e.g. after the
+    /// window was resized.
+    pub fn invalidate_swapchain(&mut self) {
+        self.swapchain_lifecycle.invalidate();
+    }
+    /// Sharing mode for a resource used from all of `queues`. Resources that
+    /// cross queue families are shared concurrently, so no explicit
+    /// ownership transfer barriers are needed; otherwise they stay exclusive.
+    pub fn sharing(queues: &[&Arc<Queue>]) -> Sharing<SmallVec<[u32; 4]>> {
+        let mut families: SmallVec<[u32; 4]> = SmallVec::new();
+        for queue in queues {
+            let family = queue.queue_family_index();
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+        if families.len() > 1 { Sharing::Concurrent(families) }
+        else { Sharing::Exclusive }
+    }
+    /// Sharing mode for buffers and images that the graphics, compute and
+    /// transfer queues all touch.
+    pub fn resource_sharing(&self) -> Sharing<SmallVec<[u32; 4]>> {
+        Self::sharing(&[&self.graphics_queue, &self.compute_queue, &self.transfer_queue])
+    }
+    /// Switches vertical synchronization on or off. The swapchain is rebuilt
+    /// with a matching present mode before the next frame.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.swapchain_config.vsync = vsync;
+        self.invalidate_swapchain();
+    }
+    /// Whether swapchain images can be copied out for screenshots.
+    pub fn can_capture(&self) -> bool {
+        self.swapchain.image_usage().intersects(ImageUsage::TRANSFER_SRC)
+    }
+    /// Whether images can be blitted into the swapchain.
+    pub fn can_blit(&self) -> bool {
+        let blit_dst = self.physical_device.format_properties(self.swapchain.image_format())
+            .map(|properties| properties.optimal_tiling_features.intersects(FormatFeatures::BLIT_DST))
+            .unwrap_or(false);
+        blit_dst && self.swapchain.image_usage().intersects(ImageUsage::TRANSFER_DST)
+    }
+    /// Acquires the next swapchain image, rebuilding the swapchain or the
+    /// surface first when needed. Returns `None` when there is nothing to
+    /// render into, e.g. while the window is minimized.
+    pub fn acquire_next_image(&mut self) -> Result<Option<(u32, SwapchainAcquireFuture)>, DeviceLost> {
+        if self.device_lost_simulated {
+            return Err(DeviceLost);
+        }
+        let window_extent = self.window_extent();
+        let mut lifecycle = self.swapchain_lifecycle;
+        let acquired = lifecycle.acquire(self, window_extent);
+        self.swapchain_lifecycle = lifecycle;
+        check_device_lost(acquired.map_err(Validated::Error), "Fail to acquire next image.")
     }
     pub fn execute_command_buffer<F, C>(&self, before: F, command_buffer: Arc<C>) -> CommandBufferExecFuture<F>
     where 
@@ -347,6 +570,20 @@ impl Framework {
         before.then_execute(self.graphics_queue.clone(), command_buffer)
             .expect("Fail to execute command buffer.")
     }
+    /// Submits `command_buffer` to the compute queue. Work that reads its
+    /// results waits on the semaphore signaled by the returned future.
+    pub fn execute_compute_command_buffer<C>(
+        &self,
+        command_buffer: Arc<C>
+    ) -> Result<SemaphoreSignalFuture<CommandBufferExecFuture<NowFuture>>, Validated<VulkanError>>
+    where
+        C: 'static + PrimaryCommandBufferAbstract
+    {
+        sync::now(self.device.clone())
+            .then_execute(self.compute_queue.clone(), command_buffer)
+            .expect("Fail to execute compute command buffer.")
+            .then_signal_semaphore_and_flush()
+    }
     pub fn present_image<F: GpuFuture>(&self, before: F, image_index: u32) -> PresentFuture<F> {
         let swapchain_info = SwapchainPresentInfo::swapchain_image_index(
             self.swapchain.clone(),
@@ -354,4 +591,96 @@ impl Framework {
         );
         before.then_swapchain_present(self.present_queue.clone(), swapchain_info)
     }
-}
\ No newline at end of file
+    /// Feeds the result of flushing and waiting on a present back into the
+    /// swapchain lifecycle, so that out-of-date swapchains get rebuilt.
+    pub fn handle_present_result(&mut self, result: Result<(), Validated<VulkanError>>) -> Result<(), DeviceLost> {
+        let result = result.map_err(Validated::unwrap);
+        let presented = self.swapchain_lifecycle.presented(result);
+        check_device_lost(presented.map_err(Validated::Error), "Fail to present image.")
+    }
+}
+
+impl SwapchainBackend for Framework {
+    type Acquired = (u32, SwapchainAcquireFuture);
+
+    fn acquire(&mut self) -> Result<(Self::Acquired, bool), VulkanError> {
+        let (image_index, suboptimal, image_available) = acquire_next_image(self.swapchain.clone(), None)
+            .map_err(Validated::unwrap)?;
+        Ok(((image_index, image_available), suboptimal))
+    }
+    fn surface_extent(&self) -> Option<[u32; 2]> {
+        self.physical_device.surface_capabilities(&self.surface, SurfaceInfo::default())
+            .ok()
+            .and_then(|capabilities| capabilities.current_extent)
+    }
+    fn recreate_swapchain(&mut self, extent: [u32; 2]) -> Result<(), VulkanError> {
+        let capabilities = self.physical_device.surface_capabilities(&self.surface, SurfaceInfo::default())
+            .map_err(Validated::unwrap)?;
+        let image_extent = resolve_extent(None, extent, capabilities.min_image_extent, capabilities.max_image_extent);
+        let present_mode = Self::select_swapchain_present_mode(&self.physical_device, &self.surface, &self.swapchain_config)
+            .expect("[?]Fail to select present mode");
+        if present_mode != self.swapchain.present_mode() {
+            log::info!("Switching to present mode {present_mode:?}.");
+        }
+        let create_info = SwapchainCreateInfo {
+            image_extent,
+            present_mode,
+            ..self.swapchain.create_info()
+        };
+        let (swapchain, swapchain_images) = self.swapchain.recreate(create_info)
+            .map_err(Validated::unwrap)?;
+        self.replace_swapchain(swapchain, swapchain_images);
+        Ok(())
+    }
+    fn recreate_surface(&mut self) -> Result<(), VulkanError> {
+        log::warn!("Surface lost, recreating it.");
+        let surface = Surface::from_window(self.instance.clone(), self.window.clone())
+            .map_err(Validated::unwrap)?;
+        let create_info = SwapchainCreateInfo {
+            image_extent: self.window_extent(),
+            ..self.swapchain.create_info()
+        };
+        let (swapchain, swapchain_images) = Swapchain::new(self.device.clone(), surface.clone(), create_info)
+            .map_err(Validated::unwrap)?;
+        self.surface = surface;
+        self.replace_swapchain(swapchain, swapchain_images);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedicated_queue_families_skip_graphics_families() {
+        let universal = QueueFlags::GRAPHICS | QueueFlags::COMPUTE | QueueFlags::TRANSFER;
+        let async_compute = QueueFlags::COMPUTE | QueueFlags::TRANSFER;
+        let queue_flags = [universal, QueueFlags::TRANSFER, async_compute];
+        assert_eq!(Framework::select_dedicated_compute_queue_family(&queue_flags), Some(2));
+        assert_eq!(Framework::select_dedicated_transfer_queue_family(&queue_flags), Some(1));
+    }
+
+    #[test]
+    fn missing_dedicated_queue_families_are_none() {
+        let queue_flags = [QueueFlags::GRAPHICS | QueueFlags::COMPUTE | QueueFlags::TRANSFER];
+        assert_eq!(Framework::select_dedicated_compute_queue_family(&queue_flags), None);
+        assert_eq!(Framework::select_dedicated_transfer_queue_family(&queue_flags), None);
+        assert_eq!(Framework::select_dedicated_transfer_queue_family(&[QueueFlags::COMPUTE | QueueFlags::TRANSFER]), None);
+        assert_eq!(Framework::select_dedicated_compute_queue_family(&[]), None);
+    }
+
+    #[test]
+    fn device_lost_is_recoverable() {
+        let result: Result<(), _> = Err(Validated::Error(VulkanError::DeviceLost));
+        assert_eq!(check_device_lost(result, "test"), Err(DeviceLost));
+        assert_eq!(check_device_lost(Ok::<_, Validated<VulkanError>>(3), "test"), Ok(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "test")]
+    fn other_errors_panic() {
+        let result: Result<(), _> = Err(Validated::Error(VulkanError::OutOfDeviceMemory));
+        let _ = check_device_lost(result, "test");
+    }
+}
@@ -0,0 +1,443 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH
+};
+
+use vulkano::{
+    DeviceSize,
+    buffer::{BufferContents, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::WriteDescriptorSet,
+    device::{Device, DeviceOwned, Queue},
+    format::Format,
+    image::{
+        Image, ImageAspects, ImageCreateFlags, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode},
+        view::{ImageView, ImageViewCreateInfo, ImageViewType}
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}
+};
+
+use crate::{
+    debug,
+    allocator::Allocator,
+    compute::{self, ComputePass},
+    texture::{self, HdrData}
+};
+
+pub const ENVIRONMENT_SIZE: u32 = 512;
+pub const IRRADIANCE_SIZE: u32 = 32;
+pub const PREFILTERED_SIZE: u32 = 128;
+/// Roughness 0.0 to 1.0 in even steps, one per mip level.
+pub const PREFILTERED_MIPS: u32 = 5;
+pub const BRDF_LUT_SIZE: u32 = 128;
+
+const IBL_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+const LOCAL_SIZE: u32 = 8;
+/// Bumped whenever the baking changes.
+const CACHE_MAGIC: &[u8; 8] = b"LVIBL001";
+
+/// Shape of one baked image.
+#[derive(Clone, Copy, Debug)]
+struct BakedImage {
+    name: &'static str,
+    size: u32,
+    array_layers: u32,
+    mip_levels: u32
+}
+
+impl BakedImage {
+    fn create_info(&self, usage: ImageUsage) -> ImageCreateInfo {
+        let flags = if self.array_layers == 6 { ImageCreateFlags::CUBE_COMPATIBLE } else { ImageCreateFlags::empty() };
+        ImageCreateInfo {
+            flags,
+            image_type: ImageType::Dim2d,
+            format: IBL_FORMAT,
+            extent: [self.size, self.size, 1],
+            array_layers: self.array_layers,
+            mip_levels: self.mip_levels,
+            usage,
+            ..Default::default()
+        }
+    }
+    fn byte_size(&self) -> DeviceSize {
+        texture::image_byte_size(IBL_FORMAT, [self.size; 2], self.array_layers, self.mip_levels)
+    }
+}
+
+/// The baked images, in cache order.
+const BAKED_IMAGES: [BakedImage; 4] = [
+    BakedImage { name: "environment", size: ENVIRONMENT_SIZE, array_layers: 6, mip_levels: ENVIRONMENT_SIZE.ilog2() + 1 },
+    BakedImage { name: "irradiance", size: IRRADIANCE_SIZE, array_layers: 6, mip_levels: 1 },
+    BakedImage { name: "prefiltered", size: PREFILTERED_SIZE, array_layers: 6, mip_levels: PREFILTERED_MIPS },
+    BakedImage { name: "BRDF table", size: BRDF_LUT_SIZE, array_layers: 1, mip_levels: 1 }
+];
+
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+struct BakeConstants {
+    params: [f32; 4]
+}
+
+fn new_view(image: &Arc<Image>, view_type: ImageViewType, mip_levels: Range<u32>, usage: ImageUsage) -> Arc<ImageView> {
+    let create_info = ImageViewCreateInfo {
+        view_type,
+        subresource_range: ImageSubresourceRange {
+            aspects: ImageAspects::COLOR,
+            mip_levels,
+            array_layers: 0..image.array_layers()
+        },
+        usage,
+        ..ImageViewCreateInfo::from_image(image)
+    };
+    ImageView::new(image.clone(), create_info).expect("Fail to create environment image view.")
+}
+
+fn cube_view(image: &Arc<Image>, mip_levels: Range<u32>) -> Arc<ImageView> {
+    new_view(image, ImageViewType::Cube, mip_levels, ImageUsage::SAMPLED)
+}
+
+/// The six faces of one mip level, for compute shaders to write.
+fn faces_view(image: &Arc<Image>, mip_level: u32) -> Arc<ImageView> {
+    new_view(image, ImageViewType::Dim2dArray, mip_level..mip_level + 1, ImageUsage::STORAGE)
+}
+
+fn cube_group_counts(size: u32) -> [u32; 3] {
+    let groups = compute::group_count(size, LOCAL_SIZE);
+    [groups, groups, 6]
+}
+
+/// Ambient lighting baked from an HDR environment: irradiance, prefiltered
+/// reflections and the split-sum BRDF table.
+pub struct ImageBasedLighting {
+    pub environment: Arc<ImageView>,
+    pub irradiance: Arc<ImageView>,
+    pub prefiltered: Arc<ImageView>,
+    pub brdf_lut: Arc<ImageView>,
+    pub sampler: Arc<Sampler>,
+    /// Scales every contribution of the environment.
+    pub intensity: f32
+}
+
+impl ImageBasedLighting {
+    fn new_sampler(device: Arc<Device>) -> Arc<Sampler> {
+        Sampler::new(device, SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..Default::default()
+        }).expect("Fail to create environment sampler.")
+    }
+    fn from_images(device: Arc<Device>, images: [Arc<Image>; 4]) -> Self {
+        let [environment, irradiance, prefiltered, brdf_lut] = images;
+        ImageBasedLighting {
+            environment: cube_view(&environment, 0..environment.mip_levels()),
+            irradiance: cube_view(&irradiance, 0..1),
+            prefiltered: cube_view(&prefiltered, 0..prefiltered.mip_levels()),
+            brdf_lut: new_view(&brdf_lut, ImageViewType::Dim2d, 0..1, ImageUsage::SAMPLED),
+            sampler: Self::new_sampler(device),
+            intensity: 1.0
+        }
+    }
+    fn images(&self) -> [Arc<Image>; 4] {
+        [&self.environment, &self.irradiance, &self.prefiltered, &self.brdf_lut].map(|view| view.image().clone())
+    }
+    /// Records the upload of images read back from a bake.
+    fn record_restore(
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        shapes: [BakedImage; 4],
+        blobs: &[Vec<u8>]
+    ) -> Self {
+        let images = std::array::from_fn(|i| {
+            let create_info = shapes[i].create_info(ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC);
+            texture::record_image_upload(builder, allocator, create_info, &blobs[i], shapes[i].name)
+        });
+        Self::from_images(allocator.memory_allocator.device().clone(), images)
+    }
+    /// A black environment, bound when a scene has none.
+    pub fn black(allocator: &Allocator, queue: &Arc<Queue>) -> Self {
+        let shapes = BAKED_IMAGES.map(|shape| BakedImage { size: 1, mip_levels: 1, ..shape });
+        let blobs: Vec<Vec<u8>> = shapes.iter().map(|shape| vec![0; shape.byte_size() as usize]).collect();
+        texture::upload_now(allocator, queue, |builder| Self::record_restore(builder, allocator, shapes, &blobs))
+    }
+    /// Records the whole bake of `hdr`, an equirectangular environment.
+    pub fn record_bake(
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        hdr: &HdrData
+    ) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let sampler = Self::new_sampler(device.clone());
+        let usage = ImageUsage::STORAGE | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC;
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        };
+        let [environment, irradiance, prefiltered, brdf_lut] = BAKED_IMAGES.map(|shape| {
+            let create_info = ImageCreateInfo { sharing: allocator.sharing.clone(), ..shape.create_info(usage) };
+            let image = Image::new(allocator.memory_allocator.clone(), create_info, allocation_info.clone())
+                .expect("Fail to allocate environment image.");
+            debug::set_object_name(&*image, shape.name);
+            image
+        });
+        let source_writes = |source: Arc<ImageView>, target: Arc<ImageView>| [
+            WriteDescriptorSet::image_view(0, source),
+            WriteDescriptorSet::sampler(1, sampler.clone()),
+            WriteDescriptorSet::image_view(2, target)
+        ];
+        let constants = |params: [f32; 4]| BakeConstants { params };
+
+        let equirectangular = {
+            let create_info = ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: IBL_FORMAT,
+                extent: [hdr.extent[0], hdr.extent[1], 1],
+                usage: ImageUsage::SAMPLED,
+                ..Default::default()
+            };
+            let image = texture::record_image_upload(builder, allocator, create_info, &hdr.to_rgba16f(), "equirectangular environment");
+            ImageView::new_default(image).expect("Fail to create equirectangular image view.")
+        };
+        let pass = ComputePass::new(device.clone(), "ibl_equirect_to_cube_comp.spv", "equirectangular to cube");
+        let descriptor_set = pass.descriptor_set(allocator, 0, source_writes(equirectangular, faces_view(&environment, 0)));
+        pass.record_dispatch(builder, descriptor_set, constants([ENVIRONMENT_SIZE as f32, 0.0, 0.0, 0.0]), cube_group_counts(ENVIRONMENT_SIZE));
+
+        let pass = ComputePass::new(device.clone(), "ibl_downsample_cube_comp.spv", "environment mips");
+        for mip_level in 1..environment.mip_levels() {
+            let size = texture::mip_extent([ENVIRONMENT_SIZE; 2], mip_level)[0];
+            let writes = source_writes(cube_view(&environment, mip_level - 1..mip_level), faces_view(&environment, mip_level));
+            let descriptor_set = pass.descriptor_set(allocator, 0, writes);
+            pass.record_dispatch(builder, descriptor_set, constants([size as f32, 0.0, 0.0, 0.0]), cube_group_counts(size));
+        }
+
+        let source = cube_view(&environment, 0..environment.mip_levels());
+        let highest_mip = (environment.mip_levels() - 1) as f32;
+        let pass = ComputePass::new(device.clone(), "ibl_irradiance_comp.spv", "irradiance");
+        let descriptor_set = pass.descriptor_set(allocator, 0, source_writes(source.clone(), faces_view(&irradiance, 0)));
+        let params = [IRRADIANCE_SIZE as f32, highest_mip, ENVIRONMENT_SIZE as f32, 0.0];
+        pass.record_dispatch(builder, descriptor_set, constants(params), cube_group_counts(IRRADIANCE_SIZE));
+
+        let pass = ComputePass::new(device.clone(), "ibl_prefilter_comp.spv", "prefiltered environment");
+        for mip_level in 0..PREFILTERED_MIPS {
+            let size = texture::mip_extent([PREFILTERED_SIZE; 2], mip_level)[0];
+            let roughness = mip_level as f32 / (PREFILTERED_MIPS - 1) as f32;
+            let descriptor_set = pass.descriptor_set(allocator, 0, source_writes(source.clone(), faces_view(&prefiltered, mip_level)));
+            let params = [size as f32, roughness, highest_mip, ENVIRONMENT_SIZE as f32];
+            pass.record_dispatch(builder, descriptor_set, constants(params), cube_group_counts(size));
+        }
+
+        let pass = ComputePass::new(device.clone(), "ibl_brdf_lut_comp.spv", "BRDF table");
+        let table = new_view(&brdf_lut, ImageViewType::Dim2d, 0..1, ImageUsage::STORAGE);
+        let descriptor_set = pass.descriptor_set(allocator, 0, [WriteDescriptorSet::image_view(0, table)]);
+        let groups = compute::group_count(BRDF_LUT_SIZE, LOCAL_SIZE);
+        pass.record_dispatch(builder, descriptor_set, constants([BRDF_LUT_SIZE as f32, 0.0, 0.0, 0.0]), [groups, groups, 1]);
+
+        Self::from_images(device, [environment, irradiance, prefiltered, brdf_lut])
+    }
+    /// Bakes `hdr` on `queue`, which must support compute, and blocks
+    /// until done.
+    pub fn bake(allocator: &Allocator, queue: &Arc<Queue>, hdr: &HdrData) -> Self {
+        texture::upload_now(allocator, queue, |builder| Self::record_bake(builder, allocator, hdr))
+    }
+    /// Loads the environment of an equirectangular `.hdr` file, through
+    /// the cache in `cache_directory` when set. Cached images are uploaded
+    /// on `transfer_queue`, bakes run on `compute_queue`.
+    pub fn load(
+        allocator: &Allocator,
+        transfer_queue: &Arc<Queue>,
+        compute_queue: &Arc<Queue>,
+        path: &Path,
+        cache_directory: Option<&Path>
+    ) -> io::Result<Self> {
+        let key = cache_key(path)?;
+        let cache_path = cache_directory.map(|directory| cache_file(directory, path));
+        let sizes = BAKED_IMAGES.map(|shape| shape.byte_size());
+        if let Some(cache_path) = &cache_path {
+            match read_cache(cache_path, key, &sizes) {
+                Ok(blobs) => {
+                    log::info!("Loaded baked environment from {}.", cache_path.display());
+                    return Ok(texture::upload_now(allocator, transfer_queue, |builder| {
+                        Self::record_restore(builder, allocator, BAKED_IMAGES, &blobs)
+                    }));
+                }
+                Err(error) => log::debug!("Not using environment cache {}: {error}.", cache_path.display())
+            }
+        }
+
+        let hdr = texture::load_hdr(path)?;
+        let (environment, downloads) = texture::upload_now(allocator, compute_queue, |builder| {
+            let environment = Self::record_bake(builder, allocator, &hdr);
+            let downloads = cache_path.is_some().then(|| environment.record_download(builder, allocator));
+            (environment, downloads)
+        });
+        if let (Some(cache_path), Some(downloads)) = (cache_path, downloads) {
+            let blobs: Vec<Vec<u8>> = downloads.iter()
+                .map(|download| download.read().expect("Fail to read baked environment.").to_vec())
+                .collect();
+            match write_cache(&cache_path, key, &blobs) {
+                Ok(()) => log::info!("Cached baked environment in {}.", cache_path.display()),
+                Err(error) => log::warn!("Fail to cache baked environment in {}: {error}.", cache_path.display())
+            }
+        }
+        Ok(environment)
+    }
+    /// Records a copy of every baked image to host memory, in cache order.
+    pub fn record_download(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator
+    ) -> [Subbuffer<[u8]>; 4] {
+        self.images().map(|image| texture::record_image_download(builder, allocator, image))
+    }
+    /// Highest mip level of the prefiltered cube.
+    pub fn max_prefiltered_mip(&self) -> f32 {
+        (self.prefiltered.image().mip_levels() - 1) as f32
+    }
+    /// Binds the irradiance cube, prefiltered cube, BRDF table and sampler
+    /// from `first_binding` on.
+    pub fn sampled(&self, first_binding: u32) -> [WriteDescriptorSet; 4] {
+        [
+            WriteDescriptorSet::image_view(first_binding, self.irradiance.clone()),
+            WriteDescriptorSet::image_view(first_binding + 1, self.prefiltered.clone()),
+            WriteDescriptorSet::image_view(first_binding + 2, self.brdf_lut.clone()),
+            WriteDescriptorSet::sampler(first_binding + 3, self.sampler.clone())
+        ]
+    }
+}
+
+/// Identifies the version of a source file a cache was baked from.
+fn cache_key(path: &Path) -> io::Result<[u64; 2]> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    Ok([metadata.len(), modified])
+}
+
+/// Names the cache after the source stem and its canonical path.
+fn cache_file(directory: &Path, source: &Path) -> PathBuf {
+    let stem = source.file_stem().map_or(String::from("environment"), |stem| stem.to_string_lossy().into_owned());
+    let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+    // FNV-1a, stable across Rust releases.
+    let hash = source.as_os_str().as_encoded_bytes().iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3));
+    directory.join(format!("{stem}-{hash:016x}.ibl"))
+}
+
+fn write_cache(path: &Path, key: [u64; 2], blobs: &[Vec<u8>]) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(CACHE_MAGIC)?;
+    for value in key {
+        file.write_all(&value.to_le_bytes())?;
+    }
+    for blob in blobs {
+        file.write_all(&(blob.len() as u64).to_le_bytes())?;
+        file.write_all(blob)?;
+    }
+    file.flush()
+}
+
+/// Reads a cache written by [`write_cache`] for `key`.
+fn read_cache(path: &Path, key: [u64; 2], sizes: &[DeviceSize]) -> io::Result<Vec<Vec<u8>>> {
+    let stale = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut file = BufReader::new(File::open(path)?);
+    fn read_u64(file: &mut impl Read) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        file.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != CACHE_MAGIC {
+        return Err(stale("written by another version"));
+    }
+    if [read_u64(&mut file)?, read_u64(&mut file)?] != key {
+        return Err(stale("source file changed"));
+    }
+    sizes.iter()
+        .map(|size| {
+            if read_u64(&mut file)? != *size {
+                return Err(stale("unexpected image size"));
+            }
+            let mut blob = vec![0; *size as usize];
+            file.read_exact(&mut blob)?;
+            Ok(blob)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+
+    use vulkano::{
+        command_buffer::{CommandBufferUsage, PrimaryCommandBufferAbstract},
+        sync::GpuFuture
+    };
+
+    use crate::test_support;
+
+    use super::*;
+
+    #[test]
+    fn cache_round_trips_and_rejects_stale_entries() {
+        let directory = std::env::temp_dir().join(format!("learn-vulkano-ibl-{}", std::process::id()));
+        let path = cache_file(&directory, Path::new("assets/sky.hdr"));
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("sky-"));
+        assert_eq!(path, cache_file(&directory, Path::new("assets/sky.hdr")));
+        assert_ne!(path, cache_file(&directory, Path::new("other/sky.hdr")));
+        let blobs = vec![vec![1, 2, 3], vec![4; 5]];
+        write_cache(&path, [7, 8], &blobs).unwrap();
+
+        assert_eq!(read_cache(&path, [7, 8], &[3, 5]).unwrap(), blobs);
+        assert!(read_cache(&path, [7, 9], &[3, 5]).is_err());
+        assert!(read_cache(&path, [7, 8], &[3, 6]).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(read_cache(&path, [7, 8], &[3, 5]).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn uniform_environment_bakes_to_uniform_irradiance() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device);
+        let hdr = HdrData { extent: [64, 32], rgb: vec![[0.5, 1.0, 2.0]; 64 * 32] };
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        let environment = ImageBasedLighting::record_bake(&mut builder, &allocator, &hdr);
+        let [_, irradiance, prefiltered, brdf_lut] = environment.record_download(&mut builder, &allocator);
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let texel = |data: &[u8], index: usize| -> [f32; 4] {
+            std::array::from_fn(|channel| {
+                let offset = index * 8 + channel * 2;
+                f16::from_le_bytes([data[offset], data[offset + 1]]).to_f32()
+            })
+        };
+        // Averaging a constant gives the constant back, whatever the lobe.
+        let irradiance = irradiance.read().unwrap();
+        let prefiltered = prefiltered.read().unwrap();
+        for index in [0, irradiance.len() / 8 - 1] {
+            let [r, g, b, _] = texel(&irradiance, index);
+            assert!((r - 0.5).abs() < 0.01 && (g - 1.0).abs() < 0.01 && (b - 2.0).abs() < 0.02, "{r} {g} {b}");
+        }
+        let [r, _, _, _] = texel(&prefiltered, prefiltered.len() / 8 - 1);
+        assert!((r - 0.5).abs() < 0.01, "{r}");
+
+        // Viewed head-on and perfectly smooth, the lobe reflects F0 only.
+        let brdf_lut = brdf_lut.read().unwrap();
+        let [scale, bias, _, _] = texel(&brdf_lut, BRDF_LUT_SIZE as usize - 1);
+        assert!(scale > 0.9 && bias < 0.05, "{scale} {bias}");
+    }
+}
@@ -0,0 +1,208 @@
+//! Indirect draws of meshes sharing one vertex and one index buffer.
+
+use std::sync::Arc;
+
+use vulkano::{
+    device::Device,
+    buffer::{BufferContents, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, DrawIndexedIndirectCommand},
+    descriptor_set::WriteDescriptorSet,
+    pipeline::graphics::vertex_input::Vertex
+};
+
+use crate::{
+    allocator::Allocator,
+    compute::{self, ComputePass}
+};
+
+const LOCAL_SIZE: u32 = 64;
+
+/// Where a mesh lives in a [`MeshArena`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshRange {
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Added to every index of the mesh.
+    pub vertex_offset: u32
+}
+
+impl MeshRange {
+    /// Draws `instance_count` instances of the mesh from `first_instance`.
+    pub fn draw(&self, first_instance: u32, instance_count: u32) -> IndirectDraw {
+        IndirectDraw {
+            first_index: self.first_index,
+            index_count: self.index_count,
+            vertex_offset: self.vertex_offset,
+            first_instance,
+            instance_count
+        }
+    }
+}
+
+/// One draw as [`DrawCommandWriter`] reads it, matching `draw_commands.comp`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[derive(BufferContents)]
+#[repr(C)]
+pub struct IndirectDraw {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: u32,
+    pub first_instance: u32,
+    pub instance_count: u32
+}
+
+impl IndirectDraw {
+    pub fn command(&self) -> DrawIndexedIndirectCommand {
+        DrawIndexedIndirectCommand {
+            index_count: self.index_count,
+            instance_count: self.instance_count,
+            first_index: self.first_index,
+            vertex_offset: self.vertex_offset,
+            first_instance: self.first_instance
+        }
+    }
+}
+
+/// Meshes packed back to back in host memory, with mesh-local indices.
+pub struct MeshArena<V> {
+    pub vertices: Vec<V>,
+    pub indices: Vec<u32>,
+    pub meshes: Vec<MeshRange>
+}
+
+impl<V> Default for MeshArena<V> {
+    fn default() -> Self {
+        MeshArena { vertices: Vec::new(), indices: Vec::new(), meshes: Vec::new() }
+    }
+}
+
+impl<V: Vertex + Clone> MeshArena<V> {
+    /// Appends a mesh and returns its index in `meshes`.
+    pub fn add(&mut self, vertices: &[V], indices: &[u32]) -> usize {
+        self.meshes.push(MeshRange {
+            first_index: self.indices.len() as u32,
+            index_count: indices.len() as u32,
+            vertex_offset: self.vertices.len() as u32
+        });
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend_from_slice(indices);
+        self.meshes.len() - 1
+    }
+    pub fn upload(&self, allocator: &Allocator) -> GpuMeshArena<V> {
+        GpuMeshArena {
+            vertex_buffer: allocator.alloc_vertex_buffer(&self.vertices),
+            index_buffer: allocator.alloc_index_buffer(&self.indices),
+            meshes: self.meshes.clone()
+        }
+    }
+}
+
+/// A [`MeshArena`] uploaded to device memory. Cloning shares the buffers.
+#[derive(Clone)]
+pub struct GpuMeshArena<V> {
+    pub vertex_buffer: Subbuffer<[V]>,
+    pub index_buffer: Subbuffer<[u32]>,
+    pub meshes: Vec<MeshRange>
+}
+
+/// Allocates a draw command buffer for compute shaders and indirect draws,
+/// filled from `draws`.
+pub fn alloc_draw_commands(allocator: &Allocator, draws: &[IndirectDraw]) -> Subbuffer<[DrawIndexedIndirectCommand]> {
+    let commands: Vec<_> = draws.iter().map(IndirectDraw::command).collect();
+    allocator.alloc_storage_buffer(&commands, BufferUsage::INDIRECT_BUFFER, "draw command buffer")
+}
+
+#[derive(BufferContents)]
+#[repr(C)]
+struct PushConstants {
+    draw_count: u32
+}
+
+/// Fills draw command buffers on the GPU from a list of [`IndirectDraw`]s.
+pub struct DrawCommandWriter {
+    pass: ComputePass
+}
+
+impl DrawCommandWriter {
+    pub fn new(device: Arc<Device>) -> Self {
+        DrawCommandWriter {
+            pass: ComputePass::new(device, "draw_commands_comp.spv", "draw commands")
+        }
+    }
+    /// Writes one command per element of `draws` into `commands`.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        draws: Subbuffer<[IndirectDraw]>,
+        commands: Subbuffer<[DrawIndexedIndirectCommand]>
+    ) {
+        let draw_count = draws.len() as u32;
+        let descriptor_set = self.pass.descriptor_set(allocator, 0, [
+            WriteDescriptorSet::buffer(0, draws),
+            WriteDescriptorSet::buffer(1, commands)
+        ]);
+        self.pass.record_dispatch(
+            builder,
+            descriptor_set,
+            PushConstants { draw_count },
+            [compute::group_count(draw_count, LOCAL_SIZE), 1, 1]
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{
+        command_buffer::{CommandBufferUsage, PrimaryCommandBufferAbstract},
+        sync::GpuFuture
+    };
+
+    use crate::{model::ColoredVertex, test_support};
+
+    use super::*;
+
+    fn two_triangles() -> MeshArena<ColoredVertex> {
+        let vertex = ColoredVertex::new([0.0; 3], [1.0; 3]);
+        let mut arena = MeshArena::default();
+        arena.add(&vec![vertex.clone(); 3], &[0, 1, 2]);
+        arena.add(&vec![vertex; 4], &[0, 1, 2, 2, 1, 3]);
+        arena
+    }
+
+    #[test]
+    fn meshes_are_packed_back_to_back() {
+        let arena = two_triangles();
+        assert_eq!(arena.meshes, [
+            MeshRange { first_index: 0, index_count: 3, vertex_offset: 0 },
+            MeshRange { first_index: 3, index_count: 6, vertex_offset: 3 }
+        ]);
+        assert_eq!(arena.indices[3..], [0, 1, 2, 2, 1, 3]);
+        let command = arena.meshes[1].draw(5, 2).command();
+        assert_eq!((command.first_index, command.vertex_offset), (3, 3));
+        assert_eq!((command.first_instance, command.instance_count), (5, 2));
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn gpu_writes_the_draw_commands() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let writer = DrawCommandWriter::new(device);
+        let arena = two_triangles();
+        let draws: Vec<_> = (0..100)
+            .map(|i| arena.meshes[i % 2].draw(i as u32, 1 + i as u32 % 3))
+            .collect();
+        let commands = alloc_draw_commands(&allocator, &vec![IndirectDraw::default(); draws.len()]);
+
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        writer.record(&mut builder, &allocator, allocator.alloc_storage_slice(&draws), commands.clone());
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let expected: Vec<_> = draws.iter().map(IndirectDraw::command).collect();
+        assert_eq!(*commands.read().unwrap(), *expected);
+    }
+}
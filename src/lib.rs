@@ -0,0 +1,32 @@
+pub mod config;
+pub mod debug;
+pub mod framework;
+pub mod swapchain;
+pub mod model;
+pub mod shader;
+pub mod allocator;
+pub mod compute;
+pub mod camera;
+pub mod particles;
+pub mod renderer;
+pub mod texture;
+pub mod material;
+pub mod ibl;
+pub mod skybox;
+pub mod lighting;
+pub mod shadow;
+pub mod render_target;
+pub mod capture;
+pub mod post_process;
+pub mod bloom;
+pub mod deferred;
+pub mod ssao;
+pub mod indirect;
+pub mod bounds;
+pub mod stats;
+pub mod scene;
+pub mod scene_file;
+pub mod app;
+
+#[cfg(test)]
+mod test_support;
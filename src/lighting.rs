@@ -0,0 +1,804 @@
+use std::sync::Arc;
+
+use glam::{Vec3, Vec4};
+
+use vulkano::{
+    device::{DeviceOwned, Queue},
+    buffer::BufferContents,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    image::view::ImageView,
+    pipeline::{
+        Pipeline, PipelineBindPoint,
+        graphics::{GraphicsPipeline, rasterization::CullMode}
+    },
+    render_pass::Subpass
+};
+
+use crate::{
+    debug,
+    allocator::Allocator,
+    bounds::{self, BoundsOverlay, CullStats, Frustum},
+    camera::Camera,
+    ibl::ImageBasedLighting,
+    material::{Material, MaterialDefaults, MaterialSetCache, MATERIAL_SET},
+    deferred::DeferredRenderer,
+    model::MeshInstance,
+    renderer::{self, Renderer, RenderPath, RenderStage},
+    shadow::{ShadowConfig, ShadowDebugView, ShadowMaps, ShadowPlan},
+    skybox::Skybox,
+    ssao::{Ssao, SsaoConfig}
+};
+
+const LIT_LABEL_COLOR: [f32; 4] = [0.95, 0.85, 0.4, 1.0];
+
+/// Light from an infinitely distant source, such as the sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction the light travels in.
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub cast_shadows: bool
+}
+
+/// Light shining from `position` in every direction, up to `range`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32
+}
+
+/// A cone of light from `position` along `direction`, with half-angles in
+/// radians.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub cast_shadows: bool
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        SpotLight {
+            position: Vec3::new(0.0, 3.0, 0.0),
+            direction: Vec3::NEG_Y,
+            color: Vec3::ONE,
+            intensity: 10.0,
+            range: 10.0,
+            inner_angle: 20f32.to_radians(),
+            outer_angle: 30f32.to_radians(),
+            cast_shadows: true
+        }
+    }
+}
+
+/// Every light of a scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lights {
+    pub ambient: Vec3,
+    pub directional: Option<DirectionalLight>,
+    pub points: Vec<PointLight>,
+    pub spots: Vec<SpotLight>
+}
+
+impl Default for Lights {
+    /// A low ambient term and a shadow-casting sun.
+    fn default() -> Self {
+        Lights {
+            ambient: Vec3::splat(0.03),
+            directional: Some(DirectionalLight {
+                direction: Vec3::new(-0.4, -1.0, -0.3).normalize(),
+                color: Vec3::ONE,
+                intensity: 3.0,
+                cast_shadows: true
+            }),
+            points: Vec::new(),
+            spots: Vec::new()
+        }
+    }
+}
+
+/// Kind of a [`GpuLight`], stored in `direction_type[3]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightType {
+    Directional = 0,
+    Point = 1,
+    Spot = 2
+}
+
+/// One entry of the light buffer read by `lit.frag`.
+#[derive(Clone, Copy, Debug, PartialEq, BufferContents)]
+#[repr(C)]
+pub struct GpuLight {
+    /// `w` is the range of point and spot lights.
+    pub position_range: [f32; 4],
+    /// `w` is the [`LightType`].
+    pub direction_type: [f32; 4],
+    /// Color scaled by intensity.
+    pub color: [f32; 4],
+    /// Cosines of the inner and outer spot angles, then the first shadow
+    /// view, or -1.
+    pub cone_shadow: [f32; 4]
+}
+
+impl GpuLight {
+    fn new(kind: LightType, position_range: Vec4, direction: Vec3, color: Vec3, cone: [f32; 2], shadow: Option<usize>) -> Self {
+        let shadow = shadow.map_or(-1.0, |view| view as f32);
+        GpuLight {
+            position_range: position_range.into(),
+            direction_type: direction.normalize_or(Vec3::NEG_Y).extend(kind as u32 as f32).into(),
+            color: color.extend(0.0).into(),
+            cone_shadow: [cone[0], cone[1], shadow, 0.0]
+        }
+    }
+}
+
+impl Lights {
+    /// The light buffer contents, directional light first.
+    pub fn gpu_lights(&self, plan: &ShadowPlan) -> Vec<GpuLight> {
+        let mut gpu_lights = Vec::with_capacity(1 + self.points.len() + self.spots.len());
+        if let Some(light) = &self.directional {
+            gpu_lights.push(GpuLight::new(
+                LightType::Directional,
+                Vec4::ZERO,
+                light.direction,
+                light.color * light.intensity,
+                [0.0; 2],
+                plan.directional
+            ));
+        }
+        for light in &self.points {
+            gpu_lights.push(GpuLight::new(
+                LightType::Point,
+                light.position.extend(light.range),
+                Vec3::NEG_Y,
+                light.color * light.intensity,
+                [0.0; 2],
+                None
+            ));
+        }
+        for (i, light) in self.spots.iter().enumerate() {
+            gpu_lights.push(GpuLight::new(
+                LightType::Spot,
+                light.position.extend(light.range),
+                light.direction,
+                light.color * light.intensity,
+                [light.inner_angle.cos(), light.outer_angle.cos()],
+                plan.spots.get(i).copied().flatten()
+            ));
+        }
+        gpu_lights
+    }
+}
+
+/// Camera and ambient light as read by `lit.vert` and `lit.frag`.
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+pub struct FrameUniforms {
+    pub view_projection: [[f32; 4]; 4],
+    pub camera_position: [f32; 4],
+    pub camera_forward: [f32; 4],
+    pub ambient: [f32; 4],
+    /// Intensity and highest prefiltered mip of the environment, then 1.0
+    /// in `z` when there is one.
+    pub environment: [f32; 4],
+    /// `x` is the number of lights in the light buffer.
+    pub counts: [i32; 4],
+    /// See [`Ssao::frame_params`].
+    pub ambient_occlusion: [f32; 4]
+}
+
+impl FrameUniforms {
+    pub fn new(
+        camera: &Camera,
+        extent: [u32; 2],
+        lights: &Lights,
+        light_count: usize,
+        environment: Option<&ImageBasedLighting>
+    ) -> Self {
+        let forward = (camera.target - camera.position).normalize();
+        let environment = environment.map_or([0.0; 4], |environment| {
+            [environment.intensity, environment.max_prefiltered_mip(), 1.0, 0.0]
+        });
+        FrameUniforms {
+            view_projection: camera.view_projection(extent).to_cols_array_2d(),
+            camera_position: camera.position.extend(1.0).into(),
+            camera_forward: forward.extend(0.0).into(),
+            ambient: lights.ambient.extend(0.0).into(),
+            environment,
+            counts: [light_count as i32, 0, 0, 0],
+            ambient_occlusion: [0.0, 1.0 / extent[0].max(1) as f32, 1.0 / extent[1].max(1) as f32, 0.0]
+        }
+    }
+}
+
+/// Draws [`MeshInstance`]s with their PBR [`Material`]s, every light of a
+/// [`Lights`], filtered shadows and optional image-based lighting.
+pub struct LitRenderer {
+    pub pipeline: Arc<GraphicsPipeline>,
+    pub material_defaults: MaterialDefaults,
+    material_sets: MaterialSetCache,
+    /// Replaces the ambient term of [`Lights`] when set.
+    pub environment: Option<ImageBasedLighting>,
+    /// Bound in place of a missing `environment`.
+    fallback_environment: ImageBasedLighting,
+    /// Darkens the ambient term when enabled.
+    pub ssao: Ssao
+}
+
+impl LitRenderer {
+    /// `subpass` is usually [`Renderer::main_subpass`], of a renderer on
+    /// `path`.
+    pub fn new(
+        allocator: &Allocator,
+        queue: &Arc<Queue>,
+        subpass: Subpass,
+        path: RenderPath,
+        ssao_config: SsaoConfig
+    ) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let pipeline = renderer::new_mesh_pipeline(device, subpass, "lit_vert.spv", Some("lit_frag.spv"), CullMode::Back, false);
+        LitRenderer {
+            pipeline,
+            material_defaults: MaterialDefaults::new(allocator, queue),
+            material_sets: MaterialSetCache::default(),
+            environment: None,
+            fallback_environment: ImageBasedLighting::black(allocator, queue),
+            ssao: Ssao::new(allocator, queue, ssao_config, path)
+        }
+    }
+    /// Records the meshes inside the forward subpass.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        extent: [u32; 2],
+        lights: &Lights,
+        shadows: &ShadowMaps,
+        plan: &ShadowPlan,
+        meshes: &[MeshInstance]
+    ) {
+        let (writes, _) = self.frame_writes(allocator, camera, extent, lights, shadows, plan);
+        let descriptor_set = PersistentDescriptorSet::new(
+            &allocator.descriptor_set_allocator,
+            self.pipeline.layout().set_layouts()[0].clone(),
+            writes,
+            []
+        ).expect("Fail to create lit descriptor set.");
+        self.record_meshes(builder, allocator, &self.pipeline, descriptor_set, meshes, "lit meshes");
+    }
+    /// Writes of the bindings of `lighting.glsl`, and the number of lights.
+    pub fn frame_writes(
+        &self,
+        allocator: &Allocator,
+        camera: &Camera,
+        extent: [u32; 2],
+        lights: &Lights,
+        shadows: &ShadowMaps,
+        plan: &ShadowPlan
+    ) -> (Vec<WriteDescriptorSet>, usize) {
+        let mut gpu_lights = lights.gpu_lights(plan);
+        let light_count = gpu_lights.len();
+        let mut uniforms = FrameUniforms::new(camera, extent, lights, light_count, self.environment.as_ref());
+        uniforms.ambient_occlusion = self.ssao.frame_params(extent);
+        if gpu_lights.is_empty() {
+            // Storage buffers cannot be empty.
+            gpu_lights.push(GpuLight::new(LightType::Point, Vec4::ZERO, Vec3::NEG_Y, Vec3::ZERO, [0.0; 2], None));
+        }
+        let mut writes = vec![
+            WriteDescriptorSet::buffer(0, allocator.alloc_uniform_buffer(uniforms)),
+            WriteDescriptorSet::buffer(1, allocator.alloc_uniform_buffer(shadows.uniforms(plan)))
+        ];
+        writes.extend(shadows.sampled(2));
+        writes.push(WriteDescriptorSet::buffer(4, allocator.alloc_storage_slice(&gpu_lights)));
+        writes.extend(self.environment.as_ref().unwrap_or(&self.fallback_environment).sampled(5));
+        let occlusion = if self.ssao.config.enabled { self.ssao.occlusion() } else { &self.material_defaults.white };
+        writes.push(WriteDescriptorSet::image_view(9, occlusion.clone()));
+        (writes, light_count)
+    }
+    /// Draws every mesh with `pipeline`, `frame_set` as set 0 and its
+    /// material as [`MATERIAL_SET`].
+    pub fn record_meshes(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        pipeline: &Arc<GraphicsPipeline>,
+        frame_set: Arc<PersistentDescriptorSet>,
+        meshes: &[MeshInstance],
+        label: &str
+    ) {
+        let layout = pipeline.layout().clone();
+        let material_layout = layout.set_layouts()[MATERIAL_SET as usize].clone();
+
+        debug::with_label(builder, label, LIT_LABEL_COLOR, |builder| {
+            builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .expect("Fail to bind lit pipeline.")
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, frame_set)
+            .expect("Fail to bind lit descriptor set.");
+            let mut bound_material: Option<&Arc<Material>> = None;
+            for instance in meshes {
+                if !bound_material.is_some_and(|material| Arc::ptr_eq(material, &instance.material)) {
+                    let material_set = self.material_sets.get(allocator, &instance.material, &material_layout, &self.material_defaults);
+                    builder
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), MATERIAL_SET, material_set)
+                    .expect("Fail to bind material descriptor set.");
+                    bound_material = Some(&instance.material);
+                }
+                builder
+                .push_constants(layout.clone(), 0, instance.transform.to_cols_array_2d())
+                .expect("Fail to push model matrix.");
+                let mesh = &instance.mesh;
+                Renderer::record_mesh(builder, mesh.vertex_buffer.clone(), mesh.index_buffer.clone(), mesh.index_count);
+            }
+        });
+    }
+}
+
+/// The meshes of a lit scene and everything drawing them.
+pub struct LitScene {
+    pub renderer: LitRenderer,
+    /// Set when the scene is drawn on the deferred path.
+    pub deferred: Option<DeferredRenderer>,
+    pub shadows: ShadowMaps,
+    pub debug_view: ShadowDebugView,
+    pub skybox: Skybox,
+    /// Cube drawn by `skybox` in place of the environment of the renderer.
+    pub sky: Option<Arc<ImageView>>,
+    /// Draws `sky`, or else the environment of the renderer.
+    pub show_skybox: bool,
+    pub meshes: Vec<MeshInstance>,
+    /// The meshes left by [`Self::cull`].
+    pub visible_meshes: Vec<MeshInstance>,
+    pub bounds_overlay: BoundsOverlay
+}
+
+impl LitScene {
+    /// Draws into the passes of `renderer`, following its path.
+    pub fn new(
+        allocator: &Allocator,
+        queue: &Arc<Queue>,
+        renderer: &Renderer,
+        shadow_config: ShadowConfig,
+        ssao_config: SsaoConfig,
+        meshes: Vec<MeshInstance>
+    ) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let subpass = renderer.main_subpass();
+        let deferred = renderer.deferred_subpasses().map(|(geometry, lighting)| {
+            DeferredRenderer::new(device.clone(), geometry, lighting)
+        });
+        LitScene {
+            renderer: LitRenderer::new(allocator, queue, subpass.clone(), renderer.path, ssao_config),
+            deferred,
+            shadows: ShadowMaps::new(allocator, shadow_config),
+            debug_view: ShadowDebugView::new(device.clone(), subpass.clone()),
+            bounds_overlay: BoundsOverlay::new(allocator, subpass.clone()),
+            skybox: Skybox::new(device, subpass),
+            sky: None,
+            show_skybox: true,
+            visible_meshes: meshes.clone(),
+            meshes
+        }
+    }
+    /// Keeps the meshes inside the view frustum of `camera` as
+    /// `visible_meshes`. Shadows still render every mesh.
+    pub fn cull(&mut self, camera: &Camera, extent: [u32; 2]) -> CullStats {
+        let frustum = Frustum::from_view_projection(camera.view_projection(extent));
+        let (visible_meshes, stats) = bounds::cull(&frustum, &self.meshes);
+        self.visible_meshes = visible_meshes;
+        stats
+    }
+    /// Draws the world bounding boxes of the visible meshes as wireframes.
+    pub fn record_bounds(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        extent: [u32; 2]
+    ) {
+        let boxes = self.visible_meshes.iter()
+            .map(|instance| (instance.world_bounds().aabb, [0.3, 1.0, 0.3, 1.0]));
+        self.bounds_overlay.record(builder, allocator, camera.view_projection(extent), boxes);
+    }
+    /// Renders the shadow maps of `lights` and returns the plan to draw
+    /// with.
+    pub fn record_shadows(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        lights: &Lights,
+        camera: &Camera,
+        extent: [u32; 2]
+    ) -> ShadowPlan {
+        let plan = self.shadows.config.plan(lights, camera, extent);
+        self.shadows.record(builder, &plan, &self.meshes);
+        plan
+    }
+    /// Sizes the ambient occlusion for `extent` and renders it on the
+    /// forward path. The deferred path renders it in
+    /// [`RenderStage::Occlusion`].
+    pub fn record_ssao(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        extent: [u32; 2]
+    ) {
+        let ssao = &mut self.renderer.ssao;
+        if !ssao.config.enabled {
+            return;
+        }
+        ssao.resize(allocator, extent);
+        if self.deferred.is_none() {
+            ssao.record(builder, allocator, camera, &self.visible_meshes);
+        }
+    }
+    /// Draws the part of the scene belonging to `stage` of the scene pass.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        stage: RenderStage,
+        camera: &Camera,
+        extent: [u32; 2],
+        lights: &Lights,
+        plan: &ShadowPlan
+    ) {
+        if let Some(deferred) = &self.deferred {
+            match stage {
+                RenderStage::Geometry => {
+                    deferred.record_geometry(builder, allocator, &self.renderer, camera, extent, lights, &self.visible_meshes);
+                    return;
+                }
+                RenderStage::Occlusion(target) => {
+                    if self.renderer.ssao.config.enabled {
+                        self.renderer.ssao.record_gbuffer(builder, allocator, camera, target);
+                    }
+                    return;
+                }
+                RenderStage::Lighting(target) => {
+                    deferred.record_lighting(builder, allocator, &self.renderer, target, camera, lights, &self.shadows, plan);
+                    return;
+                }
+                RenderStage::Forward => ()
+            }
+        } else if !matches!(stage, RenderStage::Forward) {
+            return;
+        }
+        let sky = match (&self.sky, &self.renderer.environment) {
+            (Some(sky), _) => Some((sky.clone(), 1.0)),
+            (None, Some(environment)) => Some((environment.environment.clone(), environment.intensity)),
+            (None, None) => None
+        };
+        let sky = sky.filter(|_| self.show_skybox);
+        let record_sky = |builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, (cube, intensity)| {
+            self.skybox.record(builder, allocator, camera, extent, cube, intensity);
+        };
+        if let Some(sky) = sky.clone().filter(|_| !self.skybox.depth_tested) {
+            record_sky(builder, sky);
+        }
+        if self.deferred.is_none() {
+            self.renderer.record_draw(builder, allocator, camera, extent, lights, &self.shadows, plan, &self.visible_meshes);
+        }
+        if let Some(sky) = sky.filter(|_| self.skybox.depth_tested) {
+            record_sky(builder, sky);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::PI, path::PathBuf};
+
+    use glam::{Mat4, Vec4Swizzles};
+    use half::f16;
+
+    use vulkano::{
+        device::Device,
+        command_buffer::{CommandBufferUsage, PrimaryCommandBufferAbstract},
+        sync::GpuFuture
+    };
+
+    use crate::{
+        capture,
+        test_support,
+        model::Mesh,
+        post_process::HDR_FORMAT,
+        render_target::{RenderTarget, DEFAULT_DEPTH_FORMAT},
+        texture
+    };
+
+    use super::*;
+
+    const GOLDEN_EXTENT: [u32; 2] = [256, 128];
+    const SPHERE_RADIUS: f32 = 0.4;
+
+    #[test]
+    fn light_buffer_references_shadow_views() {
+        let lights = Lights {
+            points: vec![PointLight { position: Vec3::ONE, color: Vec3::ONE, intensity: 2.0, range: 5.0 }],
+            spots: vec![SpotLight::default(); 2],
+            ..Default::default()
+        };
+        let plan = ShadowPlan {
+            directional: Some(0),
+            spots: vec![Some(4), None],
+            ..Default::default()
+        };
+        let gpu_lights = lights.gpu_lights(&plan);
+        assert_eq!(gpu_lights.len(), 4);
+        assert_eq!(gpu_lights[0].direction_type[3], LightType::Directional as u32 as f32);
+        assert_eq!(gpu_lights[0].color, [3.0, 3.0, 3.0, 0.0]);
+        assert_eq!(gpu_lights[0].cone_shadow[2], 0.0);
+        assert_eq!(gpu_lights[1].position_range, [1.0, 1.0, 1.0, 5.0]);
+        assert_eq!(gpu_lights[1].cone_shadow[2], -1.0);
+        assert_eq!(gpu_lights[2].direction_type, [0.0, -1.0, 0.0, LightType::Spot as u32 as f32]);
+        assert_eq!(gpu_lights[2].cone_shadow[2], 4.0);
+        assert_eq!(gpu_lights[3].cone_shadow[2], -1.0);
+
+        let uniforms = FrameUniforms::new(&Camera::default(), [800, 600], &lights, gpu_lights.len(), None);
+        assert_eq!(uniforms.counts[0], 4);
+        assert_eq!(uniforms.camera_forward, [0.0, 0.0, -1.0, 0.0]);
+    }
+
+    fn golden_path() -> PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/golden/pbr_spheres_reference.png")
+    }
+
+    fn reference_camera() -> Camera {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 6.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fov_y: 30f32.to_radians(),
+            near: 0.1,
+            far: 20.0
+        }
+    }
+
+    fn reference_lights() -> Lights {
+        let spot_position = Vec3::new(-2.5, 2.0, 3.0);
+        Lights {
+            ambient: Vec3::splat(0.03),
+            directional: Some(DirectionalLight {
+                direction: Vec3::new(-0.3, -0.5, -1.0).normalize(),
+                color: Vec3::ONE,
+                intensity: 2.0,
+                cast_shadows: false
+            }),
+            points: vec![PointLight {
+                position: Vec3::new(2.5, 2.0, 2.5),
+                color: Vec3::new(0.9, 0.8, 1.0),
+                intensity: 10.0,
+                range: 15.0
+            }],
+            spots: vec![SpotLight {
+                position: spot_position,
+                direction: (Vec3::new(-1.0, 0.0, 0.0) - spot_position).normalize(),
+                color: Vec3::new(1.0, 0.6, 0.3),
+                intensity: 15.0,
+                range: 15.0,
+                inner_angle: 10f32.to_radians(),
+                outer_angle: 20f32.to_radians(),
+                cast_shadows: false
+            }]
+        }
+    }
+
+    /// A dielectric row above a metallic row, roughness rising to the right.
+    fn reference_spheres() -> Vec<(Vec3, Material)> {
+        let mut spheres = Vec::new();
+        for (row, metallic) in [0.0, 1.0].into_iter().enumerate() {
+            for (column, roughness) in [0.2, 0.4, 0.6, 0.9].into_iter().enumerate() {
+                let center = Vec3::new(column as f32 - 1.5, 0.5 - row as f32, 0.0);
+                spheres.push((center, Material::new([0.9, 0.35, 0.2], metallic, roughness)));
+            }
+        }
+        spheres
+    }
+
+    /// `lit.frag` for a textureless material, without shadows.
+    fn shade(position: Vec3, n: Vec3, material: &Material, camera: &Camera, lights: &Lights) -> Vec3 {
+        let albedo = Vec4::from(material.base_color).xyz();
+        let metallic = material.metallic;
+        let roughness = material.roughness.clamp(0.045, 1.0);
+        let v = (camera.position - position).normalize();
+        let brdf = |l: Vec3| {
+            let h = (v + l).normalize();
+            let n_dot_l = n.dot(l).max(0.0);
+            let n_dot_v = n.dot(v).max(1e-4);
+            let n_dot_h = n.dot(h).max(0.0);
+            let v_dot_h = v.dot(h).max(0.0);
+            let alpha2 = (roughness * roughness).powi(2);
+            let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+            let distribution = alpha2 / (PI * d * d);
+            let view = n_dot_l * (n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2).sqrt();
+            let light = n_dot_v * (n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2).sqrt();
+            let visibility = 0.5 / (view + light).max(1e-5);
+            let f0 = Vec3::splat(0.04).lerp(albedo, metallic);
+            let fresnel = f0 + (Vec3::ONE - f0) * (1.0 - v_dot_h).powi(5);
+            let specular = distribution * visibility * fresnel;
+            let diffuse = (Vec3::ONE - fresnel) * (1.0 - metallic) * albedo / PI;
+            (diffuse + specular) * n_dot_l
+        };
+
+        let mut radiance = lights.ambient * albedo;
+        for light in lights.gpu_lights(&ShadowPlan::default()) {
+            let position_range = Vec4::from(light.position_range);
+            let direction_type = Vec4::from(light.direction_type);
+            let (l, attenuation) = if direction_type.w == LightType::Directional as u32 as f32 {
+                (-direction_type.xyz().normalize(), 1.0)
+            } else {
+                let to_light = position_range.xyz() - position;
+                let distance = to_light.length();
+                let l = to_light / distance;
+                let window = (1.0 - (distance / position_range.w).powi(4)).clamp(0.0, 1.0);
+                let mut attenuation = window * window / (distance * distance).max(1e-4);
+                if direction_type.w == LightType::Spot as u32 as f32 {
+                    let [inner, outer, ..] = light.cone_shadow;
+                    let t = ((-l.dot(direction_type.xyz().normalize()) - outer) / (inner - outer)).clamp(0.0, 1.0);
+                    attenuation *= t * t * (3.0 - 2.0 * t);
+                }
+                (l, attenuation)
+            };
+            radiance += brdf(l) * Vec4::from(light.color).xyz() * attenuation;
+        }
+        radiance
+    }
+
+    /// Ray casts the reference spheres into an RGBA8 image.
+    fn reference_image() -> Vec<u8> {
+        let camera = reference_camera();
+        let lights = reference_lights();
+        let spheres = reference_spheres();
+        let inverse = camera.view_projection(GOLDEN_EXTENT).inverse();
+        let unproject = |ndc: Vec4| {
+            let world = inverse * ndc;
+            world.xyz() / world.w
+        };
+        let [width, height] = GOLDEN_EXTENT;
+        let mut hdr = Vec::with_capacity((width * height * 8) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let ndc_x = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+                let ndc_y = (y as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+                let origin = unproject(Vec4::new(ndc_x, ndc_y, 0.0, 1.0));
+                let direction = (unproject(Vec4::new(ndc_x, ndc_y, 1.0, 1.0)) - origin).normalize();
+                let hit = spheres.iter()
+                    .filter_map(|(center, material)| {
+                        let offset = origin - *center;
+                        let b = offset.dot(direction);
+                        let discriminant = b * b - (offset.length_squared() - SPHERE_RADIUS * SPHERE_RADIUS);
+                        (discriminant >= 0.0).then(|| (-b - discriminant.sqrt(), *center, material))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+                let color = match hit {
+                    Some((distance, center, material)) => {
+                        let position = origin + direction * distance;
+                        shade(position, (position - center).normalize(), material, &camera, &lights).extend(1.0)
+                    }
+                    None => Vec4::new(0.0, 0.0, 0.0, 1.0)
+                };
+                hdr.extend(color.to_array().iter().flat_map(|channel| f16::from_f32(*channel).to_le_bytes()));
+            }
+        }
+        capture::to_rgba8(HDR_FORMAT, &hdr).unwrap()
+    }
+
+    /// Fraction of pixels where any channel differs by more than `tolerance`.
+    fn mismatch(actual: &[u8], expected: &[u8], tolerance: u8) -> f32 {
+        let differing = actual.chunks_exact(4)
+            .zip(expected.chunks_exact(4))
+            .filter(|(a, b)| a.iter().zip(b.iter()).any(|(a, b)| a.abs_diff(*b) > tolerance))
+            .count();
+        differing as f32 / (actual.len() / 4) as f32
+    }
+
+    /// A surface facing both the camera and a unit directional light, at
+    /// full roughness: D = 1/pi, V = 1/4 and F = F0.
+    #[test]
+    fn shading_matches_hand_computed_radiance() {
+        let camera = Camera { position: Vec3::Z, ..reference_camera() };
+        let lights = Lights {
+            ambient: Vec3::ZERO,
+            directional: Some(DirectionalLight {
+                direction: -Vec3::Z,
+                color: Vec3::ONE,
+                intensity: 1.0,
+                cast_shadows: false
+            }),
+            ..Default::default()
+        };
+        let radiance = |material: Material| shade(Vec3::ZERO, Vec3::Z, &material, &camera, &lights);
+        // 0.96 * 0.5 / pi diffuse plus 0.04 / (4 * pi) specular.
+        let dielectric = radiance(Material::new([0.5; 3], 0.0, 1.0));
+        assert!((dielectric - Vec3::splat(0.49 / PI)).abs().max_element() < 1e-5, "{dielectric}");
+        // No diffuse, and F0 = 1 leaves 1 / (4 * pi) specular.
+        let metal = radiance(Material::new([1.0; 3], 1.0, 1.0));
+        assert!((metal - Vec3::splat(0.25 / PI)).abs().max_element() < 1e-5, "{metal}");
+    }
+
+    /// The corners and the gap between the four centre spheres show the
+    /// black background.
+    #[test]
+    fn golden_image_background_is_black() {
+        let golden = texture::load_png(&golden_path()).unwrap();
+        assert_eq!(golden.extent, GOLDEN_EXTENT);
+        let [width, height] = GOLDEN_EXTENT;
+        for [x, y] in [[0, 0], [width - 1, height - 1], [width / 2, height / 2]] {
+            let offset = ((y * width + x) * 4) as usize;
+            assert_eq!(golden.rgba[offset..offset + 4], [0, 0, 0, 255], "pixel {x}, {y}");
+        }
+    }
+
+    /// Rewrites the golden image from the CPU reference.
+    #[test]
+    #[ignore = "rewrites assets/golden"]
+    fn update_golden_image() {
+        capture::write_png(&golden_path(), GOLDEN_EXTENT, &reference_image()).unwrap();
+    }
+
+    /// Renders the reference spheres into `target` and compares them with
+    /// the golden image.
+    fn assert_spheres_match_golden_image(device: Arc<Device>, queue: Arc<Queue>, allocator: &Allocator, target: RenderTarget) {
+        let renderer = Renderer::for_target(device, &target);
+        let sphere = Mesh::sphere(SPHERE_RADIUS, 64, 32, [1.0; 3]).upload(allocator);
+        let meshes = reference_spheres().into_iter()
+            .map(|(center, material)| MeshInstance {
+                mesh: sphere.clone(),
+                material: Arc::new(material),
+                transform: Mat4::from_translation(center)
+            })
+            .collect();
+        let shadow_config = ShadowConfig { enabled: false, ..Default::default() };
+        let ssao_config = SsaoConfig { enabled: false, ..Default::default() };
+        let scene = LitScene::new(allocator, &queue, &renderer, shadow_config, ssao_config, meshes);
+        let camera = reference_camera();
+        let lights = reference_lights();
+
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        let plan = scene.record_shadows(&mut builder, &lights, &camera, GOLDEN_EXTENT);
+        renderer.record_target_pass_stages(&mut builder, &target, |builder, stage| {
+            scene.record_draw(builder, allocator, stage, &camera, GOLDEN_EXTENT, &lights, &plan);
+        });
+        let readback = capture::record_readback(&mut builder, allocator, target.color.image().clone());
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        // Tessellation shifts silhouettes and highlights slightly.
+        let golden = texture::load_png(&golden_path()).unwrap();
+        let mismatch = mismatch(&readback.to_rgba8().unwrap(), &golden.rgba, 16);
+        assert!(mismatch < 0.02, "{:.2}% of pixels differ", mismatch * 100.0);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn spheres_match_golden_image() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let target = RenderTarget::new(&allocator, GOLDEN_EXTENT, HDR_FORMAT, Some(DEFAULT_DEPTH_FORMAT), "golden spheres");
+        assert_spheres_match_golden_image(device, queue, &allocator, target);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn deferred_spheres_match_golden_image() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let target = RenderTarget::new_deferred(&allocator, GOLDEN_EXTENT, HDR_FORMAT, "deferred golden spheres");
+        assert_spheres_match_golden_image(device, queue, &allocator, target);
+    }
+}
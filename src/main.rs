@@ -1,13 +1,8 @@
-mod debug;
-mod framework;
-mod model;
-mod allocator;
-mod renderer;
-mod app;
+use learn_vulkano::{app, debug};
 
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(debug::DEFAULT_LOG_FILTER)).init();
     let event_loop = winit::event_loop::EventLoop::new().unwrap();
     let mut app = app::OptionApp::default();
     event_loop.run_app(&mut app).unwrap();
-    return;
-}
\ No newline at end of file
+}
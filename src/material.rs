@@ -0,0 +1,236 @@
+use std::sync::{Arc, Mutex, Weak};
+
+use ahash::HashMap;
+
+use vulkano::{
+    buffer::BufferContents,
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet, layout::DescriptorSetLayout},
+    device::{DeviceOwned, Queue},
+    format::Format,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView
+    }
+};
+
+use crate::{
+    allocator::Allocator,
+    texture::{self, TextureData}
+};
+
+/// Descriptor set of the material in `lit.frag`.
+pub const MATERIAL_SET: u32 = 1;
+
+/// A metallic-roughness PBR material, as in glTF. Every factor multiplies
+/// the matching texture.
+#[derive(Clone, Debug)]
+pub struct Material {
+    /// Linear RGBA.
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB radiance added on top of the lighting.
+    pub emissive: [f32; 3],
+    /// Scales the X and Y of tangent-space normals.
+    pub normal_scale: f32,
+    /// How much of the occlusion texture applies, from 0.0 to 1.0.
+    pub occlusion_strength: f32,
+    /// sRGB color with linear alpha.
+    pub base_color_texture: Option<Arc<ImageView>>,
+    /// Tangent-space normals.
+    pub normal_texture: Option<Arc<ImageView>>,
+    /// Roughness in green and metallic in blue.
+    pub metallic_roughness_texture: Option<Arc<ImageView>>,
+    /// Ambient occlusion in red.
+    pub occlusion_texture: Option<Arc<ImageView>>,
+    /// sRGB color.
+    pub emissive_texture: Option<Arc<ImageView>>
+}
+
+impl Default for Material {
+    /// A white, fully rough dielectric.
+    fn default() -> Self {
+        Material {
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None
+        }
+    }
+}
+
+/// Material factors as read by `lit.frag`.
+#[derive(Clone, Copy, Debug, PartialEq, BufferContents)]
+#[repr(C)]
+pub struct MaterialUniforms {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 4],
+    /// Metallic, roughness, normal scale and occlusion strength.
+    pub params: [f32; 4]
+}
+
+impl Material {
+    /// A textureless material.
+    pub fn new(base_color: [f32; 3], metallic: f32, roughness: f32) -> Self {
+        Material {
+            base_color: [base_color[0], base_color[1], base_color[2], 1.0],
+            metallic,
+            roughness,
+            ..Default::default()
+        }
+    }
+    pub fn uniforms(&self) -> MaterialUniforms {
+        let normal_scale = if self.normal_texture.is_some() { self.normal_scale } else { 0.0 };
+        MaterialUniforms {
+            base_color: self.base_color,
+            emissive: [self.emissive[0], self.emissive[1], self.emissive[2], 0.0],
+            params: [self.metallic, self.roughness, normal_scale, self.occlusion_strength]
+        }
+    }
+    /// Allocates the descriptor set of the material.
+    pub fn descriptor_set(
+        &self,
+        allocator: &Allocator,
+        layout: Arc<DescriptorSetLayout>,
+        defaults: &MaterialDefaults
+    ) -> Arc<PersistentDescriptorSet> {
+        let slot = |texture: &Option<Arc<ImageView>>, fallback: &Arc<ImageView>| {
+            texture.clone().unwrap_or_else(|| fallback.clone())
+        };
+        let writes = [
+            WriteDescriptorSet::buffer(0, allocator.alloc_uniform_buffer(self.uniforms())),
+            WriteDescriptorSet::sampler(1, defaults.sampler.clone()),
+            WriteDescriptorSet::image_view(2, slot(&self.base_color_texture, &defaults.white)),
+            WriteDescriptorSet::image_view(3, slot(&self.normal_texture, &defaults.flat_normal)),
+            WriteDescriptorSet::image_view(4, slot(&self.metallic_roughness_texture, &defaults.white)),
+            WriteDescriptorSet::image_view(5, slot(&self.occlusion_texture, &defaults.white)),
+            WriteDescriptorSet::image_view(6, slot(&self.emissive_texture, &defaults.white))
+        ];
+        PersistentDescriptorSet::new(&allocator.descriptor_set_allocator, layout, writes, [])
+            .expect("Fail to create material descriptor set.")
+    }
+}
+
+/// A cached set and the material it was built for.
+type CachedSet = (Weak<Material>, Arc<PersistentDescriptorSet>);
+
+/// Material descriptor sets, built once per material and set layout.
+#[derive(Default)]
+pub struct MaterialSetCache {
+    /// Keyed by the addresses of the material and the layout.
+    sets: Mutex<HashMap<(usize, usize), CachedSet>>
+}
+
+impl MaterialSetCache {
+    pub fn get(
+        &self,
+        allocator: &Allocator,
+        material: &Arc<Material>,
+        layout: &Arc<DescriptorSetLayout>,
+        defaults: &MaterialDefaults
+    ) -> Arc<PersistentDescriptorSet> {
+        let key = (Arc::as_ptr(material) as usize, Arc::as_ptr(layout) as usize);
+        let mut sets = self.sets.lock().expect("Material set cache poisoned.");
+        if let Some((_, set)) = sets.get(&key).filter(|(cached, _)| cached.strong_count() > 0) {
+            return set.clone();
+        }
+        sets.retain(|_, (cached, _)| cached.strong_count() > 0);
+        let set = material.descriptor_set(allocator, layout.clone(), defaults);
+        sets.insert(key, (Arc::downgrade(material), set.clone()));
+        set
+    }
+    pub fn len(&self) -> usize {
+        self.sets.lock().expect("Material set cache poisoned.").len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The material sampler and the textures of empty slots.
+pub struct MaterialDefaults {
+    pub sampler: Arc<Sampler>,
+    pub white: Arc<ImageView>,
+    pub flat_normal: Arc<ImageView>
+}
+
+impl MaterialDefaults {
+    /// Blocks until the fallback textures are uploaded.
+    pub fn new(allocator: &Allocator, queue: &Arc<Queue>) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let sampler = Sampler::new(device, SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            address_mode: [SamplerAddressMode::Repeat; 3],
+            ..Default::default()
+        }).expect("Fail to create material sampler.");
+        let (white, flat_normal) = texture::upload_now(allocator, queue, |builder| {
+            let white = TextureData::solid([255; 4]);
+            let flat_normal = TextureData::solid([128, 128, 255, 255]);
+            (
+                texture::record_upload(builder, allocator, &white, Format::R8G8B8A8_UNORM, "white texture"),
+                texture::record_upload(builder, allocator, &flat_normal, Format::R8G8B8A8_UNORM, "flat normal texture")
+            )
+        });
+        MaterialDefaults { sampler, white, flat_normal }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use vulkano::{
+        descriptor_set::layout::{DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType},
+        shader::ShaderStages
+    };
+
+    use crate::test_support;
+
+    use super::*;
+
+    #[test]
+    fn normal_mapping_needs_a_texture() {
+        let material = Material { normal_scale: 0.5, ..Material::new([0.5; 3], 1.0, 0.25) };
+        assert_eq!(material.uniforms(), MaterialUniforms {
+            base_color: [0.5, 0.5, 0.5, 1.0],
+            emissive: [0.0; 4],
+            params: [1.0, 0.25, 0.0, 1.0]
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn material_sets_are_built_once_per_material() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let defaults = MaterialDefaults::new(&allocator, &queue);
+        let binding = |descriptor_type| DescriptorSetLayoutBinding {
+            stages: ShaderStages::FRAGMENT,
+            ..DescriptorSetLayoutBinding::descriptor_type(descriptor_type)
+        };
+        let mut bindings = BTreeMap::from([
+            (0, binding(DescriptorType::UniformBuffer)),
+            (1, binding(DescriptorType::Sampler))
+        ]);
+        bindings.extend((2..7).map(|index| (index, binding(DescriptorType::SampledImage))));
+        let layout = DescriptorSetLayout::new(device, DescriptorSetLayoutCreateInfo { bindings, ..Default::default() })
+            .unwrap();
+
+        let cache = MaterialSetCache::default();
+        let material = Arc::new(Material::default());
+        let set = cache.get(&allocator, &material, &layout, &defaults);
+        assert!(Arc::ptr_eq(&set, &cache.get(&allocator, &material, &layout, &defaults)));
+        drop(material);
+        let other = Arc::new(Material::new([0.5; 3], 0.0, 0.5));
+        cache.get(&allocator, &other, &layout, &defaults);
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -1,8 +1,18 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+
 use vulkano::{
-    buffer::BufferContents,
+    buffer::{BufferContents, Subbuffer},
     pipeline::graphics::vertex_input::Vertex
 };
 
+use crate::{
+    allocator::Allocator,
+    bounds::Bounds,
+    material::Material
+};
+
 #[derive(Clone)]
 #[derive(BufferContents, Vertex)]
 #[repr(C)]
@@ -13,7 +23,218 @@ pub struct ColoredVertex {
     pub color: [f32; 3]
 }
 impl ColoredVertex {
+    /// The member shaders read at each input location.
+    pub const ATTRIBUTES: [&'static str; 2] = ["position", "color"];
     pub fn new(position: [f32; 3], color: [f32; 3]) -> Self {
         ColoredVertex { position, color }
     }
-}
\ No newline at end of file
+}
+
+/// Per-instance data of instanced [`ColoredVertex`] meshes, read from a
+/// second vertex buffer advancing once per instance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+pub struct ColoredInstance {
+    /// Model matrix, one input location per column.
+    #[format(R32G32B32A32_SFLOAT)]
+    pub transform: [[f32; 4]; 4],
+    /// Multiplies the vertex color.
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+    /// Free for shaders; `instanced.vert` offsets the world position by
+    /// `xyz`.
+    #[format(R32G32B32A32_SFLOAT)]
+    pub custom: [f32; 4]
+}
+impl ColoredInstance {
+    /// The member shaders read at each input location after the
+    /// [`ColoredVertex`] ones; `transform` spans four locations.
+    pub const ATTRIBUTES: [&'static str; 3] = ["transform", "color", "custom"];
+    pub fn new(transform: Mat4, color: [f32; 4]) -> Self {
+        ColoredInstance { transform: transform.to_cols_array_2d(), color, custom: [0.0; 4] }
+    }
+}
+
+/// Vertex of lit meshes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+pub struct MeshVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+    /// `w` is the handedness of the bitangent, `cross(normal, tangent) * w`.
+    #[format(R32G32B32A32_SFLOAT)]
+    pub tangent: [f32; 4],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+    #[format(R32G32B32_SFLOAT)]
+    pub color: [f32; 3]
+}
+impl MeshVertex {
+    /// The member shaders read at each input location.
+    pub const ATTRIBUTES: [&'static str; 5] = ["position", "normal", "tangent", "uv", "color"];
+    pub fn new(position: [f32; 3], normal: [f32; 3], tangent: [f32; 4], uv: [f32; 2], color: [f32; 3]) -> Self {
+        MeshVertex { position, normal, tangent, uv, color }
+    }
+}
+
+/// Indexed triangle list on the host. Triangles wind counter-clockwise
+/// when seen from outside.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>
+}
+
+impl Mesh {
+    /// Appends a quad from its four corners in counter-clockwise order,
+    /// starting at the bottom-left one. `tangent` points along +U.
+    fn push_quad(&mut self, corners: [Vec3; 4], normal: Vec3, tangent: Vec3, color: [f32; 3]) {
+        let first = self.vertices.len() as u32;
+        let uvs = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+        let vertices = corners.iter()
+            .zip(uvs)
+            .map(|(corner, uv)| MeshVertex::new((*corner).into(), normal.into(), tangent.extend(1.0).into(), uv, color));
+        self.vertices.extend(vertices);
+        self.indices.extend([0, 1, 2, 2, 3, 0].map(|index| first + index));
+    }
+    /// A square in the XZ plane facing +Y.
+    pub fn plane(half_size: f32, color: [f32; 3]) -> Self {
+        let mut mesh = Mesh::default();
+        let corners = [
+            Vec3::new(-half_size, 0.0, half_size),
+            Vec3::new(half_size, 0.0, half_size),
+            Vec3::new(half_size, 0.0, -half_size),
+            Vec3::new(-half_size, 0.0, -half_size)
+        ];
+        mesh.push_quad(corners, Vec3::Y, Vec3::X, color);
+        mesh
+    }
+    /// An axis-aligned cube centered on the origin.
+    pub fn cube(half_extent: f32, color: [f32; 3]) -> Self {
+        let mut mesh = Mesh::default();
+        for normal in [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z] {
+            let tangent = if normal.y.abs() > 0.5 { Vec3::X } else { Vec3::Y.cross(normal) };
+            let bitangent = normal.cross(tangent);
+            let center = normal * half_extent;
+            let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .map(|(u, v)| center + (tangent * u + bitangent * v) * half_extent);
+            mesh.push_quad(corners, normal, tangent, color);
+        }
+        mesh
+    }
+    /// A UV sphere centered on the origin with `segments` slices around
+    /// the Y axis and `rings` stacks from pole to pole.
+    pub fn sphere(radius: f32, segments: u32, rings: u32, color: [f32; 3]) -> Self {
+        let segments = segments.max(3);
+        let rings = rings.max(2);
+        let mut mesh = Mesh::default();
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let theta = v * std::f32::consts::PI;
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let phi = u * std::f32::consts::TAU;
+                let normal = Vec3::new(theta.sin() * phi.sin(), theta.cos(), theta.sin() * phi.cos());
+                let tangent = Vec3::new(phi.cos(), 0.0, -phi.sin());
+                let vertex = MeshVertex::new((normal * radius).into(), normal.into(), tangent.extend(1.0).into(), [u, v], color);
+                mesh.vertices.push(vertex);
+            }
+        }
+        let stride = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let top = ring * stride + segment;
+                let bottom = top + stride;
+                mesh.indices.extend([top, bottom, bottom + 1, top, bottom + 1, top + 1]);
+            }
+        }
+        mesh
+    }
+    pub fn bounds(&self) -> Bounds {
+        Bounds::from_points(self.vertices.iter().map(|vertex| Vec3::from(vertex.position)))
+    }
+    pub fn upload(&self, allocator: &Allocator) -> GpuMesh {
+        GpuMesh {
+            vertex_buffer: allocator.alloc_vertex_buffer(&self.vertices),
+            index_buffer: allocator.alloc_index_buffer(&self.indices),
+            index_count: self.indices.len() as u32,
+            bounds: self.bounds()
+        }
+    }
+}
+
+/// A mesh uploaded to device memory. Cloning shares the buffers.
+#[derive(Clone)]
+pub struct GpuMesh {
+    pub vertex_buffer: Subbuffer<[MeshVertex]>,
+    pub index_buffer: Subbuffer<[u32]>,
+    pub index_count: u32,
+    /// Computed from the vertices on upload, in model space.
+    pub bounds: Bounds
+}
+
+/// A mesh placed in the world and the material it is shaded with.
+#[derive(Clone)]
+pub struct MeshInstance {
+    pub mesh: GpuMesh,
+    pub material: Arc<Material>,
+    pub transform: Mat4
+}
+
+impl MeshInstance {
+    pub fn world_bounds(&self) -> Bounds {
+        self.mesh.bounds.transformed(&self.transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn face_normal(mesh: &Mesh, triangle: usize) -> Vec3 {
+        let corner = |i: usize| Vec3::from(mesh.vertices[mesh.indices[triangle * 3 + i] as usize].position);
+        (corner(1) - corner(0)).cross(corner(2) - corner(0)).normalize()
+    }
+
+    #[test]
+    fn cube_winds_counter_clockwise_from_outside() {
+        let cube = Mesh::cube(0.5, [1.0; 3]);
+        assert_eq!(cube.vertices.len(), 24);
+        assert_eq!(cube.indices.len(), 36);
+        for triangle in 0..12 {
+            let vertex = cube.vertices[cube.indices[triangle * 3] as usize];
+            assert!((face_normal(&cube, triangle) - Vec3::from(vertex.normal)).length() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sphere_faces_outwards_with_orthogonal_tangents() {
+        let sphere = Mesh::sphere(2.0, 16, 8, [1.0; 3]);
+        assert_eq!(sphere.vertices.len(), 17 * 9);
+        for triangle in 0..sphere.indices.len() / 3 {
+            let corners: Vec<Vec3> = (0..3)
+                .map(|i| Vec3::from(sphere.vertices[sphere.indices[triangle * 3 + i] as usize].position))
+                .collect();
+            // Triangles touching a pole have two coincident corners.
+            let face = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            if face.length() > 1e-6 {
+                assert!(face.dot(corners[0] + corners[1] + corners[2]) > 0.0, "triangle {triangle} faces inwards");
+            }
+        }
+        for vertex in &sphere.vertices {
+            assert!((Vec3::from(vertex.position).length() - 2.0).abs() < 1e-5);
+            assert!(Vec3::from(vertex.normal).dot(Vec3::from_slice(&vertex.tangent[..3])).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn plane_faces_up() {
+        let plane = Mesh::plane(2.0, [1.0; 3]);
+        assert!((face_normal(&plane, 0) - Vec3::Y).length() < 1e-6);
+        assert!((face_normal(&plane, 1) - Vec3::Y).length() < 1e-6);
+    }
+}
@@ -0,0 +1,647 @@
+use std::{
+    fs,
+    sync::Arc
+};
+
+use ahash::HashSet;
+
+use serde::Deserialize;
+
+use vulkano::{
+    device::Device,
+    buffer::{BufferContents, BufferUsage, Subbuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    render_pass::Subpass,
+    pipeline::{
+        Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo, DynamicState,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        graphics::{
+            GraphicsPipeline, GraphicsPipelineCreateInfo,
+            vertex_input::VertexInputState,
+            input_assembly::InputAssemblyState,
+            viewport::ViewportState,
+            rasterization::{RasterizationState, CullMode},
+            multisample::MultisampleState,
+            color_blend::{ColorBlendState, ColorBlendAttachmentState, AttachmentBlend, BlendFactor},
+            subpass::PipelineSubpassType
+        }
+    }
+};
+
+use smallvec::SmallVec;
+
+use crate::{
+    debug,
+    shader,
+    allocator::Allocator,
+    camera::Camera,
+    renderer,
+    compute::{self, ComputePass}
+};
+
+const LOCAL_SIZE: u32 = 64;
+const VERTICES_PER_PARTICLE: u32 = 6;
+const DRAW_LABEL_COLOR: [f32; 4] = [1.0, 0.7, 0.3, 1.0];
+
+/// Samples per baked curve, as in `particles.vert`.
+pub const CURVE_SAMPLES: usize = 16;
+
+/// How particles are composited onto the frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum BlendMode {
+    /// Colors add up, e.g. for fire and sparks.
+    #[default]
+    Additive,
+    /// Alpha blending, without sorting.
+    Alpha
+}
+
+/// A value that can be interpolated between two curve keyframes.
+pub trait Keyframe: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Keyframe for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Keyframe for [f32; 4] {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        std::array::from_fn(|i| self[i].lerp(other[i], t))
+    }
+}
+
+/// Evaluates a piecewise linear curve of `(time, value)` keyframes sorted
+/// by time.
+pub fn sample_curve<T: Keyframe>(keys: &[(f32, T)], time: f32, fallback: T) -> T {
+    let Some(&(first_time, first_value)) = keys.first() else {
+        return fallback;
+    };
+    if time <= first_time {
+        return first_value;
+    }
+    for window in keys.windows(2) {
+        let ((start, from), (end, to)) = (window[0], window[1]);
+        if time <= end {
+            let span = end - start;
+            let t = if span > 0.0 { (time - start) / span } else { 1.0 };
+            return from.lerp(to, t);
+        }
+    }
+    keys[keys.len() - 1].1
+}
+
+/// Samples a curve at [`CURVE_SAMPLES`] points of the normalized age.
+pub fn bake_curve<T: Keyframe>(keys: &[(f32, T)], fallback: T) -> [T; CURVE_SAMPLES] {
+    std::array::from_fn(|i| {
+        let time = i as f32 / (CURVE_SAMPLES - 1) as f32;
+        sample_curve(keys, time, fallback)
+    })
+}
+
+/// Emitter settings, loaded from a RON file such as
+/// `assets/particles/fountain.ron`. Curves are keyed on normalized age.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct EmitterConfig {
+    /// Particles spawned per second.
+    pub spawn_rate: f32,
+    /// Seconds a particle lives.
+    pub lifetime: f32,
+    /// Capacity of the particle buffers.
+    pub max_particles: u32,
+    pub origin: [f32; 3],
+    pub velocity: [f32; 3],
+    /// Random offset of each velocity component.
+    pub velocity_spread: [f32; 3],
+    pub gravity: [f32; 3],
+    pub color: Vec<(f32, [f32; 4])>,
+    pub size: Vec<(f32, f32)>,
+    pub blend: BlendMode,
+    pub seed: u32
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        EmitterConfig {
+            spawn_rate: 200.0,
+            lifetime: 2.0,
+            max_particles: 4096,
+            origin: [0.0; 3],
+            velocity: [0.0, 1.5, 0.0],
+            velocity_spread: [0.5, 0.3, 0.5],
+            gravity: [0.0, -1.0, 0.0],
+            color: vec![(0.0, [1.0, 1.0, 1.0, 1.0]), (1.0, [1.0, 1.0, 1.0, 0.0])],
+            size: vec![(0.0, 0.05), (1.0, 0.0)],
+            blend: BlendMode::Additive,
+            seed: 1
+        }
+    }
+}
+
+impl EmitterConfig {
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(text)
+    }
+    /// Reads an emitter file, or logs why it could not.
+    pub fn load(path: &str) -> Option<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => {
+                log::warn!("Fail to read particle emitter {path}: {error}.");
+                return None;
+            }
+        };
+        match Self::from_ron(&text) {
+            Ok(config) => Some(config),
+            Err(error) => {
+                log::warn!("Fail to parse particle emitter {path}: {error}.");
+                None
+            }
+        }
+    }
+}
+
+/// One particle of the simulation buffers, dead once its age reaches its
+/// lifetime.
+#[derive(Clone, Copy, Debug, Default, PartialEq, BufferContents)]
+#[repr(C)]
+pub struct GpuParticle {
+    /// Position in `xyz`, seconds since spawn in `w`.
+    pub position_age: [f32; 4],
+    /// Velocity in `xyz`, lifetime in seconds in `w`.
+    pub velocity_lifetime: [f32; 4]
+}
+
+impl GpuParticle {
+    pub fn is_alive(&self) -> bool {
+        self.position_age[3] < self.velocity_lifetime[3]
+    }
+}
+
+/// Push constants of `particles_simulate.comp`.
+#[derive(Clone, Copy, Debug, PartialEq, BufferContents)]
+#[repr(C)]
+pub struct SimulationStep {
+    pub origin: [f32; 4],
+    pub velocity: [f32; 4],
+    pub velocity_spread: [f32; 4],
+    pub gravity: [f32; 4],
+    pub delta_time: f32,
+    pub lifetime: f32,
+    /// First buffer slot respawned this step.
+    pub spawn_start: u32,
+    pub spawn_count: u32,
+    /// Serial number of the first spawned particle.
+    pub spawn_serial: u32,
+    pub max_particles: u32,
+    pub seed: u32
+}
+
+/// Turns elapsed time into spawn requests and buffer slots.
+#[derive(Clone, Debug)]
+pub struct ParticleEmitter {
+    pub config: EmitterConfig,
+    spawn_accumulator: f32,
+    spawn_cursor: u32,
+    spawn_serial: u32
+}
+
+impl ParticleEmitter {
+    pub fn new(config: EmitterConfig) -> Self {
+        ParticleEmitter {
+            config,
+            spawn_accumulator: 0.0,
+            spawn_cursor: 0,
+            spawn_serial: 0
+        }
+    }
+    fn extend(vector: [f32; 3]) -> [f32; 4] {
+        [vector[0], vector[1], vector[2], 0.0]
+    }
+    /// Advances the emitter by `delta_time` seconds.
+    pub fn step(&mut self, delta_time: f32) -> SimulationStep {
+        let config = &self.config;
+        let max_particles = config.max_particles.max(1);
+        self.spawn_accumulator += config.spawn_rate * delta_time;
+        let requested = self.spawn_accumulator.floor();
+        self.spawn_accumulator -= requested;
+        let spawn_count = (requested as u32).min(max_particles);
+
+        let step = SimulationStep {
+            origin: Self::extend(config.origin),
+            velocity: Self::extend(config.velocity),
+            velocity_spread: Self::extend(config.velocity_spread),
+            gravity: Self::extend(config.gravity),
+            delta_time,
+            lifetime: config.lifetime,
+            spawn_start: self.spawn_cursor,
+            spawn_count,
+            spawn_serial: self.spawn_serial,
+            max_particles,
+            seed: config.seed
+        };
+        self.spawn_cursor = (self.spawn_cursor + spawn_count) % max_particles;
+        self.spawn_serial = self.spawn_serial.wrapping_add(spawn_count);
+        step
+    }
+}
+
+fn pcg_hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// Same generator as `random` in `particles_simulate.comp`.
+fn random(seed: u32, serial: u32, channel: u32) -> f32 {
+    let bits = pcg_hash(seed ^ pcg_hash(serial.wrapping_mul(4).wrapping_add(channel)));
+    (bits >> 8) as f32 / 16777216.0
+}
+
+/// CPU version of `particles_simulate.comp`.
+#[derive(Clone, Debug)]
+pub struct CpuParticleSimulation {
+    pub particles: Vec<GpuParticle>
+}
+
+impl CpuParticleSimulation {
+    pub fn new(max_particles: u32) -> Self {
+        CpuParticleSimulation {
+            particles: vec![GpuParticle::default(); max_particles as usize]
+        }
+    }
+    pub fn step(&mut self, step: &SimulationStep) {
+        let max_particles = step.max_particles;
+        for (index, particle) in self.particles.iter_mut().enumerate().take(max_particles as usize) {
+            let offset = (index as u32 + max_particles - step.spawn_start) % max_particles;
+            if offset < step.spawn_count {
+                let serial = step.spawn_serial.wrapping_add(offset);
+                let velocity = |axis: usize| {
+                    let jitter = random(step.seed, serial, axis as u32) * 2.0 - 1.0;
+                    step.velocity[axis] + step.velocity_spread[axis] * jitter
+                };
+                particle.position_age = [step.origin[0], step.origin[1], step.origin[2], 0.0];
+                particle.velocity_lifetime = [velocity(0), velocity(1), velocity(2), step.lifetime];
+            }
+            else if particle.is_alive() {
+                particle.position_age[3] += step.delta_time;
+                for axis in 0..3 {
+                    let velocity = particle.velocity_lifetime[axis] + step.gravity[axis] * step.delta_time;
+                    particle.position_age[axis] += velocity * step.delta_time;
+                    particle.velocity_lifetime[axis] = velocity;
+                }
+            }
+        }
+    }
+    pub fn alive_count(&self) -> usize {
+        self.particles.iter().filter(|particle| particle.is_alive()).count()
+    }
+}
+
+/// Runs `particles_simulate.comp` over a pair of ping-pong storage buffers.
+pub struct GpuParticleSimulation {
+    pub pass: ComputePass,
+    pub buffers: [Subbuffer<[GpuParticle]>; 2],
+    descriptor_sets: [Arc<PersistentDescriptorSet>; 2],
+    current: usize
+}
+
+impl GpuParticleSimulation {
+    pub fn new(device: Arc<Device>, allocator: &Allocator, max_particles: u32) -> Self {
+        let pass = ComputePass::new(device, "particles_simulate_comp.spv", "particle simulation");
+        let particles = vec![GpuParticle::default(); max_particles.max(1) as usize];
+        let buffers = ["particle buffer A", "particle buffer B"].map(|name| {
+            allocator.alloc_storage_buffer(&particles, BufferUsage::empty(), name)
+        });
+        let descriptor_sets = [(0, 1), (1, 0)].map(|(source, destination)| {
+            pass.descriptor_set(allocator, 0, [
+                WriteDescriptorSet::buffer(0, buffers[source].clone()),
+                WriteDescriptorSet::buffer(1, buffers[destination].clone())
+            ])
+        });
+        GpuParticleSimulation {
+            pass,
+            buffers,
+            descriptor_sets,
+            current: 0
+        }
+    }
+    /// The buffer holding the result of the latest recorded step.
+    pub fn current_buffer(&self) -> Subbuffer<[GpuParticle]> {
+        self.buffers[self.current].clone()
+    }
+    pub fn record_step(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        step: SimulationStep
+    ) {
+        let group_count = compute::group_count(step.max_particles, LOCAL_SIZE);
+        self.pass.record_dispatch(builder, self.descriptor_sets[self.current].clone(), step, [group_count, 1, 1]);
+        self.current = 1 - self.current;
+    }
+}
+
+/// Layout of the `ParticleUniforms` block in `particles.vert`.
+#[derive(Clone, Copy, BufferContents)]
+#[repr(C)]
+pub struct ParticleUniforms {
+    pub view_projection: [[f32; 4]; 4],
+    pub camera_right: [f32; 4],
+    pub camera_up: [f32; 4],
+    pub color_curve: [[f32; 4]; CURVE_SAMPLES],
+    pub size_curve: [[f32; 4]; CURVE_SAMPLES / 4]
+}
+
+/// Draws particles as camera-facing billboards.
+pub struct ParticleRenderer {
+    pub pipeline: Arc<GraphicsPipeline>,
+    pub color_curve: [[f32; 4]; CURVE_SAMPLES],
+    pub size_curve: [f32; CURVE_SAMPLES]
+}
+
+impl ParticleRenderer {
+    fn attachment_blend(blend: BlendMode) -> AttachmentBlend {
+        match blend {
+            BlendMode::Additive => AttachmentBlend {
+                src_color_blend_factor: BlendFactor::SrcAlpha,
+                ..AttachmentBlend::additive()
+            },
+            BlendMode::Alpha => AttachmentBlend::alpha()
+        }
+    }
+    fn new_pipeline(device: Arc<Device>, subpass: Subpass, blend: BlendMode) -> Arc<GraphicsPipeline> {
+        let vertex_shader = shader::load_shader(device.clone(), "particles_vert.spv");
+        let fragment_shader = shader::load_shader(device.clone(), "particles_frag.spv");
+        let stages: SmallVec<[PipelineShaderStageCreateInfo; 5]> = SmallVec::from_vec(vec![
+            PipelineShaderStageCreateInfo::new(vertex_shader.entry_point("main").expect("Fail to find entry point")),
+            PipelineShaderStageCreateInfo::new(fragment_shader.entry_point("main").expect("Fail to find entry point"))
+        ]);
+
+        let pipeline_layout = {
+            let create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .expect("Fail to reflect particle pipeline layout.");
+            PipelineLayout::new(device.clone(), create_info).expect("Fail to create particle pipeline layout.")
+        };
+        debug::set_object_name(&*pipeline_layout, "particle pipeline layout");
+
+        let color_blend_state = ColorBlendState {
+            attachments: vec![
+                ColorBlendAttachmentState {
+                    blend: Some(Self::attachment_blend(blend)),
+                    ..Default::default()
+                }
+            ],
+            ..Default::default()
+        };
+        let mut dynamic_state = HashSet::default();
+        dynamic_state.insert(DynamicState::Viewport);
+
+        let create_info = GraphicsPipelineCreateInfo {
+            stages,
+            vertex_input_state: Some(VertexInputState::new()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState {
+                cull_mode: CullMode::None,
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState::default()),
+            depth_stencil_state: renderer::depth_stencil_state(&subpass, false),
+            color_blend_state: Some(color_blend_state),
+            dynamic_state,
+            subpass: Some(PipelineSubpassType::BeginRenderPass(subpass)),
+            ..GraphicsPipelineCreateInfo::layout(pipeline_layout)
+        };
+        let pipeline = GraphicsPipeline::new(device, None, create_info)
+            .expect("Fail to create particle pipeline.");
+        debug::set_object_name(&*pipeline, "particle pipeline");
+        pipeline
+    }
+    pub fn new(device: Arc<Device>, subpass: Subpass, config: &EmitterConfig) -> Self {
+        ParticleRenderer {
+            pipeline: Self::new_pipeline(device, subpass, config.blend),
+            color_curve: bake_curve(&config.color, [1.0; 4]),
+            size_curve: bake_curve(&config.size, 0.0)
+        }
+    }
+    fn uniforms(&self, camera: &Camera, extent: [u32; 2]) -> ParticleUniforms {
+        let (right, up) = camera.billboard_axes();
+        let mut size_curve = [[0.0; 4]; CURVE_SAMPLES / 4];
+        for (i, size) in self.size_curve.iter().enumerate() {
+            size_curve[i / 4][i % 4] = *size;
+        }
+        ParticleUniforms {
+            view_projection: camera.view_projection(extent).to_cols_array_2d(),
+            camera_right: right.extend(0.0).to_array(),
+            camera_up: up.extend(0.0).to_array(),
+            color_curve: self.color_curve,
+            size_curve
+        }
+    }
+    /// Records the billboard draw inside the subpass of the renderer.
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        particles: Subbuffer<[GpuParticle]>,
+        camera: &Camera,
+        extent: [u32; 2]
+    ) {
+        let vertex_count = particles.len() as u32 * VERTICES_PER_PARTICLE;
+        let uniform_buffer = allocator.alloc_uniform_buffer(self.uniforms(camera, extent));
+        let layout = self.pipeline.layout().set_layouts()[0].clone();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &allocator.descriptor_set_allocator,
+            layout,
+            [
+                WriteDescriptorSet::buffer(0, uniform_buffer),
+                WriteDescriptorSet::buffer(1, particles)
+            ],
+            []
+        ).expect("Fail to create particle descriptor set.");
+
+        debug::with_label(builder, "draw particles", DRAW_LABEL_COLOR, |builder| {
+            builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .expect("Fail to bind particle pipeline.")
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, descriptor_set)
+            .expect("Fail to bind particle descriptor set.")
+            .draw(vertex_count, 1, 0, 0)
+            .expect("Fail to draw particles.");
+        });
+    }
+}
+
+/// An emitter together with its GPU simulation and renderer.
+pub struct ParticleSystem {
+    pub emitter: ParticleEmitter,
+    pub simulation: GpuParticleSimulation,
+    pub renderer: ParticleRenderer
+}
+
+impl ParticleSystem {
+    pub fn new(device: Arc<Device>, allocator: &Allocator, subpass: Subpass, config: EmitterConfig) -> Self {
+        let simulation = GpuParticleSimulation::new(device.clone(), allocator, config.max_particles);
+        let renderer = ParticleRenderer::new(device, subpass, &config);
+        ParticleSystem {
+            emitter: ParticleEmitter::new(config),
+            simulation,
+            renderer
+        }
+    }
+    /// Records one simulation step.
+    pub fn record_simulation(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        delta_time: f32
+    ) {
+        let step = self.emitter.step(delta_time);
+        self.simulation.record_step(builder, step);
+    }
+    pub fn record_draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        extent: [u32; 2]
+    ) {
+        self.renderer.record_draw(builder, allocator, self.simulation.current_buffer(), camera, extent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{
+        command_buffer::{CommandBufferUsage, PrimaryCommandBufferAbstract},
+        sync::GpuFuture
+    };
+
+    use crate::test_support;
+
+    use super::*;
+
+    fn test_config() -> EmitterConfig {
+        EmitterConfig {
+            spawn_rate: 64.0,
+            lifetime: 0.5,
+            max_particles: 64,
+            ..EmitterConfig::default()
+        }
+    }
+
+    #[test]
+    fn emitter_spawns_at_rate_and_carries_remainder() {
+        let mut emitter = ParticleEmitter::new(test_config());
+        let first = emitter.step(0.0234375);
+        let second = emitter.step(0.0234375);
+        assert_eq!((first.spawn_count, first.spawn_start), (1, 0));
+        assert_eq!((second.spawn_count, second.spawn_start, second.spawn_serial), (2, 1, 1));
+    }
+
+    #[test]
+    fn emitter_wraps_around_the_buffer() {
+        let mut emitter = ParticleEmitter::new(test_config());
+        emitter.step(0.9375);
+        let step = emitter.step(0.15625);
+        assert_eq!((step.spawn_start, step.spawn_count), (60, 10));
+        assert_eq!(emitter.step(0.0).spawn_start, 6);
+    }
+
+    #[test]
+    fn cpu_simulation_is_deterministic_and_expires_particles() {
+        let mut emitter = ParticleEmitter::new(test_config());
+        let mut first = CpuParticleSimulation::new(64);
+        let mut second = CpuParticleSimulation::new(64);
+        for _ in 0..10 {
+            let step = emitter.step(0.03125);
+            first.step(&step);
+            second.step(&step);
+        }
+        assert_eq!(first.particles, second.particles);
+        assert_eq!(first.alive_count(), 20);
+
+        let idle = SimulationStep { delta_time: 0.03125, spawn_count: 0, ..emitter.step(0.0) };
+        for _ in 0..30 {
+            first.step(&idle);
+        }
+        assert_eq!(first.alive_count(), 0);
+    }
+
+    #[test]
+    fn spawned_velocity_stays_within_spread() {
+        let config = test_config();
+        let mut simulation = CpuParticleSimulation::new(64);
+        simulation.step(&ParticleEmitter::new(config.clone()).step(0.5));
+        for particle in simulation.particles.iter().filter(|particle| particle.is_alive()) {
+            for axis in 0..3 {
+                let deviation = (particle.velocity_lifetime[axis] - config.velocity[axis]).abs();
+                assert!(deviation <= config.velocity_spread[axis]);
+            }
+        }
+    }
+
+    #[test]
+    fn curves_bake_linearly_and_clamp() {
+        let baked = bake_curve(&[(0.2, 1.0), (0.8, 4.0)], 0.0);
+        assert_eq!(baked[0], 1.0);
+        assert_eq!(baked[CURVE_SAMPLES - 1], 4.0);
+        assert!((sample_curve(&[(0.2, 1.0), (0.8, 4.0)], 0.5, 0.0) - 2.5).abs() < 1e-6);
+        assert_eq!(bake_curve::<f32>(&[], 3.0), [3.0; CURVE_SAMPLES]);
+    }
+
+    #[test]
+    fn emitter_config_parses_from_ron_with_defaults() {
+        let config = EmitterConfig::from_ron("(spawn_rate: 50.0, blend: Alpha, size: [(0.0, 0.1)])").unwrap();
+        assert_eq!(config.spawn_rate, 50.0);
+        assert_eq!(config.blend, BlendMode::Alpha);
+        assert_eq!(config.size, vec![(0.0, 0.1)]);
+        assert_eq!(config.lifetime, EmitterConfig::default().lifetime);
+    }
+
+    #[test]
+    fn shipped_emitters_parse() {
+        let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/particles");
+        for entry in fs::read_dir(directory).expect("Fail to list emitters.") {
+            let path = entry.unwrap().path();
+            let text = fs::read_to_string(&path).unwrap();
+            EmitterConfig::from_ron(&text).unwrap_or_else(|error| panic!("{path:?}: {error}"));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn gpu_simulation_matches_cpu() {
+        let (device, queue) = test_support::headless_device();
+        let config = test_config();
+        let allocator = Allocator::new(device.clone());
+        let mut gpu = GpuParticleSimulation::new(device, &allocator, config.max_particles);
+        let mut cpu = CpuParticleSimulation::new(config.max_particles);
+        let mut emitter = ParticleEmitter::new(config);
+
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        for _ in 0..40 {
+            let step = emitter.step(1.0 / 60.0);
+            gpu.record_step(&mut builder, step);
+            cpu.step(&step);
+        }
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let gpu_particles = gpu.current_buffer().read().unwrap().to_vec();
+        for (gpu_particle, cpu_particle) in gpu_particles.iter().zip(cpu.particles.iter()) {
+            assert_eq!(gpu_particle.is_alive(), cpu_particle.is_alive());
+            let gpu_values = gpu_particle.position_age.iter().chain(&gpu_particle.velocity_lifetime);
+            let cpu_values = cpu_particle.position_age.iter().chain(&cpu_particle.velocity_lifetime);
+            for (gpu_value, cpu_value) in gpu_values.zip(cpu_values) {
+                assert!((gpu_value - cpu_value).abs() < 1e-4, "{gpu_particle:?} != {cpu_particle:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,626 @@
+use std::sync::Arc;
+
+use ahash::HashSet;
+
+use vulkano::{
+    device::{Device, DeviceOwned},
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo,
+        SubpassEndInfo, BlitImageInfo, CopyBufferToImageInfo
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    format::{Format, NumericFormat},
+    image::{
+        Image, ImageCreateInfo, ImageType, ImageUsage,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    render_pass::Subpass,
+    pipeline::{
+        Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo, DynamicState,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        graphics::{
+            GraphicsPipeline, GraphicsPipelineCreateInfo,
+            vertex_input::VertexInputState,
+            input_assembly::InputAssemblyState,
+            viewport::{Viewport, ViewportState},
+            rasterization::{RasterizationState, CullMode},
+            multisample::MultisampleState,
+            depth_stencil::{DepthStencilState, DepthState, CompareOp},
+            color_blend::{ColorBlendState, ColorBlendAttachmentState, AttachmentBlend},
+            subpass::PipelineSubpassType
+        }
+    }
+};
+
+use smallvec::SmallVec;
+
+use crate::{
+    debug,
+    shader,
+    allocator::Allocator,
+    bloom::{Bloom, BloomConfig},
+    render_target::{RenderTarget, DEFAULT_DEPTH_FORMAT},
+    renderer::RenderPath
+};
+
+/// Format of the scene and of every intermediate post-processing image.
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+const PASS_LABEL_COLOR: [f32; 4] = [0.7, 0.5, 0.9, 1.0];
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    Reinhard,
+    Aces
+}
+
+/// A built-in full-screen effect and its parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostEffect {
+    /// Maps HDR color to `0.0..=1.0` after scaling it by `exposure`.
+    ToneMapping { operator: ToneMapOperator, exposure: f32 },
+    /// Gamma encoding, for outputs that are not sRGB formats.
+    Gamma { gamma: f32 },
+    Fxaa,
+    /// Darkens towards the corners, starting at `radius` (1.0 is a corner).
+    Vignette { intensity: f32, radius: f32, smoothness: f32 },
+    /// Blends towards the color looked up in the processor's LUT.
+    ColorGrading { strength: f32 }
+}
+
+impl PostEffect {
+    const SHADERS: [&'static str; 5] = [
+        "tonemap_frag.spv",
+        "gamma_frag.spv",
+        "fxaa_frag.spv",
+        "vignette_frag.spv",
+        "color_grading_frag.spv"
+    ];
+    fn index(&self) -> usize {
+        match self {
+            PostEffect::ToneMapping { .. } => 0,
+            PostEffect::Gamma { .. } => 1,
+            PostEffect::Fxaa => 2,
+            PostEffect::Vignette { .. } => 3,
+            PostEffect::ColorGrading { .. } => 4
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostEffect::ToneMapping { .. } => "tone mapping",
+            PostEffect::Gamma { .. } => "gamma",
+            PostEffect::Fxaa => "fxaa",
+            PostEffect::Vignette { .. } => "vignette",
+            PostEffect::ColorGrading { .. } => "color grading"
+        }
+    }
+    /// The `params` push constant of the effect's shader.
+    pub fn params(&self, lut_size: u32) -> [f32; 4] {
+        match *self {
+            PostEffect::ToneMapping { operator, exposure } => {
+                let operator = match operator {
+                    ToneMapOperator::Reinhard => 0.0,
+                    ToneMapOperator::Aces => 1.0
+                };
+                [exposure, operator, 0.0, 0.0]
+            }
+            PostEffect::Gamma { gamma } => [gamma, 0.0, 0.0, 0.0],
+            PostEffect::Fxaa => [0.0; 4],
+            PostEffect::Vignette { intensity, radius, smoothness } => [intensity, radius, smoothness, 0.0],
+            PostEffect::ColorGrading { strength } => [strength, lut_size as f32, 0.0, 0.0]
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostPass {
+    pub effect: PostEffect,
+    pub enabled: bool
+}
+
+/// The ordered list of full-screen passes applied after the scene.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PostStack {
+    pub passes: Vec<PostPass>
+}
+
+impl PostStack {
+    /// Tone mapping, color grading, vignette, FXAA and gamma, with gamma
+    /// only enabled when `output_format` is not sRGB.
+    pub fn default_for(output_format: Format) -> Self {
+        let srgb_output = output_format.numeric_format_color() == Some(NumericFormat::SRGB);
+        let mut stack = PostStack::default();
+        stack
+            .push(PostEffect::ToneMapping { operator: ToneMapOperator::Aces, exposure: 1.0 }, true)
+            .push(PostEffect::ColorGrading { strength: 1.0 }, false)
+            .push(PostEffect::Vignette { intensity: 0.35, radius: 0.6, smoothness: 0.4 }, false)
+            .push(PostEffect::Fxaa, true)
+            .push(PostEffect::Gamma { gamma: 2.2 }, !srgb_output);
+        stack
+    }
+    pub fn push(&mut self, effect: PostEffect, enabled: bool) -> &mut Self {
+        self.passes.push(PostPass { effect, enabled });
+        self
+    }
+    /// Index of the first pass named `name`.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|pass| pass.effect.name() == name)
+    }
+    /// Whether a pass named `name` exists.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                self.passes[index].enabled = enabled;
+                true
+            }
+            None => false
+        }
+    }
+    /// Flips the pass at `index` and returns its new state.
+    pub fn toggle(&mut self, index: usize) -> Option<bool> {
+        let pass = self.passes.get_mut(index)?;
+        pass.enabled = !pass.enabled;
+        Some(pass.enabled)
+    }
+    /// Moves the pass at `from` to `to`.
+    pub fn move_pass(&mut self, from: usize, to: usize) {
+        if from >= self.passes.len() || to >= self.passes.len() {
+            return;
+        }
+        let pass = self.passes.remove(from);
+        self.passes.insert(to, pass);
+    }
+    pub fn enabled(&self) -> impl Iterator<Item = &PostEffect> {
+        self.passes.iter().filter(|pass| pass.enabled).map(|pass| &pass.effect)
+    }
+}
+
+/// A 3D color lookup table with `size` texels per side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorLut {
+    pub size: u32,
+    pub texels: Vec<[u8; 4]>
+}
+
+impl ColorLut {
+    fn encode(value: f32) -> u8 {
+        (value.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+    /// A LUT that leaves colors unchanged.
+    pub fn identity(size: u32) -> Self {
+        let scale = (size.max(2) - 1) as f32;
+        let mut texels = Vec::with_capacity((size * size * size) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    let channel = |value: u32| Self::encode(value as f32 / scale);
+                    texels.push([channel(red), channel(green), channel(blue), 255]);
+                }
+            }
+        }
+        ColorLut { size, texels }
+    }
+    /// Parses a 3D `.cube` table with the default domain.
+    pub fn from_cube(text: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut texels = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(value) = line.strip_prefix("LUT_3D_SIZE") {
+                size = Some(value.trim().parse::<u32>().map_err(|error| format!("Invalid LUT size: {error}"))?);
+                continue;
+            }
+            if line.starts_with(|first: char| first.is_ascii_alphabetic()) {
+                continue;
+            }
+            let values: Vec<f32> = line.split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|error| format!("Invalid LUT entry {line:?}: {error}"))?;
+            let [red, green, blue] = values[..] else {
+                return Err(format!("Expected three values in LUT entry {line:?}"));
+            };
+            texels.push([Self::encode(red), Self::encode(green), Self::encode(blue), 255]);
+        }
+        let size = size.ok_or("Missing LUT_3D_SIZE")?;
+        if texels.len() != (size * size * size) as usize {
+            return Err(format!("Expected {} LUT entries, found {}", size * size * size, texels.len()));
+        }
+        Ok(ColorLut { size, texels })
+    }
+}
+
+/// Push constants shared by every full-screen shader.
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+pub struct PassConstants {
+    pub params: [f32; 4],
+    pub texel_size: [f32; 2]
+}
+
+/// A pipeline drawing `fragment_shader` over the whole of a single-color
+/// subpass, without depth testing. `blend` is `None` to overwrite the
+/// target.
+pub fn new_fullscreen_pipeline(
+    device: Arc<Device>,
+    subpass: Subpass,
+    fragment_shader: &str,
+    blend: Option<AttachmentBlend>
+) -> Arc<GraphicsPipeline> {
+    let vertex_module = shader::load_shader(device.clone(), "fullscreen_vert.spv");
+    let fragment_module = shader::load_shader(device.clone(), fragment_shader);
+    let stages: SmallVec<[PipelineShaderStageCreateInfo; 5]> = SmallVec::from_vec(vec![
+        PipelineShaderStageCreateInfo::new(vertex_module.entry_point("main").expect("Fail to find entry point")),
+        PipelineShaderStageCreateInfo::new(fragment_module.entry_point("main").expect("Fail to find entry point"))
+    ]);
+    let pipeline_layout = {
+        let create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .expect("Fail to reflect full-screen pipeline layout.");
+        PipelineLayout::new(device.clone(), create_info).expect("Fail to create full-screen pipeline layout.")
+    };
+    debug::set_object_name(&*pipeline_layout, &format!("{fragment_shader} pipeline layout"));
+
+    let color_blend_state = ColorBlendState {
+        attachments: vec![
+            ColorBlendAttachmentState {
+                blend,
+                ..Default::default()
+            }
+        ],
+        ..Default::default()
+    };
+    let mut dynamic_state = HashSet::default();
+    dynamic_state.insert(DynamicState::Viewport);
+
+    let create_info = GraphicsPipelineCreateInfo {
+        stages,
+        vertex_input_state: Some(VertexInputState::new()),
+        input_assembly_state: Some(InputAssemblyState::default()),
+        viewport_state: Some(ViewportState::default()),
+        rasterization_state: Some(RasterizationState {
+            cull_mode: CullMode::None,
+            ..Default::default()
+        }),
+        multisample_state: Some(MultisampleState::default()),
+        depth_stencil_state: subpass.subpass_desc().depth_stencil_attachment.as_ref().map(|_| DepthStencilState {
+            depth: Some(DepthState {
+                write_enable: false,
+                compare_op: CompareOp::Always
+            }),
+            ..Default::default()
+        }),
+        color_blend_state: Some(color_blend_state),
+        dynamic_state,
+        subpass: Some(PipelineSubpassType::BeginRenderPass(subpass)),
+        ..GraphicsPipelineCreateInfo::layout(pipeline_layout)
+    };
+    let pipeline = GraphicsPipeline::new(device, None, create_info)
+        .expect("Fail to create full-screen pipeline.");
+    debug::set_object_name(&*pipeline, &format!("{fragment_shader} pipeline"));
+    pipeline
+}
+
+/// Records one full-screen draw of `pipeline` into `target`, with set 0
+/// built from `writes`.
+pub fn record_fullscreen_pass(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    allocator: &Allocator,
+    pipeline: &Arc<GraphicsPipeline>,
+    target: &RenderTarget,
+    writes: impl IntoIterator<Item = WriteDescriptorSet>,
+    push_constants: PassConstants,
+    label: &str
+) {
+    let layout = pipeline.layout().clone();
+    let descriptor_set = PersistentDescriptorSet::new(
+        &allocator.descriptor_set_allocator,
+        layout.set_layouts()[0].clone(),
+        writes,
+        []
+    ).expect("Fail to create full-screen pass descriptor set.");
+    let extent = target.extent();
+    let viewports: SmallVec<[Viewport; 2]> = SmallVec::from_vec(vec![
+        Viewport {
+            extent: [extent[0] as f32, extent[1] as f32],
+            ..Default::default()
+        }
+    ]);
+    let render_pass_begin_info = RenderPassBeginInfo {
+        clear_values: target.clear_values(),
+        ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+    };
+
+    debug::with_label(builder, label, PASS_LABEL_COLOR, |builder| {
+        builder
+        .begin_render_pass(render_pass_begin_info, SubpassBeginInfo::default())
+        .expect("Fail to begin full-screen pass.")
+        .bind_pipeline_graphics(pipeline.clone())
+        .expect("Fail to bind full-screen pipeline.")
+        .set_viewport(0, viewports)
+        .expect("Fail to set viewport.")
+        .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, descriptor_set)
+        .expect("Fail to bind full-screen descriptor set.")
+        .push_constants(layout, 0, push_constants)
+        .expect("Fail to push full-screen constants.")
+        .draw(3, 1, 0, 0)
+        .expect("Fail to draw full-screen triangle.")
+        .end_render_pass(SubpassEndInfo::default())
+        .expect("Fail to end full-screen pass.");
+    });
+}
+
+/// Owns the HDR scene target and runs bloom and a [`PostStack`] over it,
+/// blitting the result into the output image.
+pub struct PostProcessor {
+    pub scene: RenderTarget,
+    pub sampler: Arc<Sampler>,
+    pub bloom: Option<Bloom>,
+    targets: [RenderTarget; 2],
+    pipelines: Vec<Arc<GraphicsPipeline>>,
+    lut: Arc<ImageView>,
+    lut_size: u32,
+    pending_lut: Option<Subbuffer<[u8]>>
+}
+
+impl PostProcessor {
+    fn new_sampler(device: Arc<Device>) -> Arc<Sampler> {
+        let create_info = SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..Default::default()
+        };
+        Sampler::new(device, create_info).expect("Fail to create post-processing sampler.")
+    }
+    fn new_lut_image(allocator: &Allocator, size: u32) -> Arc<ImageView> {
+        let create_info = ImageCreateInfo {
+            image_type: ImageType::Dim3d,
+            format: Format::R8G8B8A8_UNORM,
+            extent: [size, size, size],
+            usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        };
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        };
+        let image = Image::new(allocator.memory_allocator.clone(), create_info, allocation_info)
+            .expect("Fail to allocate color LUT.");
+        debug::set_object_name(&*image, "color grading LUT");
+        ImageView::new_default(image).expect("Fail to create color LUT view.")
+    }
+    /// The scene target is built for `path`.
+    pub fn new(allocator: &Allocator, extent: [u32; 2], path: RenderPath) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let scene = match path {
+            RenderPath::Forward => RenderTarget::new(allocator, extent, HDR_FORMAT, Some(DEFAULT_DEPTH_FORMAT), "scene"),
+            RenderPath::Deferred => RenderTarget::new_deferred(allocator, extent, HDR_FORMAT, "scene")
+        };
+        let targets = ["post A", "post B"].map(|name| RenderTarget::new(allocator, extent, HDR_FORMAT, None, name));
+        let subpass = Subpass::from(targets[0].render_pass.clone(), 0).unwrap();
+        let pipelines = PostEffect::SHADERS.iter()
+            .map(|shader_file| new_fullscreen_pipeline(device.clone(), subpass.clone(), shader_file, None))
+            .collect();
+        let mut post_processor = PostProcessor {
+            scene,
+            sampler: Self::new_sampler(device),
+            bloom: None,
+            targets,
+            pipelines,
+            lut: Self::new_lut_image(allocator, IDENTITY_LUT_SIZE),
+            lut_size: IDENTITY_LUT_SIZE,
+            pending_lut: None
+        };
+        post_processor.set_color_lut(allocator, &ColorLut::identity(IDENTITY_LUT_SIZE));
+        post_processor
+    }
+    /// Replaces the color grading LUT from the next [`Self::record`] on.
+    pub fn set_color_lut(&mut self, allocator: &Allocator, lut: &ColorLut) {
+        if lut.size != self.lut_size {
+            self.lut = Self::new_lut_image(allocator, lut.size);
+            self.lut_size = lut.size;
+        }
+        let create_info = BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        };
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let staging = Buffer::from_iter(
+            allocator.memory_allocator.clone(),
+            create_info,
+            allocation_info,
+            lut.texels.concat()
+        ).expect("Fail to allocate color LUT staging buffer.");
+        self.pending_lut = Some(staging);
+    }
+    /// Enables, reconfigures or drops the bloom chain.
+    pub fn set_bloom(&mut self, allocator: &Allocator, config: BloomConfig) {
+        match (&mut self.bloom, config.enabled) {
+            (Some(bloom), true) => bloom.set_config(allocator, config),
+            (None, true) => self.bloom = Some(Bloom::new(allocator, self.extent(), config)),
+            (_, false) => self.bloom = None
+        }
+    }
+    /// Reallocates the scene and intermediate images when `extent` changed.
+    pub fn resize(&mut self, allocator: &Allocator, extent: [u32; 2]) {
+        self.scene.resize(allocator, extent);
+        if let Some(bloom) = self.bloom.as_mut() {
+            bloom.resize(allocator, extent);
+        }
+        for target in self.targets.iter_mut() {
+            target.resize(allocator, extent);
+        }
+    }
+    pub fn extent(&self) -> [u32; 2] {
+        self.scene.extent()
+    }
+    /// The subpass scene pipelines must target.
+    pub fn scene_subpass(&self) -> Subpass {
+        Subpass::from(self.scene.render_pass.clone(), self.scene.path.forward_subpass()).unwrap()
+    }
+    fn input_writes(&self, effect: &PostEffect, input: Arc<ImageView>) -> Vec<WriteDescriptorSet> {
+        let mut writes = vec![
+            WriteDescriptorSet::image_view(0, input),
+            WriteDescriptorSet::sampler(1, self.sampler.clone())
+        ];
+        if let PostEffect::ColorGrading { .. } = effect {
+            writes.push(WriteDescriptorSet::image_view(2, self.lut.clone()));
+        }
+        writes
+    }
+    /// Applies bloom and the enabled passes of `stack` to the scene color,
+    /// then blits the result into `output`.
+    pub fn record(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        stack: &PostStack,
+        output: Arc<Image>
+    ) {
+        if let Some(staging) = self.pending_lut.take() {
+            builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(staging, self.lut.image().clone()))
+            .expect("Fail to upload color LUT.");
+        }
+
+        let extent = self.extent();
+        let texel_size = [1.0 / extent[0] as f32, 1.0 / extent[1] as f32];
+        let mut input = match &self.bloom {
+            Some(bloom) => bloom.record(builder, allocator, &self.sampler, self.scene.color.clone()),
+            None => self.scene.color.clone()
+        };
+        for (i, effect) in stack.enabled().enumerate() {
+            let target = &self.targets[i % 2];
+            let push_constants = PassConstants {
+                params: effect.params(self.lut_size),
+                texel_size
+            };
+            let writes = self.input_writes(effect, input);
+            record_fullscreen_pass(
+                builder,
+                allocator,
+                &self.pipelines[effect.index()],
+                target,
+                writes,
+                push_constants,
+                effect.name()
+            );
+            input = target.color.clone();
+        }
+
+        debug::with_label(builder, "post-processing blit", PASS_LABEL_COLOR, |builder| {
+            let blit_info = BlitImageInfo {
+                filter: Filter::Linear,
+                ..BlitImageInfo::images(input.image().clone(), output)
+            };
+            builder
+            .blit_image(blit_info)
+            .expect("Fail to blit post-processing output.");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::command_buffer::{CommandBufferUsage, PrimaryCommandBufferAbstract};
+    use vulkano::sync::GpuFuture;
+
+    use crate::{
+        capture,
+        model::ColoredVertex,
+        renderer::Renderer,
+        test_support
+    };
+
+    use super::*;
+
+    #[test]
+    fn default_stack_adds_gamma_only_for_linear_outputs() {
+        let srgb = PostStack::default_for(Format::B8G8R8A8_SRGB);
+        let unorm = PostStack::default_for(Format::B8G8R8A8_UNORM);
+        let gamma_enabled = |stack: &PostStack| stack.enabled().any(|effect| effect.name() == "gamma");
+        assert!(!gamma_enabled(&srgb));
+        assert!(gamma_enabled(&unorm));
+        assert_eq!(srgb.enabled().next().unwrap().name(), "tone mapping");
+    }
+
+    #[test]
+    fn passes_can_be_toggled_and_reordered() {
+        let mut stack = PostStack::default_for(Format::B8G8R8A8_SRGB);
+        assert!(stack.set_enabled("vignette", true));
+        assert!(!stack.set_enabled("missing", true));
+        assert_eq!(stack.toggle(0), Some(false));
+        assert_eq!(stack.toggle(99), None);
+
+        let fxaa = stack.position("fxaa").unwrap();
+        stack.move_pass(fxaa, 0);
+        let names: Vec<_> = stack.passes.iter().map(|pass| pass.effect.name()).collect();
+        assert_eq!(names, ["fxaa", "tone mapping", "color grading", "vignette", "gamma"]);
+        let enabled: Vec<_> = stack.enabled().map(PostEffect::name).collect();
+        assert_eq!(enabled, ["fxaa", "vignette"]);
+    }
+
+    #[test]
+    fn identity_lut_spans_the_color_cube() {
+        let lut = ColorLut::identity(4);
+        assert_eq!(lut.texels.len(), 64);
+        assert_eq!(lut.texels[0], [0, 0, 0, 255]);
+        assert_eq!(lut.texels[1], [85, 0, 0, 255]);
+        assert_eq!(lut.texels[63], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn cube_files_parse() {
+        let text = "TITLE \"test\"\n# comment\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\n\
+            0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+        assert_eq!(ColorLut::from_cube(text).unwrap(), ColorLut::identity(2));
+        assert!(ColorLut::from_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(ColorLut::from_cube("0 0 0\n").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn reinhard_maps_one_to_half() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let mut post_processor = PostProcessor::new(&allocator, [4, 4], RenderPath::Forward);
+        post_processor.scene.clear_color = [1.0, 1.0, 1.0, 1.0];
+        let renderer = Renderer::for_target(device, &post_processor.scene);
+        let mut stack = PostStack::default();
+        stack.push(PostEffect::ToneMapping { operator: ToneMapOperator::Reinhard, exposure: 1.0 }, true);
+
+        let output = RenderTarget::new(&allocator, [4, 4], Format::R8G8B8A8_UNORM, None, "output");
+        let vertices = vec![ColoredVertex::new([0.0; 3], [0.0; 3]); 3];
+        let indices = vec![0, 1, 2];
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        renderer.record_target_pass(
+            &mut builder,
+            &post_processor.scene,
+            allocator.alloc_vertex_buffer(&vertices),
+            allocator.alloc_index_buffer(&indices),
+            3,
+            |_| {}
+        );
+        post_processor.record(&mut builder, &allocator, &stack, output.color.image().clone());
+        let readback = capture::record_readback(&mut builder, &allocator, output.color.image().clone());
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let pixels = readback.to_rgba8().unwrap();
+        assert!((i32::from(pixels[0]) - 128).abs() <= 1, "{:?}", &pixels[..4]);
+        assert_eq!(pixels[3], 255);
+    }
+}
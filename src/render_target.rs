@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use vulkano::{
+    device::{Device, DeviceOwned},
+    format::{ClearValue, Format},
+    image::{
+        Image, ImageCreateInfo, ImageLayout, ImageType, ImageUsage,
+        sampler::Sampler,
+        view::ImageView
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    render_pass::{
+        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+        Framebuffer, FramebufferCreateInfo, RenderPass, RenderPassCreateInfo, SubpassDescription
+    },
+    descriptor_set::WriteDescriptorSet
+};
+
+use crate::{
+    debug,
+    allocator::Allocator,
+    deferred,
+    renderer::RenderPath
+};
+
+pub const DEFAULT_DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+
+/// An offscreen color image, with an optional depth image, to render into
+/// with [`Renderer::for_target`](crate::renderer::Renderer::for_target)
+/// and sample afterwards.
+pub struct RenderTarget {
+    pub name: String,
+    /// The pass taking forward draws.
+    pub render_pass: Arc<RenderPass>,
+    /// The path `render_pass` was built for.
+    pub path: RenderPath,
+    /// The pass filling the G-buffer of a deferred target.
+    pub geometry_render_pass: Option<Arc<RenderPass>>,
+    pub color: Arc<ImageView>,
+    pub depth: Option<Arc<ImageView>>,
+    /// The G-buffer of a deferred target, see [`deferred::GBUFFER_FORMATS`].
+    pub gbuffer: Vec<Arc<ImageView>>,
+    pub framebuffer: Arc<Framebuffer>,
+    pub geometry_framebuffer: Option<Arc<Framebuffer>>,
+    pub clear_color: [f32; 4]
+}
+
+impl RenderTarget {
+    /// A single-subpass render pass with one color and an optional depth
+    /// attachment.
+    pub fn new_render_pass(device: Arc<Device>, color_format: Format, depth_format: Option<Format>) -> Arc<RenderPass> {
+        let color_attachment = AttachmentDescription {
+            format: color_format,
+            load_op: AttachmentLoadOp::Clear,
+            store_op: AttachmentStoreOp::Store,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: ImageLayout::ShaderReadOnlyOptimal,
+            ..Default::default()
+        };
+        let mut attachments = vec![color_attachment];
+        let color_attachments = vec![
+            Some(AttachmentReference {
+                attachment: 0,
+                layout: ImageLayout::ColorAttachmentOptimal,
+                ..Default::default()
+            })
+        ];
+
+        let depth_stencil_attachment = depth_format.map(|format| {
+            attachments.push(AttachmentDescription {
+                format,
+                load_op: AttachmentLoadOp::Clear,
+                store_op: AttachmentStoreOp::DontCare,
+                initial_layout: ImageLayout::Undefined,
+                final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+                ..Default::default()
+            });
+            AttachmentReference {
+                attachment: 1,
+                layout: ImageLayout::DepthStencilAttachmentOptimal,
+                ..Default::default()
+            }
+        });
+
+        let subpass_description = SubpassDescription {
+            color_attachments,
+            depth_stencil_attachment,
+            ..Default::default()
+        };
+        let create_info = RenderPassCreateInfo {
+            attachments,
+            subpasses: vec![subpass_description],
+            ..Default::default()
+        };
+        RenderPass::new(device, create_info).expect("Fail to create render target render pass.")
+    }
+    fn new_attachment(
+        allocator: &Allocator,
+        format: Format,
+        extent: [u32; 2],
+        usage: ImageUsage,
+        name: &str
+    ) -> Arc<ImageView> {
+        let create_info = ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format,
+            extent: [extent[0], extent[1], 1],
+            usage,
+            ..Default::default()
+        };
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        };
+        let image = Image::new(allocator.memory_allocator.clone(), create_info, allocation_info)
+            .expect("Fail to allocate render target image.");
+        debug::set_object_name(&*image, name);
+        ImageView::new_default(image).expect("Fail to create render target image view.")
+    }
+    fn new_framebuffer(
+        render_pass: Arc<RenderPass>,
+        color: &Arc<ImageView>,
+        depth: &Option<Arc<ImageView>>,
+        gbuffer: &[Arc<ImageView>],
+        name: &str
+    ) -> Arc<Framebuffer> {
+        let create_info = FramebufferCreateInfo {
+            attachments: [Some(color), depth.as_ref()].into_iter().flatten().chain(gbuffer).cloned().collect(),
+            ..Default::default()
+        };
+        let framebuffer = Framebuffer::new(render_pass, create_info)
+            .expect("Fail to create render target framebuffer.");
+        debug::set_object_name(&*framebuffer, &format!("{name} framebuffer"));
+        framebuffer
+    }
+    fn new_images(
+        allocator: &Allocator,
+        render_pass: &RenderPass,
+        extent: [u32; 2],
+        name: &str
+    ) -> (Arc<ImageView>, Option<Arc<ImageView>>, Vec<Arc<ImageView>>) {
+        let attachments = render_pass.attachments();
+        // Read back in the lighting subpass of a deferred target.
+        let input_usage = if attachments.len() > 2 { ImageUsage::INPUT_ATTACHMENT } else { ImageUsage::empty() };
+        let color = Self::new_attachment(
+            allocator,
+            attachments[0].format,
+            extent,
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST,
+            &format!("{name} color")
+        );
+        let depth = attachments.get(1).map(|attachment| Self::new_attachment(
+            allocator,
+            attachment.format,
+            extent,
+            ImageUsage::DEPTH_STENCIL_ATTACHMENT | input_usage,
+            &format!("{name} depth")
+        ));
+        let gbuffer = attachments.iter().skip(2).enumerate()
+            .map(|(i, attachment)| Self::new_attachment(
+                allocator,
+                attachment.format,
+                extent,
+                ImageUsage::COLOR_ATTACHMENT | ImageUsage::INPUT_ATTACHMENT | ImageUsage::SAMPLED,
+                &format!("{name} G-buffer {i}")
+            ))
+            .collect();
+        (color, depth, gbuffer)
+    }
+    pub fn new(
+        allocator: &Allocator,
+        extent: [u32; 2],
+        color_format: Format,
+        depth_format: Option<Format>,
+        name: &str
+    ) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let render_pass = Self::new_render_pass(device, color_format, depth_format);
+        Self::with_render_pass(allocator, render_pass, RenderPath::Forward, None, extent, name)
+    }
+    /// A target for the deferred path, see [`deferred::new_render_passes`].
+    pub fn new_deferred(allocator: &Allocator, extent: [u32; 2], color_format: Format, name: &str) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let (geometry_render_pass, render_pass) = deferred::new_render_passes(device, color_format, DEFAULT_DEPTH_FORMAT);
+        debug::set_object_name(&*geometry_render_pass, &format!("{name} geometry pass"));
+        Self::with_render_pass(allocator, render_pass, RenderPath::Deferred, Some(geometry_render_pass), extent, name)
+    }
+    fn with_render_pass(
+        allocator: &Allocator,
+        render_pass: Arc<RenderPass>,
+        path: RenderPath,
+        geometry_render_pass: Option<Arc<RenderPass>>,
+        extent: [u32; 2],
+        name: &str
+    ) -> Self {
+        debug::set_object_name(&*render_pass, &format!("{name} render pass"));
+        let (color, depth, gbuffer) = Self::new_images(allocator, &render_pass, extent, name);
+        let framebuffer = Self::new_framebuffer(render_pass.clone(), &color, &depth, &gbuffer, name);
+        let geometry_framebuffer = geometry_render_pass.clone().map(|geometry_render_pass| {
+            Self::new_framebuffer(geometry_render_pass, &color, &depth, &gbuffer, &format!("{name} geometry"))
+        });
+        RenderTarget {
+            name: String::from(name),
+            render_pass,
+            path,
+            geometry_render_pass,
+            color,
+            depth,
+            gbuffer,
+            framebuffer,
+            geometry_framebuffer,
+            clear_color: [0.0, 0.0, 0.0, 1.0]
+        }
+    }
+    /// Reallocates the images at `extent`, keeping the render pass.
+    pub fn resize(&mut self, allocator: &Allocator, extent: [u32; 2]) {
+        if extent == self.extent() {
+            return;
+        }
+        let (color, depth, gbuffer) = Self::new_images(allocator, &self.render_pass, extent, &self.name);
+        self.framebuffer = Self::new_framebuffer(self.render_pass.clone(), &color, &depth, &gbuffer, &self.name);
+        self.geometry_framebuffer = self.geometry_render_pass.clone().map(|geometry_render_pass| {
+            Self::new_framebuffer(geometry_render_pass, &color, &depth, &gbuffer, &format!("{} geometry", self.name))
+        });
+        self.color = color;
+        self.depth = depth;
+        self.gbuffer = gbuffer;
+    }
+    pub fn extent(&self) -> [u32; 2] {
+        self.framebuffer.extent()
+    }
+    pub fn color_format(&self) -> Format {
+        self.color.format()
+    }
+    /// Clear values of the first pass into the target.
+    pub fn clear_values(&self) -> Vec<Option<ClearValue>> {
+        let mut clear_values = vec![Some(self.clear_color.into())];
+        if self.depth.is_some() {
+            clear_values.push(Some(1.0.into()));
+        }
+        clear_values.extend(self.gbuffer.iter().map(|_| Some([0.0; 4].into())));
+        clear_values
+    }
+    /// Binds the color image for sampling with `sampler`.
+    pub fn sampled(&self, binding: u32, sampler: Arc<Sampler>) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(binding, self.color.clone(), sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{
+        buffer::{Buffer, BufferCreateInfo, BufferUsage},
+        command_buffer::{CommandBufferUsage, CopyImageToBufferInfo, PrimaryCommandBufferAbstract},
+        sync::GpuFuture
+    };
+
+    use crate::{
+        model::ColoredVertex,
+        renderer::Renderer,
+        test_support
+    };
+
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn renders_into_target_and_reads_back() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let mut target = RenderTarget::new(&allocator, [8, 8], Format::R8G8B8A8_UNORM, None, "test target");
+        target.resize(&allocator, [16, 16]);
+        assert_eq!(target.extent(), [16, 16]);
+        target.clear_color = [0.0, 0.0, 1.0, 1.0];
+        let renderer = Renderer::for_target(device, &target);
+
+        let white = [1.0, 1.0, 1.0];
+        let vertices = vec![
+            ColoredVertex::new([-0.5, -0.5, 0.0], white),
+            ColoredVertex::new([-0.5, 0.5, 0.0], white),
+            ColoredVertex::new([0.5, -0.5, 0.0], white),
+            ColoredVertex::new([0.5, 0.5, 0.0], white)
+        ];
+        let indices = vec![0, 1, 2, 2, 1, 3];
+        let readback = Buffer::new_slice::<[u8; 4]>(
+            allocator.memory_allocator.clone(),
+            BufferCreateInfo { usage: BufferUsage::TRANSFER_DST, ..Default::default() },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            16 * 16
+        ).unwrap();
+
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        renderer.record_target_pass(
+            &mut builder,
+            &target,
+            allocator.alloc_vertex_buffer(&vertices),
+            allocator.alloc_index_buffer(&indices),
+            indices.len() as u32,
+            |_| {}
+        );
+        builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(target.color.image().clone(), readback.clone()))
+            .unwrap();
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let pixels = readback.read().unwrap();
+        assert_eq!(pixels[0], [0, 0, 255, 255]);
+        assert_eq!(pixels[8 * 16 + 8], [255, 255, 255, 255]);
+    }
+}
@@ -1,69 +1,128 @@
-use std::{
-    sync::Arc,
-    fs::File,
-    io::Read
-};
+use std::sync::Arc;
+
+use ahash::HashSet;
+
+use glam::Mat4;
 
-use ahash::{HashSet, HashMap};
+use serde::{Deserialize, Serialize};
 
 use vulkano::{
-    device::Device,
-    pipeline::layout::{PipelineLayout, PipelineLayoutCreateInfo},
+    device::{Device, DeviceOwned},
+    pipeline::layout::{PipelineLayout, PipelineLayoutCreateInfo, PipelineDescriptorSetLayoutCreateInfo},
     format::Format,
     render_pass::{
         RenderPass, Subpass, Framebuffer, FramebufferCreateInfo, RenderPassCreateInfo,
         AttachmentDescription, AttachmentLoadOp, AttachmentStoreOp, AttachmentReference,
         SubpassDescription
     },
-    shader::{ShaderModule, ShaderModuleCreateInfo},
+    image::view::ImageView,
     pipeline::{
-        PipelineCreateFlags, PipelineShaderStageCreateInfo, DynamicState,
+        Pipeline, PipelineCreateFlags, PipelineShaderStageCreateInfo, DynamicState,
         graphics::{
             GraphicsPipeline, GraphicsPipelineCreateInfo,
             vertex_input::{
-                VertexInputState, Vertex, VertexInputBindingDescription, VertexInputAttributeDescription
+                VertexInputState, Vertex, VertexInputBindingDescription, VertexInputAttributeDescription,
+                VertexBufferDescription
             },
             input_assembly::{InputAssemblyState, PrimitiveTopology},
             viewport::{Viewport, ViewportState},
             rasterization::{
-                RasterizationState, PolygonMode, FrontFace, CullMode
+                RasterizationState, PolygonMode, FrontFace, CullMode, DepthBiasState
             },
             multisample::MultisampleState,
+            depth_stencil::{DepthStencilState, DepthState},
             color_blend::{ColorBlendState, ColorBlendAttachmentState},
             subpass::PipelineSubpassType
         }
     },
-    image::{
-        ImageLayout,
-        view::ImageView
-    },
+    image::ImageLayout,
+    format::ClearValue,
     command_buffer::{
         CommandBufferUsage, RenderPassBeginInfo, SubpassBeginInfo, SubpassEndInfo,
+        AutoCommandBufferBuilder, DrawIndexedIndirectCommand,
         auto::PrimaryAutoCommandBuffer
     },
-    buffer::Subbuffer
+    buffer::Subbuffer,
+    shader::ShaderInterface
 };
 
 use smallvec::SmallVec;
 
 use crate::{
+    debug,
+    shader,
     allocator::Allocator,
-    model::ColoredVertex
+    deferred,
+    indirect::{GpuMeshArena, IndirectDraw},
+    model::{ColoredVertex, ColoredInstance, MeshVertex},
+    render_target::RenderTarget
 };
+
+const MAIN_PASS_LABEL_COLOR: [f32; 4] = [0.2, 0.6, 0.9, 1.0];
+const DRAW_LABEL_COLOR: [f32; 4] = [0.9, 0.5, 0.65, 1.0];
+
+/// How a [`Renderer`] shades lit meshes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderPath {
+    /// Every light is evaluated while drawing each mesh.
+    #[default]
+    Forward,
+    /// Meshes fill a G-buffer that every light then shades, see
+    /// [`deferred`].
+    Deferred
+}
+
+impl RenderPath {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "forward" => Some(RenderPath::Forward),
+            "deferred" => Some(RenderPath::Deferred),
+            _ => None
+        }
+    }
+    /// Index of the subpass taking forward draws.
+    pub fn forward_subpass(self) -> u32 {
+        match self {
+            RenderPath::Forward => 0,
+            RenderPath::Deferred => deferred::FORWARD_SUBPASS
+        }
+    }
+}
+
+/// The subpass a pass is in when it calls back to record draws.
+#[derive(Clone, Copy)]
+pub enum RenderStage<'a> {
+    /// Deferred only: fill the G-buffer.
+    Geometry,
+    /// Deferred only: sample the G-buffer, outside any render pass.
+    Occlusion(&'a RenderTarget),
+    /// Deferred only: shade the G-buffer of the target.
+    Lighting(&'a RenderTarget),
+    /// Forward draws, e.g. particles and overlays.
+    Forward
+}
+
 pub struct Renderer {
     pub pipeline_layout: Arc<PipelineLayout>,
     pub render_pass: Arc<RenderPass>,
-    pub graphics_pipeline: Arc<GraphicsPipeline>
+    /// The geometry pass of the deferred path.
+    pub geometry_render_pass: Option<Arc<RenderPass>>,
+    pub graphics_pipeline: Arc<GraphicsPipeline>,
+    /// Draws [`ColoredVertex`] meshes once per [`ColoredInstance`].
+    pub instanced_pipeline: Arc<GraphicsPipeline>,
+    pub path: RenderPath
 }
 
 impl Renderer {
     fn new_pipeline_layout(device: Arc<Device>) -> Arc<PipelineLayout> {
         let create_info = PipelineLayoutCreateInfo::default();
-        PipelineLayout::new(device, create_info).expect("Fail to create pipeline layout.")
+        let pipeline_layout = PipelineLayout::new(device, create_info).expect("Fail to create pipeline layout.");
+        debug::set_object_name(&*pipeline_layout, "main pipeline layout");
+        pipeline_layout
     }
     fn new_render_pass(device: Arc<Device>, format: Format) -> Arc<RenderPass> {
         let color_attachment = AttachmentDescription {
-            format: format,
+            format,
             load_op: AttachmentLoadOp::Clear,
             store_op: AttachmentStoreOp::Store,
             initial_layout: ImageLayout::PresentSrc,
@@ -89,17 +148,10 @@ impl Renderer {
             subpasses,
             ..Default::default()
         };
-        RenderPass::new(device, create_info)
-            .expect("Fail to create render pass")
-    }
-    fn read_spirv_code(device: Arc<Device>, path: String) -> Arc<ShaderModule> {
-        let mut handler = File::open(path).expect("Fail to open the spv file.");
-        let mut bytes = Vec::new();
-        handler.read_to_end(&mut bytes).expect("Fail to read the spv file.");
-        let words = vulkano::shader::spirv::bytes_to_words(bytes.as_slice())
-            .expect("Fail to translate spir-v bytes to words.");
-        let create_info = ShaderModuleCreateInfo::new(&*words);
-        unsafe { ShaderModule::new(device, create_info).expect("Fail to create shader module.") }
+        let render_pass = RenderPass::new(device, create_info)
+            .expect("Fail to create render pass");
+        debug::set_object_name(&*render_pass, "main render pass");
+        render_pass
     }
     fn new_graphics_pipeline(
         device: Arc<Device>,
@@ -108,56 +160,23 @@ impl Renderer {
     ) -> Arc<GraphicsPipeline> {
         let flags = PipelineCreateFlags::empty();
         
-        let vertex_shader = Self::read_spirv_code(device.clone(), String::from(".\\shaders\\vert.spv"));
-        let fragment_shader = Self::read_spirv_code(device.clone(), String::from(".\\shaders\\frag.spv"));
+        let vertex_shader = shader::load_shader(device.clone(), "vert.spv");
+        let fragment_shader = shader::load_shader(device.clone(), "frag.spv");
+
+        let vertex_entry_point = vertex_shader.entry_point("main").expect("Fail to find entry point");
+        let vertex_input_state = Some(vertex_input(
+            &vertex_entry_point.info().input_interface,
+            &[(ColoredVertex::per_vertex(), &ColoredVertex::ATTRIBUTES)]
+        ));
 
         let stages = {
-            let vertex_shader_stage = PipelineShaderStageCreateInfo::new(
-                vertex_shader.entry_point("main").expect("Fail to find entry point"
-            ));
+            let vertex_shader_stage = PipelineShaderStageCreateInfo::new(vertex_entry_point);
             let fragment_shader_stage = PipelineShaderStageCreateInfo::new(
                 fragment_shader.entry_point("main").expect("Fail to find entry point"
             ));
             SmallVec::from_vec(vec![vertex_shader_stage, fragment_shader_stage])
         };
 
-        let vertex_input_state = {
-            let vertex_buffer_description = ColoredVertex::per_vertex();
-
-            let bindings = {
-                let vertex_binding_description = VertexInputBindingDescription {
-                    stride: vertex_buffer_description.stride,
-                    input_rate: vertex_buffer_description.input_rate
-                };
-                let mut current_map = HashMap::default();
-                current_map.insert(0, vertex_binding_description);
-                current_map
-            };
-
-            let attributes = {
-                let mut current_map = HashMap::default();
-                let input_arguments = [String::from("position"), String::from("color")];
-                for i in 0..(input_arguments.len()) {
-                    let vertex_member_info = vertex_buffer_description.members.get(&input_arguments[i]).unwrap();
-                    let vertex_attribute_description = VertexInputAttributeDescription {
-                        binding: 0,
-                        format: vertex_member_info.format,
-                        offset: vertex_member_info.offset as u32
-                    };
-                    current_map.insert(i as u32, vertex_attribute_description);
-                }
-                current_map
-            };
-
-            Some(
-                VertexInputState {
-                    bindings,
-                    attributes,
-                    ..Default::default()
-                }
-            )
-        };
-
         let input_assembly_state = Some(
             InputAssemblyState {
                 topology: PrimitiveTopology::TriangleList,
@@ -184,7 +203,7 @@ impl Renderer {
             MultisampleState::default()
         );
 
-        let depth_stencil_state = None;
+        let depth_stencil_state = depth_stencil_state(&subpass, true);
 
         let color_blend_state = Some(
             ColorBlendState {
@@ -221,59 +240,314 @@ impl Renderer {
             ..GraphicsPipelineCreateInfo::layout(pipeline_layout.clone())
         };
 
-        GraphicsPipeline::new(device, None, create_info)
-            .expect("Fail to create graphics pipeline.")
+        let graphics_pipeline = GraphicsPipeline::new(device, None, create_info)
+            .expect("Fail to create graphics pipeline.");
+        debug::set_object_name(&*graphics_pipeline, "colored vertex pipeline");
+        graphics_pipeline
+    }
+    /// The subpass every forward pipeline must target.
+    pub fn main_subpass(&self) -> Subpass {
+        Subpass::from(self.render_pass.clone(), self.path.forward_subpass()).unwrap()
+    }
+    /// The G-buffer and lighting subpasses of the deferred path.
+    pub fn deferred_subpasses(&self) -> Option<(Subpass, Subpass)> {
+        self.geometry_render_pass.as_ref().map(|geometry_render_pass| (
+            Subpass::from(geometry_render_pass.clone(), deferred::GEOMETRY_SUBPASS).unwrap(),
+            Subpass::from(self.render_pass.clone(), deferred::LIGHTING_SUBPASS).unwrap()
+        ))
     }
+    /// Renders into swapchain images of `format`.
     pub fn new(device: Arc<Device>, format: Format) -> Self {
-        let pipeline_layout = Self::new_pipeline_layout(device.clone());
-
         let render_pass = Self::new_render_pass(device.clone(), format);
+        Self::with_render_pass(device, render_pass, RenderPath::Forward, None)
+    }
+    /// Renders into `target`, following its path.
+    pub fn for_target(device: Arc<Device>, target: &RenderTarget) -> Self {
+        Self::with_render_pass(device, target.render_pass.clone(), target.path, target.geometry_render_pass.clone())
+    }
+    fn with_render_pass(
+        device: Arc<Device>,
+        render_pass: Arc<RenderPass>,
+        path: RenderPath,
+        geometry_render_pass: Option<Arc<RenderPass>>
+    ) -> Self {
+        let pipeline_layout = Self::new_pipeline_layout(device.clone());
 
-        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        let subpass = Subpass::from(render_pass.clone(), path.forward_subpass()).unwrap();
 
-        let graphics_pipeline = Self::new_graphics_pipeline(device.clone(), pipeline_layout.clone(), subpass);
+        let graphics_pipeline = Self::new_graphics_pipeline(device.clone(), pipeline_layout.clone(), subpass.clone());
+        let instanced_pipeline = new_instanced_pipeline(device.clone(), subpass, PrimitiveTopology::TriangleList);
 
         Renderer {
             pipeline_layout,
             render_pass,
-            graphics_pipeline
+            geometry_render_pass,
+            graphics_pipeline,
+            instanced_pipeline,
+            path
         }
     }
-    pub fn record_command_buffer(
+    /// Records the main render pass into `builder`. Work recorded before it
+    /// in the same command buffer, such as compute dispatches producing the
+    /// vertex buffer, is synchronized automatically.
+    pub fn record_main_pass(
         &self,
-        allocator: &Allocator,
-        graphics_queue_family_index: u32,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
         vertex_buffer: Subbuffer<[ColoredVertex]>,
         index_buffer: Subbuffer<[u32]>,
         index_count: u32,
         output: Arc<ImageView>,
-    ) -> Arc<PrimaryAutoCommandBuffer> {
-        let (render_area_extent, layers) = {
-            let extent = output.image().extent();
-            ([extent[0], extent[1]], extent[2])
-        };
-
+    ) {
+        self.record_main_pass_with(builder, vertex_buffer, index_buffer, index_count, output, |_| {});
+    }
+    /// Same as [`Self::record_main_pass`], then lets `overlay` record more
+    /// draws into the same subpass, e.g. particles. The viewport is already
+    /// set when `overlay` runs.
+    pub fn record_main_pass_with(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertex_buffer: Subbuffer<[ColoredVertex]>,
+        index_buffer: Subbuffer<[u32]>,
+        index_count: u32,
+        output: Arc<ImageView>,
+        overlay: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>)
+    ) {
+        self.record_main_pass_draws(builder, output, |builder| {
+            Self::record_mesh(builder, vertex_buffer, index_buffer, index_count);
+            overlay(builder);
+        });
+    }
+    /// Begins the main pass on `output` and lets `draw` record every draw
+    /// in it, without the built-in mesh.
+    pub fn record_main_pass_draws(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        output: Arc<ImageView>,
+        draw: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>)
+    ) {
         let framebuffer = {
             let create_info = FramebufferCreateInfo {
                 attachments: vec![output.clone()],
-                layers,
+                layers: output.image().extent()[2],
                 ..Default::default()
             };
-            Framebuffer::new(self.render_pass.clone(), create_info)
-                .expect("Fail to create framebuffer.")
+            let framebuffer = Framebuffer::new(self.render_pass.clone(), create_info)
+                .expect("Fail to create framebuffer.");
+            debug::set_object_name(&*framebuffer, "main framebuffer");
+            framebuffer
         };
-
         let clear_values = vec![
             Some([0.0, 0.0, 0.0, 1.0].into())
         ];
+        self.record_pass(builder, framebuffer, clear_values, "main pass", None, forward_only(draw));
+    }
+    /// Records the mesh into `target`, see [`Self::for_target`].
+    pub fn record_target_pass(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        target: &RenderTarget,
+        vertex_buffer: Subbuffer<[ColoredVertex]>,
+        index_buffer: Subbuffer<[u32]>,
+        index_count: u32,
+        overlay: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>)
+    ) {
+        self.record_target_pass_draws(builder, target, |builder| {
+            Self::record_mesh(builder, vertex_buffer, index_buffer, index_count);
+            overlay(builder);
+        });
+    }
+    /// Begins a pass on `target` and lets `draw` record its forward draws.
+    pub fn record_target_pass_draws(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        target: &RenderTarget,
+        draw: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>)
+    ) {
+        self.record_target_pass_stages(builder, target, forward_only(draw));
+    }
+    /// Begins a pass on `target` and calls `draw` once per stage of the
+    /// path, in order.
+    pub fn record_target_pass_stages(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        target: &RenderTarget,
+        draw: impl FnMut(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, RenderStage)
+    ) {
+        self.record_pass(builder, target.framebuffer.clone(), target.clear_values(), &target.name, Some(target), draw);
+    }
+    /// Binds the buffers and draws them with the bound pipeline.
+    pub fn record_mesh<V: Vertex>(
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        vertex_buffer: Subbuffer<[V]>,
+        index_buffer: Subbuffer<[u32]>,
+        index_count: u32
+    ) {
+        debug::with_label(builder, "draw mesh", DRAW_LABEL_COLOR, |builder| {
+            builder
+            .bind_vertex_buffers(0, vertex_buffer)
+            .expect("Fail to bind vertex buffer")
+            .bind_index_buffer(index_buffer)
+            .expect("Fail to bind index buffer")
+            .draw_indexed(index_count, 1, 0, 0, 0)
+            .expect("Fail to draw vertices.");
+        });
+    }
+    /// Draws the mesh once per element of `instance_buffer`, binding the
+    /// instanced pipeline.
+    pub fn record_instances(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        view_projection: Mat4,
+        vertex_buffer: Subbuffer<[ColoredVertex]>,
+        instance_buffer: Subbuffer<[ColoredInstance]>,
+        index_buffer: Subbuffer<[u32]>,
+        index_count: u32
+    ) {
+        let instance_count = instance_buffer.len() as u32;
+        debug::with_label(builder, "draw instances", DRAW_LABEL_COLOR, |builder| {
+            self.bind_instanced(builder, view_projection, vertex_buffer, instance_buffer, index_buffer);
+            builder
+            .draw_indexed(index_count, instance_count, 0, 0, 0)
+            .expect("Fail to draw instances.");
+        });
+    }
+    /// Whether [`Self::record_indirect`] is available.
+    pub fn supports_indirect(&self) -> bool {
+        self.render_pass.device().enabled_features().draw_indirect_first_instance
+    }
+    /// Draws every command of `commands` with the instanced pipeline, from
+    /// the meshes of `arena`. Panics without [`Self::supports_indirect`].
+    pub fn record_indirect(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        view_projection: Mat4,
+        arena: &GpuMeshArena<ColoredVertex>,
+        instance_buffer: Subbuffer<[ColoredInstance]>,
+        commands: Subbuffer<[DrawIndexedIndirectCommand]>
+    ) {
+        assert!(
+            self.supports_indirect(),
+            "Indirect draws need the draw_indirect_first_instance feature, which this device does not enable."
+        );
+        let multi_draw = self.render_pass.device().enabled_features().multi_draw_indirect;
+        debug::with_label(builder, "draw indirect", DRAW_LABEL_COLOR, |builder| {
+            self.bind_instanced(
+                builder,
+                view_projection,
+                arena.vertex_buffer.clone(),
+                instance_buffer,
+                arena.index_buffer.clone()
+            );
+            if multi_draw {
+                builder
+                .draw_indexed_indirect(commands)
+                .expect("Fail to draw indirect.");
+            } else {
+                for draw in 0..commands.len() {
+                    builder
+                    .draw_indexed_indirect(commands.clone().slice(draw..draw + 1))
+                    .expect("Fail to draw indirect.");
+                }
+            }
+        });
+    }
+    /// Draws `draws` from the host, one draw call each.
+    pub fn record_draws(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        view_projection: Mat4,
+        arena: &GpuMeshArena<ColoredVertex>,
+        instance_buffer: Subbuffer<[ColoredInstance]>,
+        draws: &[IndirectDraw]
+    ) {
+        debug::with_label(builder, "draw meshes", DRAW_LABEL_COLOR, |builder| {
+            self.bind_instanced(
+                builder,
+                view_projection,
+                arena.vertex_buffer.clone(),
+                instance_buffer.clone(),
+                arena.index_buffer.clone()
+            );
+            for draw in draws.iter().filter(|draw| draw.instance_count > 0) {
+                let instances = draw.first_instance as u64..(draw.first_instance + draw.instance_count) as u64;
+                builder
+                .bind_vertex_buffers(0, (arena.vertex_buffer.clone(), instance_buffer.clone().slice(instances)))
+                .expect("Fail to bind vertex buffers")
+                .draw_indexed(draw.index_count, draw.instance_count, draw.first_index, draw.vertex_offset as i32, 0)
+                .expect("Fail to draw meshes.");
+            }
+        });
+    }
+    fn bind_instanced(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        view_projection: Mat4,
+        vertex_buffer: Subbuffer<[ColoredVertex]>,
+        instance_buffer: Subbuffer<[ColoredInstance]>,
+        index_buffer: Subbuffer<[u32]>
+    ) {
+        builder
+        .bind_pipeline_graphics(self.instanced_pipeline.clone())
+        .expect("Fail to bind instanced pipeline.")
+        .push_constants(self.instanced_pipeline.layout().clone(), 0, view_projection.to_cols_array_2d())
+        .expect("Fail to push view projection.")
+        .bind_vertex_buffers(0, (vertex_buffer, instance_buffer))
+        .expect("Fail to bind vertex buffers")
+        .bind_index_buffer(index_buffer)
+        .expect("Fail to bind index buffer");
+    }
+    fn record_pass(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        framebuffer: Arc<Framebuffer>,
+        clear_values: Vec<Option<ClearValue>>,
+        label: &str,
+        target: Option<&RenderTarget>,
+        mut draw: impl FnMut(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, RenderStage)
+    ) {
+        debug::with_label(builder, label, MAIN_PASS_LABEL_COLOR, |builder| {
+            if self.path == RenderPath::Deferred {
+                let target = target.expect("Deferred passes render into a target.");
+                let geometry_framebuffer = target.geometry_framebuffer.clone()
+                    .expect("Deferred targets have a geometry framebuffer.");
+                Self::begin_pass(builder, geometry_framebuffer, clear_values);
+                draw(builder, RenderStage::Geometry);
+                builder
+                .end_render_pass(SubpassEndInfo::default())
+                .expect("Fail to end geometry pass.");
+                draw(builder, RenderStage::Occlusion(target));
+                let load_values = vec![None; framebuffer.attachments().len()];
+                Self::begin_pass(builder, framebuffer, load_values);
+                draw(builder, RenderStage::Lighting(target));
+                builder
+                .next_subpass(SubpassEndInfo::default(), SubpassBeginInfo::default())
+                .expect("Fail to begin forward subpass.");
+            }
+            else {
+                Self::begin_pass(builder, framebuffer, clear_values);
+            }
+            builder
+            .bind_pipeline_graphics(self.graphics_pipeline.clone())
+            .expect("Fail to bind graphics pipeline.");
+            draw(builder, RenderStage::Forward);
+
+            builder
+            .end_render_pass(SubpassEndInfo::default())
+            .expect("Fail to end rendering.");
+        });
+    }
+    /// Begins the render pass of `framebuffer` with a viewport covering it.
+    fn begin_pass(
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        framebuffer: Arc<Framebuffer>,
+        clear_values: Vec<Option<ClearValue>>
+    ) {
+        let render_area_extent = framebuffer.extent();
         let render_pass_begin_info = RenderPassBeginInfo {
             render_area_extent,
             clear_values,
             ..RenderPassBeginInfo::framebuffer(framebuffer)
         };
-        let subpass_begin_info = SubpassBeginInfo::default();
-        let subpass_end_info = SubpassEndInfo::default();
-
         let viewports: SmallVec<[Viewport; 2]> = SmallVec::from_vec(
             vec![
                 Viewport {
@@ -282,28 +556,248 @@ impl Renderer {
                 }
             ]
         );
-
+        builder
+        .begin_render_pass(render_pass_begin_info, SubpassBeginInfo::default())
+        .expect("Fail to begin rendering.")
+        .set_viewport(0, viewports)
+        .expect("Fail to set viewport.");
+    }
+    pub fn record_command_buffer(
+        &self,
+        allocator: &Allocator,
+        graphics_queue_family_index: u32,
+        vertex_buffer: Subbuffer<[ColoredVertex]>,
+        index_buffer: Subbuffer<[u32]>,
+        index_count: u32,
+        output: Arc<ImageView>,
+    ) -> Arc<PrimaryAutoCommandBuffer> {
         let mut builder = allocator.alloc_primary_builder(
             graphics_queue_family_index,
             CommandBufferUsage::OneTimeSubmit
         );
-
-        builder
-        .begin_render_pass(render_pass_begin_info, subpass_begin_info)
-        .expect("Fail to begin rendering.")
-        .bind_pipeline_graphics(self.graphics_pipeline.clone())
-        .expect("Fail to bind graphics pipeline.")
-        .set_viewport(0, viewports)
-        .expect("Fail to set viewport.")
-        .bind_vertex_buffers(0, vertex_buffer)
-        .expect("Fail to bind vertex buffer")
-        .bind_index_buffer(index_buffer)
-        .expect("Fail to bind index buffer")
-        .draw_indexed(index_count, 1, 0, 0, 0)
-        .expect("Fail to draw vertices.")
-        .end_render_pass(subpass_end_info)
-        .expect("Fail to end rendering.");
+        self.record_main_pass(&mut builder, vertex_buffer, index_buffer, index_count, output);
     
-        builder.build().expect("Fail to build command buffer.")
+        let command_buffer = builder.build().expect("Fail to build command buffer.");
+        debug::set_object_name(&*command_buffer, "main command buffer");
+        command_buffer
+    }
+}
+
+/// Adapts draws of the forward stage alone to [`Renderer::record_pass`].
+fn forward_only(
+    draw: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>)
+) -> impl FnMut(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, RenderStage) {
+    let mut draw = Some(draw);
+    move |builder, stage| {
+        if let Some(draw) = draw.take_if(|_| matches!(stage, RenderStage::Forward)) {
+            draw(builder);
+        }
+    }
+}
+
+/// Attribute descriptions of `bindings`, each a buffer description and
+/// the members it feeds, at consecutive locations.
+fn attribute_locations(
+    bindings: &[(VertexBufferDescription, &[&str])]
+) -> Vec<(u32, VertexInputAttributeDescription)> {
+    let mut locations = Vec::new();
+    for (binding, (description, members)) in bindings.iter().enumerate() {
+        for name in members.iter() {
+            let member = &description.members[*name];
+            for element in 0..member.num_elements {
+                let attribute = VertexInputAttributeDescription {
+                    binding: binding as u32,
+                    format: member.format,
+                    offset: (member.offset as u64 + element as u64 * member.format.block_size()) as u32
+                };
+                locations.push((locations.len() as u32, attribute));
+            }
+        }
     }
-}
\ No newline at end of file
+    locations
+}
+
+/// Vertex input for a shader reading some of the members of `bindings` at
+/// the locations of [`attribute_locations`].
+fn vertex_input(
+    interface: &ShaderInterface,
+    bindings: &[(VertexBufferDescription, &[&str])]
+) -> VertexInputState {
+    let locations = attribute_locations(bindings);
+    let attributes = interface.elements().iter().map(|element| {
+        let (_, attribute) = locations.get(element.location as usize)
+            .unwrap_or_else(|| panic!("No vertex attribute at location {}.", element.location));
+        (element.location, *attribute)
+    });
+    bindings.iter().enumerate()
+        .fold(VertexInputState::new(), |state, (binding, (description, _))| {
+            state.binding(binding as u32, VertexInputBindingDescription {
+                stride: description.stride,
+                input_rate: description.input_rate
+            })
+        })
+        .attributes(attributes)
+}
+
+/// Vertex input for a shader reading [`MeshVertex`] members at the
+/// locations of `MeshVertex::ATTRIBUTES`, possibly skipping some.
+fn mesh_vertex_input(interface: &ShaderInterface) -> VertexInputState {
+    vertex_input(interface, &[(MeshVertex::per_vertex(), &MeshVertex::ATTRIBUTES)])
+}
+
+/// A pipeline drawing [`MeshVertex`] triangle lists into `subpass`.
+/// `fragment_shader` is `None` for depth-only passes.
+pub fn new_mesh_pipeline(
+    device: Arc<Device>,
+    subpass: Subpass,
+    vertex_shader: &str,
+    fragment_shader: Option<&str>,
+    cull_mode: CullMode,
+    depth_bias: bool
+) -> Arc<GraphicsPipeline> {
+    let vertex_module = shader::load_shader(device.clone(), vertex_shader);
+    let vertex_entry_point = vertex_module.entry_point("main").expect("Fail to find entry point");
+    let vertex_input_state = mesh_vertex_input(&vertex_entry_point.info().input_interface);
+    let mut stages: SmallVec<[PipelineShaderStageCreateInfo; 5]> = SmallVec::new();
+    stages.push(PipelineShaderStageCreateInfo::new(vertex_entry_point));
+    if let Some(fragment_shader) = fragment_shader {
+        let fragment_module = shader::load_shader(device.clone(), fragment_shader);
+        stages.push(PipelineShaderStageCreateInfo::new(
+            fragment_module.entry_point("main").expect("Fail to find entry point")
+        ));
+    }
+    let pipeline_layout = {
+        let create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .expect("Fail to reflect mesh pipeline layout.");
+        PipelineLayout::new(device.clone(), create_info).expect("Fail to create mesh pipeline layout.")
+    };
+    debug::set_object_name(&*pipeline_layout, &format!("{vertex_shader} pipeline layout"));
+
+    let color_attachment_count = subpass.num_color_attachments();
+    let color_blend_state = (color_attachment_count > 0).then(|| {
+        ColorBlendState::with_attachment_states(color_attachment_count, ColorBlendAttachmentState::default())
+    });
+    let mut dynamic_state = HashSet::default();
+    dynamic_state.insert(DynamicState::Viewport);
+    if depth_bias {
+        dynamic_state.insert(DynamicState::DepthBias);
+    }
+
+    let create_info = GraphicsPipelineCreateInfo {
+        stages,
+        vertex_input_state: Some(vertex_input_state),
+        input_assembly_state: Some(InputAssemblyState::default()),
+        viewport_state: Some(ViewportState::default()),
+        rasterization_state: Some(RasterizationState {
+            cull_mode,
+            front_face: FrontFace::CounterClockwise,
+            depth_bias: depth_bias.then(DepthBiasState::default),
+            ..Default::default()
+        }),
+        multisample_state: Some(MultisampleState::default()),
+        depth_stencil_state: depth_stencil_state(&subpass, true),
+        color_blend_state,
+        dynamic_state,
+        subpass: Some(PipelineSubpassType::BeginRenderPass(subpass)),
+        ..GraphicsPipelineCreateInfo::layout(pipeline_layout)
+    };
+    let pipeline = GraphicsPipeline::new(device, None, create_info)
+        .expect("Fail to create mesh pipeline.");
+    debug::set_object_name(&*pipeline, &format!("{vertex_shader} pipeline"));
+    pipeline
+}
+
+/// A pipeline drawing [`ColoredVertex`] meshes once per [`ColoredInstance`]
+/// into `subpass`.
+pub fn new_instanced_pipeline(device: Arc<Device>, subpass: Subpass, topology: PrimitiveTopology) -> Arc<GraphicsPipeline> {
+    let vertex_module = shader::load_shader(device.clone(), "instanced_vert.spv");
+    let fragment_module = shader::load_shader(device.clone(), "frag.spv");
+    let vertex_entry_point = vertex_module.entry_point("main").expect("Fail to find entry point");
+    let vertex_input_state = vertex_input(
+        &vertex_entry_point.info().input_interface,
+        &[
+            (ColoredVertex::per_vertex(), &ColoredVertex::ATTRIBUTES),
+            (ColoredInstance::per_instance(), &ColoredInstance::ATTRIBUTES)
+        ]
+    );
+    let stages: SmallVec<[PipelineShaderStageCreateInfo; 5]> = SmallVec::from_vec(vec![
+        PipelineShaderStageCreateInfo::new(vertex_entry_point),
+        PipelineShaderStageCreateInfo::new(
+            fragment_module.entry_point("main").expect("Fail to find entry point")
+        )
+    ]);
+    let pipeline_layout = {
+        let create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .expect("Fail to reflect instanced pipeline layout.");
+        PipelineLayout::new(device.clone(), create_info).expect("Fail to create instanced pipeline layout.")
+    };
+    debug::set_object_name(&*pipeline_layout, "instanced pipeline layout");
+
+    let mut dynamic_state = HashSet::default();
+    dynamic_state.insert(DynamicState::Viewport);
+    let lines = topology == PrimitiveTopology::LineList;
+
+    let create_info = GraphicsPipelineCreateInfo {
+        stages,
+        vertex_input_state: Some(vertex_input_state),
+        input_assembly_state: Some(InputAssemblyState {
+            topology,
+            ..Default::default()
+        }),
+        viewport_state: Some(ViewportState::default()),
+        rasterization_state: Some(RasterizationState {
+            front_face: FrontFace::CounterClockwise,
+            cull_mode: if lines { CullMode::None } else { CullMode::Back },
+            ..Default::default()
+        }),
+        multisample_state: Some(MultisampleState::default()),
+        depth_stencil_state: depth_stencil_state(&subpass, !lines),
+        color_blend_state: Some(ColorBlendState::with_attachment_states(
+            subpass.num_color_attachments(),
+            ColorBlendAttachmentState::default()
+        )),
+        dynamic_state,
+        subpass: Some(PipelineSubpassType::BeginRenderPass(subpass)),
+        ..GraphicsPipelineCreateInfo::layout(pipeline_layout)
+    };
+    let pipeline = GraphicsPipeline::new(device, None, create_info)
+        .expect("Fail to create instanced pipeline.");
+    debug::set_object_name(&*pipeline, &format!("instanced {topology:?} pipeline"));
+    pipeline
+}
+/// Depth testing for pipelines drawing into `subpass`, if it has depth.
+pub fn depth_stencil_state(subpass: &Subpass, write: bool) -> Option<DepthStencilState> {
+    subpass.subpass_desc().depth_stencil_attachment.as_ref()?;
+    Some(DepthStencilState {
+        depth: Some(DepthState {
+            write_enable: write,
+            ..DepthState::simple()
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulkano::pipeline::graphics::vertex_input::VertexInputRate;
+
+    #[test]
+    fn instance_attributes_follow_the_vertex_ones() {
+        let locations = attribute_locations(&[
+            (ColoredVertex::per_vertex(), &ColoredVertex::ATTRIBUTES),
+            (ColoredInstance::per_instance(), &ColoredInstance::ATTRIBUTES)
+        ]);
+        let layout: Vec<_> = locations.iter()
+            .map(|(location, attribute)| (*location, attribute.binding, attribute.offset))
+            .collect();
+        assert_eq!(layout, [
+            (0, 0, 0), (1, 0, 12),
+            (2, 1, 0), (3, 1, 16), (4, 1, 32), (5, 1, 48),
+            (6, 1, 64), (7, 1, 80)
+        ]);
+        assert!(matches!(ColoredInstance::per_instance().input_rate, VertexInputRate::Instance { divisor: 1 }));
+    }
+}
@@ -0,0 +1,397 @@
+use std::{fmt, sync::Arc};
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::{
+    lighting::{DirectionalLight, Lights, PointLight, SpotLight},
+    material::Material,
+    model::{GpuMesh, MeshInstance}
+};
+
+/// Local placement of a node relative to its parent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Self {
+        Transform { translation, ..Default::default() }
+    }
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// A light in the space of its node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightComponent {
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight)
+}
+
+/// Handle to a node of a [`Scene`], invalid once the node is deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneError {
+    /// The handle refers to a deleted node.
+    MissingNode(NodeId),
+    /// The new parent is the node itself or one of its descendants.
+    Cycle
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::MissingNode(id) => write!(f, "no node {}v{}", id.index, id.generation),
+            SceneError::Cycle => write!(f, "a node cannot become its own descendant")
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// A named node of the hierarchy and its components.
+#[derive(Clone)]
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<GpuMesh>,
+    /// Material of `mesh`, or the default material of the scene.
+    pub material: Option<Arc<Material>>,
+    pub light: Option<LightComponent>,
+    /// Files `mesh` and `material` were loaded from.
+    pub mesh_asset: Option<String>,
+    pub material_asset: Option<String>,
+    transform: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// World matrix as of the last [`Scene::update_world_transforms`].
+    world: Mat4,
+    dirty: bool
+}
+
+impl Node {
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+    /// Replaces the local transform until the next update.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.dirty = true;
+    }
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+    pub fn world_transform(&self) -> Mat4 {
+        self.world
+    }
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>
+}
+
+/// A hierarchy of nodes with cached world transforms.
+pub struct Scene {
+    pub ambient: Vec3,
+    /// Drawn for meshes without a material.
+    pub default_material: Arc<Material>,
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+    roots: Vec<NodeId>
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            ambient: Lights::default().ambient,
+            default_material: Arc::new(Material::default()),
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+            roots: Vec::new()
+        }
+    }
+}
+
+impl Scene {
+    /// Adds a node without components under `parent`, or as a root.
+    pub fn add(&mut self, parent: Option<NodeId>, name: &str, transform: Transform) -> Result<NodeId, SceneError> {
+        if let Some(parent) = parent {
+            self.node(parent)?;
+        }
+        let node = Node {
+            name: String::from(name),
+            mesh: None,
+            material: None,
+            light: None,
+            mesh_asset: None,
+            material_asset: None,
+            transform,
+            parent,
+            children: Vec::new(),
+            world: Mat4::IDENTITY,
+            dirty: true
+        };
+        let id = match self.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        };
+        match parent.and_then(|parent| self.get_mut(parent)) {
+            Some(parent) => parent.children.push(id),
+            None => self.roots.push(id)
+        }
+        Ok(id)
+    }
+    pub fn get(&self, id: NodeId) -> Option<&Node> {
+        let slot = self.slots.get(id.index as usize)?;
+        slot.node.as_ref().filter(|_| slot.generation == id.generation)
+    }
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        slot.node.as_mut().filter(|_| slot.generation == id.generation)
+    }
+    fn node(&self, id: NodeId) -> Result<&Node, SceneError> {
+        self.get(id).ok_or(SceneError::MissingNode(id))
+    }
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free_slots.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Every node, parents before their children.
+    pub fn traverse(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            let node = self.get(id).expect("Fail to find child node.");
+            stack.extend(node.children.iter().rev());
+            Some((id, node))
+        })
+    }
+    /// The first node called `name` in traversal order.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.traverse().find(|(_, node)| node.name == name).map(|(id, _)| id)
+    }
+    /// Moves `id` with its subtree under `parent`, or to the roots, keeping
+    /// its local transform.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        let old_parent = self.node(id)?.parent;
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                if current == id {
+                    return Err(SceneError::Cycle);
+                }
+                ancestor = self.node(current)?.parent;
+            }
+        }
+        self.detach(id, old_parent);
+        match parent {
+            Some(parent) => self.get_mut(parent).unwrap().children.push(id),
+            None => self.roots.push(id)
+        }
+        let node = self.get_mut(id).unwrap();
+        node.parent = parent;
+        node.dirty = true;
+        Ok(())
+    }
+    fn detach(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let siblings = match parent {
+            Some(parent) => &mut self.get_mut(parent).unwrap().children,
+            None => &mut self.roots
+        };
+        siblings.retain(|sibling| *sibling != id);
+    }
+    /// Deletes `id` and its subtree, returning how many nodes were deleted.
+    pub fn remove(&mut self, id: NodeId) -> Result<usize, SceneError> {
+        let parent = self.node(id)?.parent;
+        self.detach(id, parent);
+        let mut stack = vec![id];
+        let mut removed = 0;
+        while let Some(current) = stack.pop() {
+            let slot = &mut self.slots[current.index as usize];
+            let node = slot.node.take().expect("Fail to find child node.");
+            slot.generation += 1;
+            self.free_slots.push(current.index);
+            stack.extend(node.children);
+            removed += 1;
+        }
+        Ok(removed)
+    }
+    /// Recomputes the world matrices of dirty nodes and their descendants.
+    pub fn update_world_transforms(&mut self) {
+        let mut stack: Vec<(NodeId, Mat4, bool)> = self.roots.iter()
+            .map(|root| (*root, Mat4::IDENTITY, false))
+            .collect();
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = self.get_mut(id).expect("Fail to find child node.");
+            let changed = parent_changed || node.dirty;
+            if changed {
+                node.world = parent_world * node.transform.matrix();
+                node.dirty = false;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().map(|child| (*child, world, changed)));
+        }
+    }
+    /// Updates the world matrices, then lists every mesh to draw.
+    pub fn draw_list(&mut self) -> Vec<MeshInstance> {
+        self.update_world_transforms();
+        self.traverse()
+            .filter_map(|(_, node)| Some(MeshInstance {
+                mesh: node.mesh.clone()?,
+                material: node.material.clone().unwrap_or_else(|| self.default_material.clone()),
+                transform: node.world
+            }))
+            .collect()
+    }
+    /// Updates the world matrices, then gathers the lights in world space.
+    pub fn lights(&mut self) -> Lights {
+        self.update_world_transforms();
+        let mut lights = Lights {
+            ambient: self.ambient,
+            directional: None,
+            points: Vec::new(),
+            spots: Vec::new()
+        };
+        for (_, node) in self.traverse() {
+            let world = node.world;
+            match node.light {
+                Some(LightComponent::Directional(light)) => {
+                    lights.directional.get_or_insert(DirectionalLight {
+                        direction: world.transform_vector3(light.direction).normalize(),
+                        ..light
+                    });
+                }
+                Some(LightComponent::Point(light)) => lights.points.push(PointLight {
+                    position: world.transform_point3(light.position),
+                    ..light
+                }),
+                Some(LightComponent::Spot(light)) => lights.spots.push(SpotLight {
+                    position: world.transform_point3(light.position),
+                    direction: world.transform_vector3(light.direction).normalize(),
+                    ..light
+                }),
+                None => ()
+            }
+        }
+        lights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx_eq(a: Vec3, b: Vec3) -> bool {
+        (a - b).abs().max_element() < 1e-5
+    }
+
+    fn world_position(scene: &Scene, id: NodeId) -> Vec3 {
+        scene.get(id).unwrap().world_transform().transform_point3(Vec3::ZERO)
+    }
+
+    /// A root at x = 1 rotated a quarter turn around Y, its child one unit
+    /// along its X axis and a grandchild one unit above the child.
+    fn chain() -> (Scene, [NodeId; 3]) {
+        let mut scene = Scene::default();
+        let root = scene.add(None, "root", Transform {
+            translation: Vec3::X,
+            rotation: Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            ..Default::default()
+        }).unwrap();
+        let child = scene.add(Some(root), "child", Transform::from_translation(Vec3::X)).unwrap();
+        let grandchild = scene.add(Some(child), "grandchild", Transform::from_translation(Vec3::Y)).unwrap();
+        (scene, [root, child, grandchild])
+    }
+
+    #[test]
+    fn world_transforms_compose_down_the_hierarchy() {
+        let (mut scene, [_, child, grandchild]) = chain();
+        scene.update_world_transforms();
+        assert!(approx_eq(world_position(&scene, child), Vec3::new(1.0, 0.0, -1.0)));
+        assert!(approx_eq(world_position(&scene, grandchild), Vec3::new(1.0, 1.0, -1.0)));
+    }
+
+    #[test]
+    fn moving_a_parent_updates_its_descendants() {
+        let (mut scene, [root, _, grandchild]) = chain();
+        scene.update_world_transforms();
+        scene.get_mut(root).unwrap().set_transform(Transform::from_translation(Vec3::Z));
+        scene.update_world_transforms();
+        assert!(approx_eq(world_position(&scene, grandchild), Vec3::new(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn reparenting_keeps_the_local_transform_and_rejects_cycles() {
+        let (mut scene, [root, child, grandchild]) = chain();
+        assert_eq!(scene.set_parent(root, Some(grandchild)), Err(SceneError::Cycle));
+        scene.set_parent(grandchild, None).unwrap();
+        scene.update_world_transforms();
+        assert_eq!(scene.roots(), [root, grandchild]);
+        assert!(scene.get(child).unwrap().children().is_empty());
+        assert!(approx_eq(world_position(&scene, grandchild), Vec3::Y));
+    }
+
+    #[test]
+    fn removing_a_subtree_invalidates_its_handles() {
+        let (mut scene, [root, child, grandchild]) = chain();
+        assert_eq!(scene.remove(child), Ok(2));
+        assert_eq!(scene.len(), 1);
+        assert!(scene.get(grandchild).is_none());
+        assert_eq!(scene.remove(child), Err(SceneError::MissingNode(child)));
+        assert_eq!(scene.add(Some(child), "orphan", Transform::default()), Err(SceneError::MissingNode(child)));
+        assert_eq!(scene.len(), 1);
+        let reused = scene.add(Some(root), "child", Transform::default()).unwrap();
+        assert!(scene.get(child).is_none());
+        assert_eq!(scene.find("child"), Some(reused));
+        assert_eq!(scene.find("grandchild"), None);
+    }
+
+    #[test]
+    fn lights_follow_their_nodes() {
+        let (mut scene, [_, child, _]) = chain();
+        scene.get_mut(child).unwrap().light = Some(LightComponent::Spot(SpotLight {
+            position: Vec3::ZERO,
+            direction: Vec3::X,
+            ..Default::default()
+        }));
+        let lights = scene.lights();
+        assert!(lights.directional.is_none());
+        assert!(approx_eq(lights.spots[0].position, Vec3::new(1.0, 0.0, -1.0)));
+        assert!(approx_eq(lights.spots[0].direction, Vec3::NEG_Z));
+    }
+}
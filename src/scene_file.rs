@@ -0,0 +1,702 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime}
+};
+
+use ahash::HashMap;
+
+use glam::Quat;
+
+use ron::{extensions::Extensions, ser::PrettyConfig};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use vulkano::{
+    device::Queue,
+    format::Format
+};
+
+use crate::{
+    allocator::Allocator,
+    camera::Camera,
+    config::Config,
+    lighting::{DirectionalLight, PointLight, SpotLight},
+    material::Material,
+    model::{GpuMesh, Mesh},
+    renderer::RenderPath,
+    scene::{LightComponent, NodeId, Scene, Transform},
+    texture
+};
+
+/// Version written by [`SceneFile::to_ron`].
+pub const SCENE_VERSION: u32 = 1;
+
+/// How often [`FileWatch`] looks at the modification time.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Texture(png::DecodingError),
+    /// The file was written by a newer version of the format.
+    UnsupportedVersion(u32),
+    /// A mesh, material or texture referenced by the scene failed to load.
+    Asset(PathBuf, Box<SceneFileError>)
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io(error) => write!(f, "{error}"),
+            SceneFileError::Parse(error) => write!(f, "{error}"),
+            SceneFileError::Texture(error) => write!(f, "{error}"),
+            SceneFileError::UnsupportedVersion(version) => {
+                write!(f, "version {version} is not supported, the latest is {SCENE_VERSION}")
+            }
+            SceneFileError::Asset(path, error) => write!(f, "{}: {error}", path.display())
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+impl From<io::Error> for SceneFileError {
+    fn from(error: io::Error) -> Self {
+        SceneFileError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for SceneFileError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        SceneFileError::Parse(error)
+    }
+}
+
+/// Accepts `Option` fields without the `Some(..)` around them.
+fn ron_options() -> ron::Options {
+    ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME)
+}
+
+/// Reads the RON file at `path`, wrapping any error with the path.
+fn read_asset<T: DeserializeOwned>(path: &Path) -> Result<T, SceneFileError> {
+    let parse = || -> Result<T, SceneFileError> {
+        Ok(ron_options().from_str(&fs::read_to_string(path)?)?)
+    };
+    parse().map_err(|error| SceneFileError::Asset(path.to_path_buf(), Box::new(error)))
+}
+
+/// A [`Camera`] with the field of view in degrees.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraDesc {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    pub fov_y_degrees: f32,
+    pub near: f32,
+    pub far: f32
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Camera::default().into()
+    }
+}
+
+impl From<Camera> for CameraDesc {
+    fn from(camera: Camera) -> Self {
+        CameraDesc {
+            position: camera.position.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+            fov_y_degrees: camera.fov_y.to_degrees(),
+            near: camera.near,
+            far: camera.far
+        }
+    }
+}
+
+impl From<CameraDesc> for Camera {
+    fn from(camera: CameraDesc) -> Self {
+        Camera {
+            position: camera.position.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+            fov_y: camera.fov_y_degrees.to_radians(),
+            near: camera.near,
+            far: camera.far
+        }
+    }
+}
+
+/// The [`Config`] settings a scene chooses.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub render_path: RenderPath,
+    pub shadows: bool,
+    pub ssao: bool,
+    pub bloom: bool,
+    pub skybox: bool,
+    pub environment_intensity: f32
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings::from_config(&Config::default())
+    }
+}
+
+impl RenderSettings {
+    pub fn from_config(config: &Config) -> Self {
+        RenderSettings {
+            render_path: config.render_path,
+            shadows: config.shadows.enabled,
+            ssao: config.ssao.enabled,
+            bloom: config.bloom.enabled,
+            skybox: config.skybox,
+            environment_intensity: config.environment_intensity
+        }
+    }
+    pub fn apply(&self, config: &mut Config) {
+        config.render_path = self.render_path;
+        config.shadows.enabled = self.shadows;
+        config.ssao.enabled = self.ssao;
+        config.bloom.enabled = self.bloom;
+        config.skybox = self.skybox;
+        config.environment_intensity = self.environment_intensity;
+    }
+}
+
+/// A [`LightComponent`] with angles in degrees.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightDesc {
+    Directional {
+        direction: [f32; 3],
+        color: [f32; 3],
+        intensity: f32,
+        cast_shadows: bool
+    },
+    Point {
+        position: [f32; 3],
+        color: [f32; 3],
+        intensity: f32,
+        range: f32
+    },
+    Spot {
+        position: [f32; 3],
+        direction: [f32; 3],
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+        inner_angle_degrees: f32,
+        outer_angle_degrees: f32,
+        cast_shadows: bool
+    }
+}
+
+impl From<LightComponent> for LightDesc {
+    fn from(light: LightComponent) -> Self {
+        match light {
+            LightComponent::Directional(light) => LightDesc::Directional {
+                direction: light.direction.into(),
+                color: light.color.into(),
+                intensity: light.intensity,
+                cast_shadows: light.cast_shadows
+            },
+            LightComponent::Point(light) => LightDesc::Point {
+                position: light.position.into(),
+                color: light.color.into(),
+                intensity: light.intensity,
+                range: light.range
+            },
+            LightComponent::Spot(light) => LightDesc::Spot {
+                position: light.position.into(),
+                direction: light.direction.into(),
+                color: light.color.into(),
+                intensity: light.intensity,
+                range: light.range,
+                inner_angle_degrees: light.inner_angle.to_degrees(),
+                outer_angle_degrees: light.outer_angle.to_degrees(),
+                cast_shadows: light.cast_shadows
+            }
+        }
+    }
+}
+
+impl From<LightDesc> for LightComponent {
+    fn from(light: LightDesc) -> Self {
+        match light {
+            LightDesc::Directional { direction, color, intensity, cast_shadows } => {
+                LightComponent::Directional(DirectionalLight {
+                    direction: direction.into(),
+                    color: color.into(),
+                    intensity,
+                    cast_shadows
+                })
+            }
+            LightDesc::Point { position, color, intensity, range } => LightComponent::Point(PointLight {
+                position: position.into(),
+                color: color.into(),
+                intensity,
+                range
+            }),
+            LightDesc::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                range,
+                inner_angle_degrees,
+                outer_angle_degrees,
+                cast_shadows
+            } => LightComponent::Spot(SpotLight {
+                position: position.into(),
+                direction: direction.into(),
+                color: color.into(),
+                intensity,
+                range,
+                inner_angle: inner_angle_degrees.to_radians(),
+                outer_angle: outer_angle_degrees.to_radians(),
+                cast_shadows
+            })
+        }
+    }
+}
+
+/// A node and its subtree. Asset paths are relative to the scene file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeDesc {
+    pub name: String,
+    pub translation: [f32; 3],
+    /// Quaternion as `(x, y, z, w)`.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    /// A [`MeshAsset`] file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<String>,
+    /// A [`MaterialAsset`] file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub light: Option<LightDesc>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NodeDesc>
+}
+
+impl Default for NodeDesc {
+    fn default() -> Self {
+        NodeDesc {
+            name: String::new(),
+            translation: [0.0; 3],
+            rotation: Quat::IDENTITY.into(),
+            scale: [1.0; 3],
+            mesh: None,
+            material: None,
+            light: None,
+            children: Vec::new()
+        }
+    }
+}
+
+impl NodeDesc {
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.translation.into(),
+            rotation: Quat::from_array(self.rotation).normalize(),
+            scale: self.scale.into()
+        }
+    }
+}
+
+/// A primitive mesh, as stored in a mesh file.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MeshAsset {
+    Plane { half_size: f32 },
+    Cube { half_extent: f32 },
+    Sphere { radius: f32, segments: u32, rings: u32 }
+}
+
+impl MeshAsset {
+    pub fn mesh(&self) -> Mesh {
+        let white = [1.0; 3];
+        match *self {
+            MeshAsset::Plane { half_size } => Mesh::plane(half_size, white),
+            MeshAsset::Cube { half_extent } => Mesh::cube(half_extent, white),
+            MeshAsset::Sphere { radius, segments, rings } => Mesh::sphere(radius, segments, rings, white)
+        }
+    }
+}
+
+/// A [`Material`] as stored in a material file, with PNG textures relative
+/// to it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialAsset {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub base_color_texture: Option<String>,
+    pub normal_texture: Option<String>,
+    pub metallic_roughness_texture: Option<String>,
+    pub occlusion_texture: Option<String>,
+    pub emissive_texture: Option<String>
+}
+
+impl Default for MaterialAsset {
+    fn default() -> Self {
+        let material = Material::default();
+        MaterialAsset {
+            base_color: material.base_color,
+            metallic: material.metallic,
+            roughness: material.roughness,
+            emissive: material.emissive,
+            normal_scale: material.normal_scale,
+            occlusion_strength: material.occlusion_strength,
+            base_color_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None
+        }
+    }
+}
+
+impl MaterialAsset {
+    /// Decodes and uploads the textures.
+    pub fn load(&self, allocator: &Allocator, queue: &Arc<Queue>, directory: &Path) -> Result<Material, SceneFileError> {
+        let slots = [
+            (&self.base_color_texture, Format::R8G8B8A8_SRGB),
+            (&self.normal_texture, Format::R8G8B8A8_UNORM),
+            (&self.metallic_roughness_texture, Format::R8G8B8A8_UNORM),
+            (&self.occlusion_texture, Format::R8G8B8A8_UNORM),
+            (&self.emissive_texture, Format::R8G8B8A8_SRGB)
+        ];
+        let mut textures = Vec::new();
+        for (path, format) in slots {
+            let Some(path) = path else {
+                textures.push(None);
+                continue;
+            };
+            let path = directory.join(path);
+            let data = texture::load_png(&path)
+                .map_err(|error| SceneFileError::Asset(path.clone(), Box::new(SceneFileError::Texture(error))))?;
+            textures.push(Some((data, format, path)));
+        }
+        let mut views = texture::upload_now(allocator, queue, |builder| {
+            textures.iter()
+                .map(|texture| texture.as_ref().map(|(data, format, path)| {
+                    texture::record_upload(builder, allocator, data, *format, &path.to_string_lossy())
+                }))
+                .collect::<Vec<_>>()
+        }).into_iter();
+        Ok(Material {
+            base_color: self.base_color,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            emissive: self.emissive,
+            normal_scale: self.normal_scale,
+            occlusion_strength: self.occlusion_strength,
+            base_color_texture: views.next().flatten(),
+            normal_texture: views.next().flatten(),
+            metallic_roughness_texture: views.next().flatten(),
+            occlusion_texture: views.next().flatten(),
+            emissive_texture: views.next().flatten()
+        })
+    }
+}
+
+/// Assets already uploaded while instantiating a scene.
+struct AssetCache<'a> {
+    allocator: &'a Allocator,
+    queue: &'a Arc<Queue>,
+    directory: &'a Path,
+    meshes: HashMap<String, GpuMesh>,
+    materials: HashMap<String, Arc<Material>>
+}
+
+impl AssetCache<'_> {
+    fn mesh(&mut self, path: &str) -> Result<GpuMesh, SceneFileError> {
+        if let Some(mesh) = self.meshes.get(path) {
+            return Ok(mesh.clone());
+        }
+        let asset: MeshAsset = read_asset(&self.directory.join(path))?;
+        let mesh = asset.mesh().upload(self.allocator);
+        self.meshes.insert(path.to_string(), mesh.clone());
+        Ok(mesh)
+    }
+    fn material(&mut self, path: &str) -> Result<Arc<Material>, SceneFileError> {
+        if let Some(material) = self.materials.get(path) {
+            return Ok(material.clone());
+        }
+        let path_on_disk = self.directory.join(path);
+        let asset: MaterialAsset = read_asset(&path_on_disk)?;
+        let directory = path_on_disk.parent().unwrap_or(Path::new(""));
+        let material = Arc::new(asset.load(self.allocator, self.queue, directory)?);
+        self.materials.insert(path.to_string(), material.clone());
+        Ok(material)
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    /// Files without a version are read as the first one.
+    #[serde(default = "first_version")]
+    version: u32
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// Parses `text`, written with format `version`. Older versions get an arm
+/// here when [`SCENE_VERSION`] is bumped.
+fn migrate(version: u32, text: &str) -> Result<SceneFile, SceneFileError> {
+    match version {
+        SCENE_VERSION => Ok(ron_options().from_str(text)?),
+        version => Err(SceneFileError::UnsupportedVersion(version))
+    }
+}
+
+/// A whole scene as a RON file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneFile {
+    pub version: u32,
+    pub camera: CameraDesc,
+    pub settings: RenderSettings,
+    pub ambient: [f32; 3],
+    pub nodes: Vec<NodeDesc>
+}
+
+impl Default for SceneFile {
+    fn default() -> Self {
+        SceneFile {
+            version: SCENE_VERSION,
+            camera: CameraDesc::default(),
+            settings: RenderSettings::default(),
+            ambient: Scene::default().ambient.into(),
+            nodes: Vec::new()
+        }
+    }
+}
+
+impl SceneFile {
+    /// Parses a scene of any supported version, migrating older ones.
+    pub fn from_ron(text: &str) -> Result<Self, SceneFileError> {
+        let options = ron_options();
+        let probe: VersionProbe = options.from_str(text)?;
+        migrate(probe.version, text)
+    }
+    pub fn load(path: &Path) -> Result<Self, SceneFileError> {
+        Self::from_ron(&fs::read_to_string(path)?)
+    }
+    pub fn to_ron(&self) -> String {
+        let config = PrettyConfig::new().extensions(Extensions::IMPLICIT_SOME);
+        ron::ser::to_string_pretty(self, config).expect("Fail to serialize scene.")
+    }
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_ron())
+    }
+    /// Describes `scene` as seen through `camera` with the settings of
+    /// `config`, leaving out assets not loaded from a file.
+    pub fn capture(scene: &Scene, camera: &Camera, config: &Config) -> Self {
+        fn describe(scene: &Scene, id: NodeId) -> NodeDesc {
+            let node = scene.get(id).expect("Fail to find child node.");
+            if node.mesh.is_some() && node.mesh_asset.is_none() {
+                log::warn!("Mesh of node {} has no file, saving the node without it.", node.name);
+            }
+            if node.material.is_some() && node.material_asset.is_none() {
+                log::warn!("Material of node {} has no file, saving the node without it.", node.name);
+            }
+            let transform = node.transform();
+            NodeDesc {
+                name: node.name.clone(),
+                translation: transform.translation.into(),
+                rotation: transform.rotation.into(),
+                scale: transform.scale.into(),
+                mesh: node.mesh_asset.clone().filter(|_| node.mesh.is_some()),
+                material: node.material_asset.clone().filter(|_| node.material.is_some()),
+                light: node.light.map(LightDesc::from),
+                children: node.children().iter().map(|child| describe(scene, *child)).collect()
+            }
+        }
+        SceneFile {
+            version: SCENE_VERSION,
+            camera: (*camera).into(),
+            settings: RenderSettings::from_config(config),
+            ambient: scene.ambient.into(),
+            nodes: scene.roots().iter().map(|root| describe(scene, *root)).collect()
+        }
+    }
+    /// Builds the scene, loading its assets relative to `directory`.
+    pub fn instantiate(&self, allocator: &Allocator, queue: &Arc<Queue>, directory: &Path) -> Result<Scene, SceneFileError> {
+        let mut assets = AssetCache {
+            allocator,
+            queue,
+            directory,
+            meshes: HashMap::default(),
+            materials: HashMap::default()
+        };
+        let mut scene = Scene::default();
+        scene.ambient = self.ambient.into();
+        let mut stack: Vec<(Option<NodeId>, &NodeDesc)> = self.nodes.iter().rev().map(|node| (None, node)).collect();
+        while let Some((parent, desc)) = stack.pop() {
+            let id = scene.add(parent, &desc.name, desc.transform())
+                .expect("Parents are added before their children.");
+            let node = scene.get_mut(id).unwrap();
+            if let Some(path) = &desc.mesh {
+                node.mesh = Some(assets.mesh(path)?);
+                node.mesh_asset = Some(path.clone());
+            }
+            if let Some(path) = &desc.material {
+                node.material = Some(assets.material(path)?);
+                node.material_asset = Some(path.clone());
+            }
+            node.light = desc.light.map(LightComponent::from);
+            stack.extend(desc.children.iter().rev().map(|child| (Some(id), child)));
+        }
+        Ok(scene)
+    }
+}
+
+/// Polls the modification time of a file.
+pub struct FileWatch {
+    pub path: PathBuf,
+    pub interval: Duration,
+    modified: Option<SystemTime>,
+    last_poll: Instant
+}
+
+impl FileWatch {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = Self::modified_time(&path);
+        FileWatch { path, interval: WATCH_INTERVAL, modified, last_poll: Instant::now() }
+    }
+    fn modified_time(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+    /// Returns `true` once per modification.
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < self.interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        let modified = Self::modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+    /// Takes the current state of the file as seen, e.g. after writing it.
+    pub fn reset(&mut self) {
+        self.modified = Self::modified_time(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    fn manifest_path(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+    }
+
+    fn sample() -> SceneFile {
+        SceneFile {
+            settings: RenderSettings { render_path: RenderPath::Deferred, ssao: false, ..Default::default() },
+            nodes: vec![NodeDesc {
+                name: String::from("cube"),
+                translation: [0.0, 1.0, 0.0],
+                rotation: Quat::from_rotation_y(0.5).into(),
+                mesh: Some(String::from("cube.ron")),
+                children: vec![NodeDesc {
+                    name: String::from("lamp"),
+                    light: Some(LightDesc::Point { position: [0.0; 3], color: [1.0; 3], intensity: 2.0, range: 4.0 }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scene_round_trips_through_ron() {
+        let file = sample();
+        let text = file.to_ron();
+        assert!(text.contains("mesh: \"cube.ron\""), "{text}");
+        assert_eq!(SceneFile::from_ron(&text).unwrap(), file);
+    }
+
+    #[test]
+    fn files_without_a_version_use_the_first_one() {
+        let file = SceneFile::from_ron("(nodes: [(name: \"arm\")])").unwrap();
+        assert_eq!(file.version, 1);
+        assert_eq!(file.nodes[0].name, "arm");
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let error = SceneFile::from_ron("(version: 2)").unwrap_err();
+        assert!(matches!(error, SceneFileError::UnsupportedVersion(2)));
+        let error = SceneFile::from_ron("(version: 0)").unwrap_err();
+        assert!(matches!(error, SceneFileError::UnsupportedVersion(0)));
+    }
+
+    #[test]
+    fn capture_keeps_the_hierarchy_and_lights() {
+        let mut scene = Scene::default();
+        let parent = scene.add(None, "parent", Transform::from_translation(Vec3::X)).unwrap();
+        let child = scene.add(Some(parent), "child", Transform::default()).unwrap();
+        scene.get_mut(child).unwrap().light = Some(LightComponent::Spot(SpotLight::default()));
+        let file = SceneFile::capture(&scene, &Camera::default(), &Config::default());
+        assert_eq!(file.nodes.len(), 1);
+        assert_eq!(file.nodes[0].translation, [1.0, 0.0, 0.0]);
+        let light = file.nodes[0].children[0].light.unwrap();
+        assert_eq!(LightComponent::from(light), LightComponent::Spot(SpotLight::default()));
+    }
+
+    #[test]
+    fn shipped_scenes_and_their_assets_parse() {
+        let directory = manifest_path("assets/scenes");
+        for entry in fs::read_dir(&directory).expect("Fail to list scenes.") {
+            let path = entry.unwrap().path();
+            let file = SceneFile::load(&path).unwrap_or_else(|error| panic!("{path:?}: {error}"));
+            let mut stack: Vec<_> = file.nodes.iter().collect();
+            while let Some(node) = stack.pop() {
+                if let Some(mesh) = &node.mesh {
+                    read_asset::<MeshAsset>(&directory.join(mesh)).unwrap_or_else(|error| panic!("{error}"));
+                }
+                if let Some(material) = &node.material {
+                    read_asset::<MaterialAsset>(&directory.join(material)).unwrap_or_else(|error| panic!("{error}"));
+                }
+                stack.extend(&node.children);
+            }
+        }
+    }
+
+    #[test]
+    fn file_watch_reports_each_modification_once() {
+        let path = std::env::temp_dir().join(format!("learn_vulkano_watch_{}.ron", std::process::id()));
+        fs::write(&path, "()").unwrap();
+        let mut watch = FileWatch { interval: Duration::ZERO, ..FileWatch::new(path.clone()) };
+        assert!(!watch.changed());
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        assert!(watch.changed());
+        assert!(!watch.changed());
+        fs::remove_file(&path).unwrap();
+        assert!(!watch.changed());
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    sync::Arc,
+    fs::File,
+    io::Read,
+    path::Path
+};
+
+use vulkano::{
+    device::Device,
+    shader::{ShaderModule, ShaderModuleCreateInfo}
+};
+
+use crate::debug;
+
+pub const SHADER_DIRECTORY: &str = "shaders";
+
+/// Path of a compiled shader inside the `shaders` directory.
+pub fn shader_path(file_name: &str) -> String {
+    Path::new(SHADER_DIRECTORY)
+        .join(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn read_spirv_code(device: Arc<Device>, path: String) -> Arc<ShaderModule> {
+    let mut handler = File::open(&path).expect("Fail to open the spv file.");
+    let mut bytes = Vec::new();
+    handler.read_to_end(&mut bytes).expect("Fail to read the spv file.");
+    let words = vulkano::shader::spirv::bytes_to_words(bytes.as_slice())
+        .expect("Fail to translate spir-v bytes to words.");
+    let create_info = ShaderModuleCreateInfo::new(&words);
+    let shader_module = unsafe { ShaderModule::new(device, create_info).expect("Fail to create shader module.") };
+    debug::set_object_name(&*shader_module, &path);
+    shader_module
+}
+
+pub fn load_shader(device: Arc<Device>, file_name: &str) -> Arc<ShaderModule> {
+    read_spirv_code(device, shader_path(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use vulkano::shader::{reflect, spirv::Spirv};
+
+    use super::*;
+
+    /// Every compiled shader shipped in the shaders directory must parse and
+    /// expose a `main` entry point, which is what the pipelines look up.
+    #[test]
+    fn compiled_shaders_reflect() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join(SHADER_DIRECTORY);
+        let mut checked = 0;
+        for entry in fs::read_dir(directory).expect("Fail to list shaders.") {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("spv") {
+                continue;
+            }
+            let bytes = fs::read(&path).unwrap();
+            let words = vulkano::shader::spirv::bytes_to_words(&bytes).unwrap();
+            let spirv = Spirv::new(&words).unwrap_or_else(|error| panic!("{path:?}: {error}"));
+            let has_main = reflect::entry_points(&spirv).any(|(_, info)| info.name == "main");
+            assert!(has_main, "{path:?} has no main entry point");
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+}
@@ -0,0 +1,584 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3, Vec3Swizzles, camera::rh::{proj::vulkan, view}};
+
+use vulkano::{
+    device::{Device, DeviceOwned},
+    buffer::BufferContents,
+    command_buffer::{
+        AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo,
+        SubpassEndInfo
+    },
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    format::FormatFeatures,
+    image::{
+        Image, ImageCreateInfo, ImageLayout, ImageType, ImageUsage,
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        Pipeline, PipelineBindPoint,
+        graphics::{
+            GraphicsPipeline,
+            depth_stencil::CompareOp,
+            rasterization::CullMode,
+            viewport::Viewport
+        }
+    },
+    render_pass::{
+        AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp,
+        Framebuffer, FramebufferCreateInfo, RenderPass, RenderPassCreateInfo, Subpass, SubpassDescription
+    }
+};
+
+use smallvec::SmallVec;
+
+use crate::{
+    debug,
+    post_process,
+    allocator::Allocator,
+    camera::Camera,
+    lighting::{Lights, SpotLight},
+    model::MeshInstance,
+    renderer::{self, Renderer},
+    render_target::DEFAULT_DEPTH_FORMAT
+};
+
+pub const MAX_CASCADES: usize = 4;
+/// Shadow-casting spot lights beyond this count are lit without shadows.
+pub const MAX_SPOT_SHADOWS: usize = 4;
+/// Cascades plus one view per shadow-casting spot light.
+pub const MAX_SHADOW_VIEWS: usize = MAX_CASCADES + MAX_SPOT_SHADOWS;
+
+const SHADOW_LABEL_COLOR: [f32; 4] = [0.35, 0.35, 0.45, 1.0];
+/// How far towards the light a cascade extends past its bounding sphere,
+/// in radii.
+const CASTER_DEPTH_SCALE: f32 = 3.0;
+const SPOT_NEAR: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowConfig {
+    pub enabled: bool,
+    /// Width and height of the shadow atlas, in texels.
+    pub atlas_size: u32,
+    /// Cascades of the directional light, at most [`MAX_CASCADES`].
+    pub cascade_count: u32,
+    /// Blend between uniform (0.0) and logarithmic (1.0) cascade splits.
+    pub cascade_split_lambda: f32,
+    /// View distance covered by the cascades.
+    pub max_distance: f32,
+    /// Depth bias of the shadow pass, constant and scaled by the slope.
+    pub depth_bias_constant: f32,
+    pub depth_bias_slope: f32,
+    /// World-space offset along the surface normal before the lookup.
+    pub normal_bias: f32,
+    /// PCF kernel radius in texels; 0 takes a single filtered tap.
+    pub pcf_radius: u32
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        ShadowConfig {
+            enabled: true,
+            atlas_size: 4096,
+            cascade_count: 4,
+            cascade_split_lambda: 0.75,
+            max_distance: 30.0,
+            depth_bias_constant: 1.25,
+            depth_bias_slope: 1.75,
+            normal_bias: 0.02,
+            pcf_radius: 1
+        }
+    }
+}
+
+/// A square region of the shadow atlas, in texels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasTile {
+    pub offset: [u32; 2],
+    pub size: u32
+}
+
+impl AtlasTile {
+    /// Offset and size in atlas texture coordinates.
+    pub fn uv_rect(&self, atlas_size: u32) -> [f32; 4] {
+        let scale = 1.0 / atlas_size as f32;
+        [
+            self.offset[0] as f32 * scale,
+            self.offset[1] as f32 * scale,
+            self.size as f32 * scale,
+            self.size as f32 * scale
+        ]
+    }
+    fn viewport(&self) -> Viewport {
+        Viewport {
+            offset: [self.offset[0] as f32, self.offset[1] as f32],
+            extent: [self.size as f32; 2],
+            ..Default::default()
+        }
+    }
+}
+
+/// Splits the atlas into a square grid of at least `count` tiles.
+pub fn atlas_tiles(count: usize, atlas_size: u32) -> Vec<AtlasTile> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let columns = (count as f32).sqrt().ceil() as u32;
+    let size = atlas_size / columns;
+    (0..count as u32)
+        .map(|i| AtlasTile {
+            offset: [(i % columns) * size, (i / columns) * size],
+            size
+        })
+        .collect()
+}
+
+/// Far distances of `count` cascades between `near` and `far`, blending
+/// logarithmic and uniform splits by `lambda`.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+fn light_up(direction: Vec3) -> Vec3 {
+    if direction.normalize().y.abs() > 0.99 { Vec3::Z } else { Vec3::Y }
+}
+
+/// An orthographic light projection enclosing the slice of the camera
+/// frustum between `near` and `far`, snapped to whole texels against
+/// shimmering.
+pub fn cascade_view_projection(
+    camera: &Camera,
+    aspect_ratio: f32,
+    near: f32,
+    far: f32,
+    direction: Vec3,
+    resolution: u32
+) -> Mat4 {
+    let tan_y = (camera.fov_y * 0.5).tan();
+    let tan_x = tan_y * aspect_ratio;
+    let inverse_view = camera.view().inverse();
+    let corners: Vec<Vec3> = [near, far].iter()
+        .flat_map(|distance| [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+            inverse_view.transform_point3(Vec3::new(x * tan_x * distance, y * tan_y * distance, -distance))
+        }))
+        .collect();
+    let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+    let radius = corners.iter().map(|corner| corner.distance(center)).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let light_view = view::look_at_mat4(center, center + direction, light_up(direction));
+    let mut projection = vulkan::orthographic(-radius, radius, -radius, radius, -radius * CASTER_DEPTH_SCALE, radius);
+    let origin = (projection * light_view).project_point3(Vec3::ZERO).xy();
+    let texel = 2.0 / resolution as f32;
+    let offset = (origin / texel).round() * texel - origin;
+    projection.w_axis.x += offset.x;
+    projection.w_axis.y += offset.y;
+    projection * light_view
+}
+
+/// A perspective projection covering the cone of `light`.
+pub fn spot_view_projection(light: &SpotLight) -> Mat4 {
+    let light_view = view::look_at_mat4(light.position, light.position + light.direction, light_up(light.direction));
+    let fov = (light.outer_angle * 2.0).min(179f32.to_radians());
+    vulkan::perspective(fov, 1.0, SPOT_NEAR, light.range.max(SPOT_NEAR * 2.0)) * light_view
+}
+
+/// One light view rendered into the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowView {
+    pub view_projection: Mat4,
+    pub tile: AtlasTile
+}
+
+/// The shadow views of one frame and which light each belongs to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShadowPlan {
+    pub views: Vec<ShadowView>,
+    /// First view of the directional light's cascades.
+    pub directional: Option<usize>,
+    /// View of each spot light, in the order of `Lights::spots`.
+    pub spots: Vec<Option<usize>>,
+    /// Camera distance at which each cascade ends.
+    pub cascade_splits: Vec<f32>
+}
+
+impl ShadowConfig {
+    /// Assigns atlas tiles and projections to every shadow view of `lights`.
+    pub fn plan(&self, lights: &Lights, camera: &Camera, extent: [u32; 2]) -> ShadowPlan {
+        let mut plan = ShadowPlan::default();
+        if !self.enabled {
+            return plan;
+        }
+        let directional = lights.directional.filter(|light| light.cast_shadows);
+        let cascade_count = if directional.is_some() { (self.cascade_count as usize).clamp(1, MAX_CASCADES) } else { 0 };
+        let spot_shadow_count = lights.spots.iter().filter(|light| light.cast_shadows).count().min(MAX_SPOT_SHADOWS);
+        let view_count = cascade_count + spot_shadow_count;
+        let mut tiles = atlas_tiles(view_count, self.atlas_size).into_iter();
+
+        if let Some(light) = directional {
+            let aspect_ratio = extent[0] as f32 / extent[1].max(1) as f32;
+            let far = camera.far.min(self.max_distance);
+            plan.cascade_splits = cascade_splits(camera.near, far, cascade_count, self.cascade_split_lambda);
+            plan.directional = Some(0);
+            let mut near = camera.near;
+            for split in plan.cascade_splits.iter() {
+                let tile = tiles.next().unwrap();
+                let view_projection = cascade_view_projection(camera, aspect_ratio, near, *split, light.direction, tile.size);
+                plan.views.push(ShadowView { view_projection, tile });
+                near = *split;
+            }
+        }
+        for light in &lights.spots {
+            if !light.cast_shadows || plan.views.len() == view_count {
+                plan.spots.push(None);
+                continue;
+            }
+            plan.spots.push(Some(plan.views.len()));
+            let tile = tiles.next().unwrap();
+            plan.views.push(ShadowView { view_projection: spot_view_projection(light), tile });
+        }
+        plan
+    }
+}
+
+/// Shadow views and sampling parameters as read by `lit.frag`.
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+pub struct ShadowUniforms {
+    pub view_projections: [[[f32; 4]; 4]; MAX_SHADOW_VIEWS],
+    pub atlas_rects: [[f32; 4]; MAX_SHADOW_VIEWS],
+    pub cascade_splits: [f32; 4],
+    /// Normal bias, PCF radius, atlas texel size and cascade count.
+    pub params: [f32; 4]
+}
+
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+struct ShadowPushConstants {
+    view_projection: [[f32; 4]; 4],
+    model: [[f32; 4]; 4]
+}
+
+/// The shadow atlas and the depth-only pass rendering it.
+pub struct ShadowMaps {
+    pub config: ShadowConfig,
+    pub render_pass: Arc<RenderPass>,
+    pub atlas: Arc<ImageView>,
+    framebuffer: Arc<Framebuffer>,
+    pipeline: Arc<GraphicsPipeline>,
+    compare_sampler: Arc<Sampler>
+}
+
+impl ShadowMaps {
+    fn new_render_pass(device: Arc<Device>) -> Arc<RenderPass> {
+        let depth_attachment = AttachmentDescription {
+            format: DEFAULT_DEPTH_FORMAT,
+            load_op: AttachmentLoadOp::Clear,
+            store_op: AttachmentStoreOp::Store,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: ImageLayout::DepthStencilReadOnlyOptimal,
+            ..Default::default()
+        };
+        let subpass_description = SubpassDescription {
+            depth_stencil_attachment: Some(AttachmentReference {
+                attachment: 0,
+                layout: ImageLayout::DepthStencilAttachmentOptimal,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let create_info = RenderPassCreateInfo {
+            attachments: vec![depth_attachment],
+            subpasses: vec![subpass_description],
+            ..Default::default()
+        };
+        let render_pass = RenderPass::new(device, create_info).expect("Fail to create shadow render pass.");
+        debug::set_object_name(&*render_pass, "shadow render pass");
+        render_pass
+    }
+    fn new_atlas(allocator: &Allocator, render_pass: Arc<RenderPass>, size: u32) -> (Arc<ImageView>, Arc<Framebuffer>) {
+        let create_info = ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: DEFAULT_DEPTH_FORMAT,
+            extent: [size, size, 1],
+            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+            ..Default::default()
+        };
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        };
+        let image = Image::new(allocator.memory_allocator.clone(), create_info, allocation_info)
+            .expect("Fail to allocate shadow atlas.");
+        debug::set_object_name(&*image, "shadow atlas");
+        let atlas = ImageView::new_default(image).expect("Fail to create shadow atlas view.");
+        let create_info = FramebufferCreateInfo {
+            attachments: vec![atlas.clone()],
+            ..Default::default()
+        };
+        let framebuffer = Framebuffer::new(render_pass, create_info).expect("Fail to create shadow framebuffer.");
+        (atlas, framebuffer)
+    }
+    fn new_compare_sampler(device: Arc<Device>) -> Arc<Sampler> {
+        let features = device.physical_device()
+            .format_properties(DEFAULT_DEPTH_FORMAT)
+            .map(|properties| properties.optimal_tiling_features)
+            .unwrap_or_default();
+        let filter = if features.contains(FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR) { Filter::Linear } else { Filter::Nearest };
+        let create_info = SamplerCreateInfo {
+            mag_filter: filter,
+            min_filter: filter,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            compare: Some(CompareOp::LessOrEqual),
+            ..Default::default()
+        };
+        Sampler::new(device, create_info).expect("Fail to create shadow sampler.")
+    }
+    pub fn new(allocator: &Allocator, config: ShadowConfig) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let render_pass = Self::new_render_pass(device.clone());
+        let (atlas, framebuffer) = Self::new_atlas(allocator, render_pass.clone(), config.atlas_size);
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        let pipeline = renderer::new_mesh_pipeline(device.clone(), subpass, "shadow_vert.spv", None, CullMode::None, true);
+        ShadowMaps {
+            config,
+            render_pass,
+            atlas,
+            framebuffer,
+            pipeline,
+            compare_sampler: Self::new_compare_sampler(device)
+        }
+    }
+    /// Replaces the settings, reallocating the atlas if its size changed.
+    pub fn set_config(&mut self, allocator: &Allocator, config: ShadowConfig) {
+        if config.atlas_size != self.config.atlas_size {
+            (self.atlas, self.framebuffer) = Self::new_atlas(allocator, self.render_pass.clone(), config.atlas_size);
+        }
+        self.config = config;
+    }
+    pub fn uniforms(&self, plan: &ShadowPlan) -> ShadowUniforms {
+        let mut uniforms = ShadowUniforms {
+            view_projections: [[[0.0; 4]; 4]; MAX_SHADOW_VIEWS],
+            atlas_rects: [[0.0; 4]; MAX_SHADOW_VIEWS],
+            cascade_splits: [0.0; 4],
+            params: [
+                self.config.normal_bias,
+                self.config.pcf_radius as f32,
+                1.0 / self.config.atlas_size as f32,
+                plan.cascade_splits.len() as f32
+            ]
+        };
+        for (i, view) in plan.views.iter().take(MAX_SHADOW_VIEWS).enumerate() {
+            uniforms.view_projections[i] = view.view_projection.to_cols_array_2d();
+            uniforms.atlas_rects[i] = view.tile.uv_rect(self.config.atlas_size);
+        }
+        for (split, distance) in uniforms.cascade_splits.iter_mut().zip(&plan.cascade_splits) {
+            *split = *distance;
+        }
+        uniforms
+    }
+    /// The atlas and its comparison sampler, from `first_binding` on.
+    pub fn sampled(&self, first_binding: u32) -> [WriteDescriptorSet; 2] {
+        [
+            WriteDescriptorSet::image_view(first_binding, self.atlas.clone()),
+            WriteDescriptorSet::sampler(first_binding + 1, self.compare_sampler.clone())
+        ]
+    }
+    /// Renders `meshes` into the tile of every view of `plan`.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        plan: &ShadowPlan,
+        meshes: &[MeshInstance]
+    ) {
+        let layout = self.pipeline.layout().clone();
+        let render_pass_begin_info = RenderPassBeginInfo {
+            clear_values: vec![Some(1.0.into())],
+            ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
+        };
+
+        debug::with_label(builder, "shadow pass", SHADOW_LABEL_COLOR, |builder| {
+            builder
+            .begin_render_pass(render_pass_begin_info, SubpassBeginInfo::default())
+            .expect("Fail to begin shadow pass.")
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .expect("Fail to bind shadow pipeline.")
+            .set_depth_bias(self.config.depth_bias_constant, 0.0, self.config.depth_bias_slope)
+            .expect("Fail to set shadow depth bias.");
+            for view in plan.views.iter() {
+                let viewports: SmallVec<[Viewport; 2]> = SmallVec::from_vec(vec![view.tile.viewport()]);
+                builder
+                .set_viewport(0, viewports)
+                .expect("Fail to set shadow viewport.");
+                for instance in meshes {
+                    let push_constants = ShadowPushConstants {
+                        view_projection: view.view_projection.to_cols_array_2d(),
+                        model: instance.transform.to_cols_array_2d()
+                    };
+                    builder
+                    .push_constants(layout.clone(), 0, push_constants)
+                    .expect("Fail to push shadow constants.");
+                    let mesh = &instance.mesh;
+                    Renderer::record_mesh(builder, mesh.vertex_buffer.clone(), mesh.index_buffer.clone(), mesh.index_count);
+                }
+            }
+            builder
+            .end_render_pass(SubpassEndInfo::default())
+            .expect("Fail to end shadow pass.");
+        });
+    }
+}
+
+/// Draws the shadow atlas into the bottom-right corner of a pass.
+pub struct ShadowDebugView {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>
+}
+
+impl ShadowDebugView {
+    pub fn new(device: Arc<Device>, subpass: Subpass) -> Self {
+        let pipeline = post_process::new_fullscreen_pipeline(device.clone(), subpass, "shadow_debug_frag.spv", None);
+        let create_info = SamplerCreateInfo {
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..Default::default()
+        };
+        let sampler = Sampler::new(device, create_info).expect("Fail to create shadow debug sampler.");
+        ShadowDebugView { pipeline, sampler }
+    }
+    /// Records the overlay into a pass of `extent`.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        shadows: &ShadowMaps,
+        extent: [u32; 2]
+    ) {
+        let layout = self.pipeline.layout().clone();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &allocator.descriptor_set_allocator,
+            layout.set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, shadows.atlas.clone()),
+                WriteDescriptorSet::sampler(1, self.sampler.clone())
+            ],
+            []
+        ).expect("Fail to create shadow debug descriptor set.");
+        let size = extent[0].min(extent[1]) as f32 / 3.0;
+        let corner = Viewport {
+            offset: [extent[0] as f32 - size, extent[1] as f32 - size],
+            extent: [size, size],
+            ..Default::default()
+        };
+        let full = Viewport {
+            extent: [extent[0] as f32, extent[1] as f32],
+            ..Default::default()
+        };
+
+        debug::with_label(builder, "shadow atlas view", SHADOW_LABEL_COLOR, |builder| {
+            builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .expect("Fail to bind shadow debug pipeline.")
+            .set_viewport(0, SmallVec::from_vec(vec![corner]))
+            .expect("Fail to set shadow debug viewport.")
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout, 0, descriptor_set)
+            .expect("Fail to bind shadow debug descriptor set.")
+            .draw(3, 1, 0, 0)
+            .expect("Fail to draw shadow atlas.")
+            .set_viewport(0, SmallVec::from_vec(vec![full]))
+            .expect("Fail to restore viewport.");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+
+    use crate::lighting::DirectionalLight;
+
+    use super::*;
+
+    #[test]
+    fn atlas_is_split_into_a_square_grid() {
+        assert!(atlas_tiles(0, 4096).is_empty());
+        assert_eq!(atlas_tiles(1, 4096), vec![AtlasTile { offset: [0, 0], size: 4096 }]);
+        let tiles = atlas_tiles(5, 4096);
+        assert_eq!(tiles.len(), 5);
+        assert_eq!(tiles[2], AtlasTile { offset: [2730, 0], size: 1365 });
+        assert_eq!(tiles[4], AtlasTile { offset: [1365, 1365], size: 1365 });
+        assert_eq!(tiles[4].uv_rect(4096)[2], 1365.0 / 4096.0);
+    }
+
+    #[test]
+    fn cascade_splits_grow_towards_far() {
+        let splits = cascade_splits(0.1, 100.0, 4, 0.75);
+        assert_eq!(splits.len(), 4);
+        assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((splits[3] - 100.0).abs() < 1e-3);
+        let uniform = cascade_splits(1.0, 9.0, 4, 0.0);
+        assert_eq!(uniform, vec![3.0, 5.0, 7.0, 9.0]);
+    }
+
+    #[test]
+    fn cascade_encloses_its_frustum_slice() {
+        let camera = Camera::default();
+        let direction = Vec3::new(-0.4, -1.0, -0.3).normalize();
+        let matrix = cascade_view_projection(&camera, 1.5, 1.0, 5.0, direction, 1024);
+        let inverse_view = camera.view().inverse();
+        let tan_y = (camera.fov_y * 0.5).tan();
+        for distance in [1.0, 5.0] {
+            for (x, y) in [(-1.0, -1.0), (1.0, 1.0), (1.0, -1.0)] {
+                let corner = inverse_view.transform_point3(Vec3::new(x * tan_y * 1.5 * distance, y * tan_y * distance, -distance));
+                let ndc = matrix.project_point3(corner);
+                assert!(ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0, "{ndc}");
+                assert!(ndc.z > 0.0 && ndc.z < 1.0, "{ndc}");
+            }
+        }
+    }
+
+    #[test]
+    fn spot_projection_centers_its_axis() {
+        let light = SpotLight::default();
+        let clip = spot_view_projection(&light) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let ndc = clip.truncate() / clip.w;
+        assert!(ndc.x.abs() < 1e-5 && ndc.y.abs() < 1e-5);
+        assert!(ndc.z > 0.0 && ndc.z < 1.0);
+    }
+
+    #[test]
+    fn plan_assigns_views_to_casters() {
+        let lights = Lights {
+            ambient: Vec3::ZERO,
+            directional: Some(DirectionalLight {
+                direction: Vec3::NEG_Y,
+                color: Vec3::ONE,
+                intensity: 1.0,
+                cast_shadows: true
+            }),
+            points: Vec::new(),
+            spots: vec![
+                SpotLight { cast_shadows: false, ..Default::default() },
+                SpotLight::default()
+            ]
+        };
+        let config = ShadowConfig { cascade_count: 3, ..Default::default() };
+        let plan = config.plan(&lights, &Camera::default(), [800, 600]);
+        assert_eq!(plan.views.len(), 4);
+        assert_eq!(plan.directional, Some(0));
+        assert_eq!(plan.spots, vec![None, Some(3)]);
+        assert_eq!(plan.cascade_splits.len(), 3);
+        assert_eq!(plan.views[3].tile.offset, [2048, 2048]);
+
+        let disabled = ShadowConfig { enabled: false, ..config };
+        assert_eq!(disabled.plan(&lights, &Camera::default(), [800, 600]), ShadowPlan::default());
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use ahash::HashSet;
+
+use glam::{Mat3, Mat4};
+
+use vulkano::{
+    device::Device,
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode},
+        view::ImageView
+    },
+    buffer::BufferContents,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    render_pass::Subpass,
+    pipeline::{
+        Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo, DynamicState,
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        graphics::{
+            GraphicsPipeline, GraphicsPipelineCreateInfo,
+            vertex_input::VertexInputState,
+            input_assembly::InputAssemblyState,
+            viewport::ViewportState,
+            rasterization::{RasterizationState, CullMode},
+            multisample::MultisampleState,
+            depth_stencil::{DepthStencilState, DepthState, CompareOp},
+            color_blend::{ColorBlendState, ColorBlendAttachmentState},
+            subpass::PipelineSubpassType
+        }
+    }
+};
+
+use smallvec::SmallVec;
+
+use crate::{
+    debug,
+    shader,
+    allocator::Allocator,
+    camera::Camera
+};
+
+const SKYBOX_LABEL_COLOR: [f32; 4] = [0.45, 0.7, 0.95, 1.0];
+
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+struct SkyboxPushConstants {
+    inverse_view_projection: [[f32; 4]; 4],
+    /// Mip level and intensity.
+    params: [f32; 4]
+}
+
+/// Draws a cube image on the far plane, behind everything else.
+pub struct Skybox {
+    pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    pub depth_tested: bool,
+    /// Mip level of the cube to show.
+    pub mip_level: f32
+}
+
+impl Skybox {
+    pub fn new(device: Arc<Device>, subpass: Subpass) -> Self {
+        let vertex_module = shader::load_shader(device.clone(), "skybox_vert.spv");
+        let fragment_module = shader::load_shader(device.clone(), "skybox_frag.spv");
+        let stages: SmallVec<[PipelineShaderStageCreateInfo; 5]> = SmallVec::from_vec(vec![
+            PipelineShaderStageCreateInfo::new(vertex_module.entry_point("main").expect("Fail to find entry point")),
+            PipelineShaderStageCreateInfo::new(fragment_module.entry_point("main").expect("Fail to find entry point"))
+        ]);
+        let pipeline_layout = {
+            let create_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                .into_pipeline_layout_create_info(device.clone())
+                .expect("Fail to reflect skybox pipeline layout.");
+            PipelineLayout::new(device.clone(), create_info).expect("Fail to create skybox pipeline layout.")
+        };
+        debug::set_object_name(&*pipeline_layout, "skybox pipeline layout");
+
+        let depth_tested = subpass.subpass_desc().depth_stencil_attachment.is_some();
+        let mut dynamic_state = HashSet::default();
+        dynamic_state.insert(DynamicState::Viewport);
+        let create_info = GraphicsPipelineCreateInfo {
+            stages,
+            vertex_input_state: Some(VertexInputState::new()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState {
+                cull_mode: CullMode::None,
+                ..Default::default()
+            }),
+            multisample_state: Some(MultisampleState::default()),
+            depth_stencil_state: depth_tested.then(|| DepthStencilState {
+                depth: Some(DepthState {
+                    write_enable: false,
+                    compare_op: CompareOp::LessOrEqual
+                }),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState::default()
+            )),
+            dynamic_state,
+            subpass: Some(PipelineSubpassType::BeginRenderPass(subpass)),
+            ..GraphicsPipelineCreateInfo::layout(pipeline_layout)
+        };
+        let pipeline = GraphicsPipeline::new(device.clone(), None, create_info)
+            .expect("Fail to create skybox pipeline.");
+        debug::set_object_name(&*pipeline, "skybox pipeline");
+        let sampler = Sampler::new(device, SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..Default::default()
+        }).expect("Fail to create skybox sampler.");
+        Skybox { pipeline, sampler, depth_tested, mip_level: 0.0 }
+    }
+    /// Maps clip space to world directions seen by `camera`.
+    pub fn inverse_view_projection(camera: &Camera, extent: [u32; 2]) -> Mat4 {
+        let aspect_ratio = extent[0] as f32 / extent[1].max(1) as f32;
+        let rotation = Mat4::from_mat3(Mat3::from_mat4(camera.view()));
+        (camera.projection(aspect_ratio) * rotation).inverse()
+    }
+    /// Records the skybox of `cube`, a cube view, scaled by `intensity`.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        extent: [u32; 2],
+        cube: Arc<ImageView>,
+        intensity: f32
+    ) {
+        let layout = self.pipeline.layout().clone();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &allocator.descriptor_set_allocator,
+            layout.set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, cube),
+                WriteDescriptorSet::sampler(1, self.sampler.clone())
+            ],
+            []
+        ).expect("Fail to create skybox descriptor set.");
+        let push_constants = SkyboxPushConstants {
+            inverse_view_projection: Self::inverse_view_projection(camera, extent).to_cols_array_2d(),
+            params: [self.mip_level, intensity, 0.0, 0.0]
+        };
+
+        debug::with_label(builder, "skybox", SKYBOX_LABEL_COLOR, |builder| {
+            builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .expect("Fail to bind skybox pipeline.")
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, descriptor_set)
+            .expect("Fail to bind skybox descriptor set.")
+            .push_constants(layout, 0, push_constants)
+            .expect("Fail to push skybox constants.")
+            .draw(3, 1, 0, 0)
+            .expect("Fail to draw skybox.");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec3, Vec4, Vec4Swizzles};
+
+    use super::*;
+
+    #[test]
+    fn screen_center_looks_along_the_camera() {
+        let camera = Camera {
+            position: Vec3::new(10.0, -4.0, 3.0),
+            target: Vec3::new(10.0, -4.0, 3.0) + Vec3::X,
+            ..Default::default()
+        };
+        let corner = Skybox::inverse_view_projection(&camera, [800, 600]) * Vec4::new(0.0, 0.0, 1.0, 1.0);
+        let direction = (corner.xyz() / corner.w).normalize();
+        assert!((direction - Vec3::X).length() < 1e-4, "{direction}");
+    }
+}
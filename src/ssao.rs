@@ -0,0 +1,479 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+
+use vulkano::{
+    device::{DeviceOwned, Queue},
+    format::Format,
+    buffer::BufferContents,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView
+    },
+    pipeline::{
+        Pipeline, PipelineBindPoint,
+        graphics::{GraphicsPipeline, rasterization::CullMode}
+    },
+    render_pass::Subpass
+};
+
+use crate::{
+    allocator::Allocator,
+    camera::Camera,
+    model::MeshInstance,
+    deferred,
+    post_process::{self, PassConstants},
+    render_target::{RenderTarget, DEFAULT_DEPTH_FORMAT},
+    renderer::{self, Renderer, RenderPath},
+    texture::{self, TextureData}
+};
+
+/// Most kernel samples `ssao.frag` reads.
+pub const MAX_SAMPLES: usize = 64;
+/// Normal in `xyz`, view depth in `w`.
+const NORMAL_DEPTH_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+const OCCLUSION_FORMAT: Format = Format::R8_UNORM;
+const NOISE_SIZE: u32 = 4;
+/// How quickly blur taps lose weight across depth differences.
+const BLUR_DEPTH_SHARPNESS: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SsaoConfig {
+    pub enabled: bool,
+    /// World-space radius of the sampled hemisphere.
+    pub radius: f32,
+    /// Depth difference below which a sample does not count as occluded.
+    pub bias: f32,
+    /// Kernel samples per pixel, at most [`MAX_SAMPLES`].
+    pub sample_count: u32,
+    /// Computes and blurs the occlusion at half the width and height.
+    pub half_resolution: bool,
+    /// How much of the ambient light the occlusion may remove.
+    pub intensity: f32
+}
+
+impl Default for SsaoConfig {
+    fn default() -> Self {
+        SsaoConfig {
+            enabled: true,
+            radius: 0.5,
+            bias: 0.025,
+            sample_count: 16,
+            half_resolution: true,
+            intensity: 1.0
+        }
+    }
+}
+
+impl SsaoConfig {
+    /// Extent of the occlusion images for a scene of `extent`.
+    pub fn occlusion_extent(&self, extent: [u32; 2]) -> [u32; 2] {
+        if self.half_resolution {
+            extent.map(|size| (size / 2).max(1))
+        }
+        else {
+            extent
+        }
+    }
+}
+
+/// A hash of `seed` mapped to `[0, 1)`.
+fn random(seed: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9E37_79B9) ^ 0x85EB_CA6B;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Points in the unit hemisphere around `+z`, denser towards the center.
+pub fn hemisphere_kernel(sample_count: usize) -> Vec<[f32; 4]> {
+    (0..sample_count)
+        .map(|i| {
+            let seed = i as u32 * 4;
+            let direction = Vec3::new(
+                random(seed) * 2.0 - 1.0,
+                random(seed + 1) * 2.0 - 1.0,
+                random(seed + 2)
+            ).try_normalize().unwrap_or(Vec3::Z);
+            let scale = i as f32 / sample_count as f32;
+            let scale = 0.1 + 0.9 * scale * scale;
+            (direction * random(seed + 3).max(0.05) * scale).extend(0.0).into()
+        })
+        .collect()
+}
+
+/// Random rotations around the normal, tiled over the screen.
+fn noise_texture() -> TextureData {
+    let rgba = (0..NOISE_SIZE * NOISE_SIZE)
+        .flat_map(|i| {
+            let seed = 0x1000 + i * 2;
+            let encode = |value: f32| (value * 255.0).round() as u8;
+            [encode(random(seed)), encode(random(seed + 1)), 128, 255]
+        })
+        .collect();
+    TextureData { extent: [NOISE_SIZE, NOISE_SIZE], rgba }
+}
+
+/// The prefix of the frame uniforms `ssao_prepass.frag` reads.
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+struct PrepassUniforms {
+    view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    camera_forward: [f32; 4]
+}
+
+#[derive(Clone, Copy, Debug, BufferContents)]
+#[repr(C)]
+struct SsaoUniforms {
+    view_projection: [[f32; 4]; 4],
+    inverse_view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    camera_forward: [f32; 4],
+    noise_scale: [f32; 4],
+    kernel: [[f32; 4]; MAX_SAMPLES]
+}
+
+/// Renders normals and depth on the forward path.
+struct Prepass {
+    normal_depth: RenderTarget,
+    renderer: Renderer,
+    pipeline: Arc<GraphicsPipeline>
+}
+
+impl Prepass {
+    fn new(allocator: &Allocator) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let mut normal_depth = RenderTarget::new(
+            allocator,
+            [1, 1],
+            NORMAL_DEPTH_FORMAT,
+            Some(DEFAULT_DEPTH_FORMAT),
+            "SSAO normal depth"
+        );
+        normal_depth.clear_color = [0.0; 4];
+        let renderer = Renderer::for_target(device.clone(), &normal_depth);
+        let pipeline = renderer::new_mesh_pipeline(
+            device,
+            renderer.main_subpass(),
+            "lit_vert.spv",
+            Some("ssao_prepass_frag.spv"),
+            CullMode::Back,
+            false
+        );
+        Prepass { normal_depth, renderer, pipeline }
+    }
+    fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        view_projection: Mat4,
+        camera_position: Vec3,
+        forward: Vec3,
+        meshes: &[MeshInstance]
+    ) {
+        let layout = self.pipeline.layout().clone();
+        let uniforms = PrepassUniforms {
+            view_projection: view_projection.to_cols_array_2d(),
+            camera_position: camera_position.extend(1.0).into(),
+            camera_forward: forward.extend(0.0).into()
+        };
+        let descriptor_set = PersistentDescriptorSet::new(
+            &allocator.descriptor_set_allocator,
+            layout.set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(0, allocator.alloc_uniform_buffer(uniforms))],
+            []
+        ).expect("Fail to create SSAO prepass descriptor set.");
+        self.renderer.record_target_pass_draws(builder, &self.normal_depth, |builder| {
+            builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .expect("Fail to bind SSAO prepass pipeline.")
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, descriptor_set)
+            .expect("Fail to bind SSAO prepass descriptor set.");
+            for instance in meshes {
+                builder
+                .push_constants(layout.clone(), 0, instance.transform.to_cols_array_2d())
+                .expect("Fail to push model matrix.");
+                let mesh = &instance.mesh;
+                Renderer::record_mesh(builder, mesh.vertex_buffer.clone(), mesh.index_buffer.clone(), mesh.index_count);
+            }
+        });
+    }
+}
+
+/// Screen-space ambient occlusion, from the G-buffer on the deferred path
+/// and from a prepass on the forward one.
+pub struct Ssao {
+    pub config: SsaoConfig,
+    extent: [u32; 2],
+    prepass: Option<Prepass>,
+    raw: RenderTarget,
+    blurred: RenderTarget,
+    occlusion_pipeline: Arc<GraphicsPipeline>,
+    blur_pipeline: Arc<GraphicsPipeline>,
+    point_sampler: Arc<Sampler>,
+    noise_sampler: Arc<Sampler>,
+    noise: Arc<ImageView>
+}
+
+impl Ssao {
+    fn new_occlusion_targets(allocator: &Allocator, extent: [u32; 2]) -> (RenderTarget, RenderTarget) {
+        let new_target = |name| {
+            let mut target = RenderTarget::new(allocator, extent, OCCLUSION_FORMAT, None, name);
+            target.clear_color = [1.0; 4];
+            target
+        };
+        (new_target("SSAO raw"), new_target("SSAO blurred"))
+    }
+    /// Occlusion of a scene drawn on `path`, sized by [`Self::resize`].
+    pub fn new(allocator: &Allocator, queue: &Arc<Queue>, config: SsaoConfig, path: RenderPath) -> Self {
+        let device = allocator.memory_allocator.device().clone();
+        let (raw, blurred) = Self::new_occlusion_targets(allocator, [1, 1]);
+        let subpass = Subpass::from(raw.render_pass.clone(), 0).unwrap();
+        let new_pipeline = |shader_file| post_process::new_fullscreen_pipeline(device.clone(), subpass.clone(), shader_file, None);
+        let new_sampler = |filter, address_mode| Sampler::new(device.clone(), SamplerCreateInfo {
+            mag_filter: filter,
+            min_filter: filter,
+            address_mode: [address_mode; 3],
+            ..Default::default()
+        }).expect("Fail to create SSAO sampler.");
+        let noise = texture::upload_now(allocator, queue, |builder| {
+            texture::record_upload(builder, allocator, &noise_texture(), Format::R8G8B8A8_UNORM, "SSAO noise")
+        });
+        Ssao {
+            config,
+            extent: [1, 1],
+            prepass: (path == RenderPath::Forward).then(|| Prepass::new(allocator)),
+            raw,
+            blurred,
+            occlusion_pipeline: new_pipeline("ssao_frag.spv"),
+            blur_pipeline: new_pipeline("ssao_blur_frag.spv"),
+            point_sampler: new_sampler(Filter::Nearest, SamplerAddressMode::ClampToEdge),
+            noise_sampler: new_sampler(Filter::Nearest, SamplerAddressMode::Repeat),
+            noise
+        }
+    }
+    /// Reallocates the images for a scene of `extent`.
+    pub fn resize(&mut self, allocator: &Allocator, extent: [u32; 2]) {
+        self.extent = extent;
+        if let Some(prepass) = self.prepass.as_mut() {
+            prepass.normal_depth.resize(allocator, extent);
+        }
+        let occlusion_extent = self.config.occlusion_extent(extent);
+        self.raw.resize(allocator, occlusion_extent);
+        self.blurred.resize(allocator, occlusion_extent);
+    }
+    /// Replaces the parameters, reallocating the images if needed.
+    pub fn set_config(&mut self, allocator: &Allocator, config: SsaoConfig) {
+        self.config = config;
+        self.resize(allocator, self.extent);
+    }
+    /// The blurred occlusion, 1.0 where nothing occludes.
+    pub fn occlusion(&self) -> &Arc<ImageView> {
+        &self.blurred.color
+    }
+    /// Records the prepass, occlusion and blur of `meshes`, on the forward
+    /// path.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        meshes: &[MeshInstance]
+    ) {
+        let prepass = self.prepass.as_ref().expect("The deferred path reads the G-buffer instead of a prepass.");
+        let view_projection = camera.view_projection(self.extent);
+        let forward = (camera.target - camera.position).normalize();
+        prepass.record(builder, allocator, view_projection, camera.position, forward, meshes);
+        self.record_occlusion(builder, allocator, camera, &prepass.normal_depth.color);
+    }
+    /// Records the occlusion and blur of the G-buffer of `target`.
+    pub fn record_gbuffer(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        target: &RenderTarget
+    ) {
+        self.record_occlusion(builder, allocator, camera, &target.gbuffer[deferred::NORMAL_DEPTH_GBUFFER]);
+    }
+    fn record_occlusion(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        allocator: &Allocator,
+        camera: &Camera,
+        normal_depth: &Arc<ImageView>
+    ) {
+        let view_projection = camera.view_projection(self.extent);
+        let forward = (camera.target - camera.position).normalize();
+        let occlusion_extent = self.raw.extent();
+        let sample_count = self.config.sample_count.clamp(1, MAX_SAMPLES as u32);
+        let mut kernel = [[0.0; 4]; MAX_SAMPLES];
+        for (slot, sample) in kernel.iter_mut().zip(hemisphere_kernel(sample_count as usize)) {
+            *slot = sample;
+        }
+        let uniforms = SsaoUniforms {
+            view_projection: view_projection.to_cols_array_2d(),
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            camera_position: camera.position.extend(1.0).into(),
+            camera_forward: forward.extend(0.0).into(),
+            noise_scale: [
+                occlusion_extent[0] as f32 / NOISE_SIZE as f32,
+                occlusion_extent[1] as f32 / NOISE_SIZE as f32,
+                0.0,
+                0.0
+            ],
+            kernel
+        };
+        let texel_size = [1.0 / occlusion_extent[0] as f32, 1.0 / occlusion_extent[1] as f32];
+        post_process::record_fullscreen_pass(
+            builder,
+            allocator,
+            &self.occlusion_pipeline,
+            &self.raw,
+            [
+                WriteDescriptorSet::image_view(0, normal_depth.clone()),
+                WriteDescriptorSet::sampler(1, self.point_sampler.clone()),
+                WriteDescriptorSet::image_view(2, self.noise.clone()),
+                WriteDescriptorSet::sampler(3, self.noise_sampler.clone()),
+                WriteDescriptorSet::buffer(4, allocator.alloc_uniform_buffer(uniforms))
+            ],
+            PassConstants {
+                params: [self.config.radius, self.config.bias, sample_count as f32, 0.0],
+                texel_size
+            },
+            &self.raw.name
+        );
+        post_process::record_fullscreen_pass(
+            builder,
+            allocator,
+            &self.blur_pipeline,
+            &self.blurred,
+            [
+                WriteDescriptorSet::image_view(0, self.raw.color.clone()),
+                WriteDescriptorSet::sampler(1, self.point_sampler.clone()),
+                WriteDescriptorSet::image_view(2, normal_depth.clone())
+            ],
+            PassConstants {
+                params: [BLUR_DEPTH_SHARPNESS, 0.0, 0.0, 0.0],
+                texel_size
+            },
+            &self.blurred.name
+        );
+    }
+    /// Strength of the occlusion and inverse scene size, for the frame
+    /// uniforms.
+    pub fn frame_params(&self, extent: [u32; 2]) -> [f32; 4] {
+        let intensity = if self.config.enabled { self.config.intensity } else { 0.0 };
+        [intensity, 1.0 / extent[0].max(1) as f32, 1.0 / extent[1].max(1) as f32, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{
+        command_buffer::{CommandBufferUsage, PrimaryCommandBufferAbstract},
+        device::Device,
+        sync::GpuFuture
+    };
+
+    use crate::{
+        capture,
+        lighting::{LitScene, Lights},
+        material::Material,
+        model::Mesh,
+        post_process::HDR_FORMAT,
+        shadow::ShadowConfig,
+        test_support
+    };
+
+    use super::*;
+
+    const PLANE_EXTENT: [u32; 2] = [128, 128];
+
+    #[test]
+    fn kernel_fills_the_hemisphere_towards_its_center() {
+        let kernel = hemisphere_kernel(MAX_SAMPLES);
+        assert_eq!(kernel.len(), MAX_SAMPLES);
+        for sample in &kernel {
+            let sample = Vec3::from_slice(sample);
+            assert!(sample.z >= 0.0, "{sample}");
+            assert!(sample.length() <= 1.0 + 1e-5, "{sample}");
+        }
+        let mean_length = |samples: &[[f32; 4]]| {
+            samples.iter().map(|sample| Vec3::from_slice(sample).length()).sum::<f32>() / samples.len() as f32
+        };
+        assert!(mean_length(&kernel[..MAX_SAMPLES / 2]) < mean_length(&kernel[MAX_SAMPLES / 2..]));
+    }
+
+    #[test]
+    fn half_resolution_never_reaches_zero() {
+        let config = SsaoConfig::default();
+        assert_eq!(config.occlusion_extent([1280, 720]), [640, 360]);
+        assert_eq!(config.occlusion_extent([1, 3]), [1, 1]);
+        let full = SsaoConfig { half_resolution: false, ..config };
+        assert_eq!(full.occlusion_extent([1280, 720]), [1280, 720]);
+    }
+
+    /// Mean occlusion of a large plane rendered into `target`.
+    fn flat_plane_occlusion(device: Arc<Device>, queue: Arc<Queue>, allocator: &Allocator, target: RenderTarget) -> f32 {
+        let renderer = Renderer::for_target(device, &target);
+        let plane = MeshInstance {
+            mesh: Mesh::plane(20.0, [1.0; 3]).upload(allocator),
+            material: Arc::new(Material::default()),
+            transform: Mat4::IDENTITY
+        };
+        let shadow_config = ShadowConfig { enabled: false, ..Default::default() };
+        let mut scene = LitScene::new(allocator, &queue, &renderer, shadow_config, SsaoConfig::default(), vec![plane]);
+        let camera = Camera {
+            position: Vec3::new(0.0, 2.0, 3.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            fov_y: 45f32.to_radians(),
+            near: 0.1,
+            far: 50.0
+        };
+        let lights = Lights::default();
+
+        let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+        scene.record_ssao(&mut builder, allocator, &camera, PLANE_EXTENT);
+        let plan = scene.record_shadows(&mut builder, &lights, &camera, PLANE_EXTENT);
+        renderer.record_target_pass_stages(&mut builder, &target, |builder, stage| {
+            scene.record_draw(builder, allocator, stage, &camera, PLANE_EXTENT, &lights, &plan);
+        });
+        let occlusion = scene.renderer.ssao.occlusion().image().clone();
+        let readback = capture::record_readback(&mut builder, allocator, occlusion);
+        builder.build().unwrap()
+            .execute(queue).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let texels = readback.buffer.read().unwrap();
+        texels.iter().map(|texel| *texel as f32 / 255.0).sum::<f32>() / texels.len() as f32
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn flat_plane_is_not_occluded() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let target = RenderTarget::new(&allocator, PLANE_EXTENT, HDR_FORMAT, Some(DEFAULT_DEPTH_FORMAT), "flat plane");
+        let occlusion = flat_plane_occlusion(device, queue, &allocator, target);
+        assert!(occlusion > 0.97, "mean occlusion {occlusion}");
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn deferred_flat_plane_is_not_occluded() {
+        let (device, queue) = test_support::headless_device();
+        let allocator = Allocator::new(device.clone());
+        let target = RenderTarget::new_deferred(&allocator, PLANE_EXTENT, HDR_FORMAT, "deferred flat plane");
+        let occlusion = flat_plane_occlusion(device, queue, &allocator, target);
+        assert!(occlusion > 0.97, "mean occlusion {occlusion}");
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+use crate::bounds::CullStats;
+
+/// Seconds of frames averaged into one report.
+const REPORT_INTERVAL: f32 = 0.5;
+
+/// Counters of the last frame and the average frame time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    /// Seconds per frame over the last full interval.
+    pub frame_time: f32,
+    pub culling: CullStats,
+    frames: u32,
+    elapsed: f32
+}
+
+impl FrameStats {
+    /// Counts a frame that took `delta_time` seconds. Returns `true` when
+    /// `frame_time` was updated.
+    pub fn end_frame(&mut self, delta_time: f32, culling: CullStats) -> bool {
+        self.culling = culling;
+        self.frames += 1;
+        self.elapsed += delta_time;
+        if self.elapsed < REPORT_INTERVAL {
+            return false;
+        }
+        self.frame_time = self.elapsed / self.frames as f32;
+        self.frames = 0;
+        self.elapsed = 0.0;
+        true
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fps = if self.frame_time > 0.0 { 1.0 / self.frame_time } else { 0.0 };
+        write!(
+            f,
+            "{fps:.0} fps ({:.2} ms), {} meshes visible, {} culled",
+            self.frame_time * 1000.0,
+            self.culling.visible,
+            self.culling.culled
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_time_is_averaged_per_interval() {
+        let mut stats = FrameStats::default();
+        let culling = CullStats { visible: 3, culled: 2 };
+        assert!(!stats.end_frame(0.25, culling));
+        assert!(stats.end_frame(0.25, culling));
+        assert_eq!(stats.frame_time, 0.25);
+        assert_eq!(stats.to_string(), "4 fps (250.00 ms), 3 meshes visible, 2 culled");
+        assert!(!stats.end_frame(0.125, CullStats::default()));
+        assert_eq!(stats.culling, CullStats::default());
+    }
+}
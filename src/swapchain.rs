@@ -0,0 +1,464 @@
+use vulkano::{
+    VulkanError,
+    format::Format,
+    swapchain::{ColorSpace, PresentMode, SurfaceCapabilities}
+};
+
+/// Preferences used when (re)creating the swapchain.
+///
+/// Both preference lists are ordered from most to least wanted; the first
+/// entry the surface supports wins. `Fifo` is always accepted as a last
+/// resort since every implementation must support it.
+#[derive(Clone, Debug)]
+pub struct SwapchainConfig {
+    pub present_modes: Vec<PresentMode>,
+    pub surface_formats: Vec<(Format, ColorSpace)>,
+    /// Desired number of images, clamped to what the surface allows.
+    pub image_count: u32,
+    /// When set, `present_modes` is ignored and a vertically synchronized
+    /// mode is used instead. The config file turns it off when it lists
+    /// `present_modes` without setting `vsync`.
+    pub vsync: bool
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        SwapchainConfig {
+            present_modes: vec![
+                PresentMode::Mailbox,
+                PresentMode::Immediate,
+                PresentMode::FifoRelaxed,
+                PresentMode::Fifo
+            ],
+            surface_formats: vec![
+                (Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear),
+                (Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear)
+            ],
+            image_count: 3,
+            vsync: true
+        }
+    }
+}
+
+impl SwapchainConfig {
+    pub fn parse_present_mode(name: &str) -> Option<PresentMode> {
+        match name.trim().to_lowercase().as_str() {
+            "immediate" => Some(PresentMode::Immediate),
+            "mailbox" => Some(PresentMode::Mailbox),
+            "fifo" => Some(PresentMode::Fifo),
+            "fifo_relaxed" => Some(PresentMode::FifoRelaxed),
+            _ => None
+        }
+    }
+    /// Parses `format` or `format:color_space`, e.g.
+    /// `r16g16b16a16_sfloat:extended_srgb_linear`. The color space defaults
+    /// to sRGB.
+    pub fn parse_surface_format(name: &str) -> Option<(Format, ColorSpace)> {
+        let (format, color_space) = name.trim().split_once(':').unwrap_or((name.trim(), "srgb_nonlinear"));
+        let format = match format.trim().to_lowercase().as_str() {
+            "b8g8r8a8_srgb" => Format::B8G8R8A8_SRGB,
+            "r8g8b8a8_srgb" => Format::R8G8B8A8_SRGB,
+            "b8g8r8a8_unorm" => Format::B8G8R8A8_UNORM,
+            "r8g8b8a8_unorm" => Format::R8G8B8A8_UNORM,
+            "a2b10g10r10_unorm_pack32" => Format::A2B10G10R10_UNORM_PACK32,
+            "r16g16b16a16_sfloat" => Format::R16G16B16A16_SFLOAT,
+            _ => return None
+        };
+        let color_space = match color_space.trim().to_lowercase().as_str() {
+            "srgb_nonlinear" => ColorSpace::SrgbNonLinear,
+            "extended_srgb_linear" => ColorSpace::ExtendedSrgbLinear,
+            "display_p3_nonlinear" => ColorSpace::DisplayP3NonLinear,
+            "hdr10_st2084" => ColorSpace::Hdr10St2084,
+            _ => return None
+        };
+        Some((format, color_space))
+    }
+    /// Present modes to try in order, taking the vsync switch into account.
+    pub fn present_mode_preferences(&self) -> Vec<PresentMode> {
+        if self.vsync {
+            vec![PresentMode::Fifo]
+        }
+        else {
+            self.present_modes.clone()
+        }
+    }
+    pub fn select_present_mode(&self, available: &[PresentMode]) -> Option<PresentMode> {
+        self.present_mode_preferences()
+            .into_iter()
+            .chain([PresentMode::Fifo])
+            .find(|mode| available.contains(mode))
+            .or(available.first().copied())
+    }
+    pub fn select_surface_format(&self, available: &[(Format, ColorSpace)]) -> Option<(Format, ColorSpace)> {
+        self.surface_formats
+            .iter()
+            .find(|format| available.contains(format))
+            .or(available.first())
+            .copied()
+    }
+    pub fn select_image_count(&self, capabilities: &SurfaceCapabilities) -> u32 {
+        clamp_image_count(self.image_count, capabilities.min_image_count, capabilities.max_image_count)
+    }
+}
+
+/// `max_image_count` of `None` means the surface has no upper limit.
+pub fn clamp_image_count(desired: u32, min_image_count: u32, max_image_count: Option<u32>) -> u32 {
+    let image_count = desired.max(min_image_count);
+    match max_image_count {
+        Some(max_image_count) => image_count.min(max_image_count),
+        None => image_count
+    }
+}
+
+/// Picks the swapchain extent. Some platforms (Wayland) leave
+/// `current_extent` undefined and let the swapchain decide, in which case the
+/// window size clamped to the surface limits is used.
+pub fn resolve_extent(
+    current_extent: Option<[u32; 2]>,
+    window_extent: [u32; 2],
+    min_image_extent: [u32; 2],
+    max_image_extent: [u32; 2]
+) -> [u32; 2] {
+    match current_extent {
+        Some(extent) => extent,
+        None => [
+            window_extent[0].clamp(min_image_extent[0], max_image_extent[0]),
+            window_extent[1].clamp(min_image_extent[1], max_image_extent[1])
+        ]
+    }
+}
+
+/// The operations [`SwapchainLifecycle`] drives. `Framework` implements it
+/// on top of vulkano; tests implement it with a scripted mock.
+pub trait SwapchainBackend {
+    type Acquired;
+
+    /// Acquires an image, also reporting whether the swapchain is suboptimal.
+    fn acquire(&mut self) -> Result<(Self::Acquired, bool), VulkanError>;
+    /// The extent reported by the surface, if it defines one.
+    fn surface_extent(&self) -> Option<[u32; 2]>;
+    /// Rebuilds the swapchain for `extent`. A zero-sized extent is never
+    /// passed.
+    fn recreate_swapchain(&mut self, extent: [u32; 2]) -> Result<(), VulkanError>;
+    /// Rebuilds the surface after it was lost. The swapchain is recreated
+    /// right after.
+    fn recreate_surface(&mut self) -> Result<(), VulkanError>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapchainState {
+    /// The swapchain matches the surface and can be acquired from.
+    Ready,
+    /// The swapchain must be rebuilt before the next acquire.
+    OutOfDate,
+    /// The surface has no area (minimized window); nothing can be rendered.
+    Minimized,
+    /// The surface itself was lost and has to be created again.
+    SurfaceLost
+}
+
+/// State machine around swapchain acquire, present and recreation.
+///
+/// Suboptimal images are still rendered and presented; recreation is
+/// deferred to the next frame. Out-of-date results, from either acquire or
+/// present, force a recreation before anything else is acquired.
+#[derive(Clone, Copy, Debug)]
+pub struct SwapchainLifecycle {
+    state: SwapchainState
+}
+
+impl Default for SwapchainLifecycle {
+    fn default() -> Self {
+        SwapchainLifecycle { state: SwapchainState::Ready }
+    }
+}
+
+impl SwapchainLifecycle {
+    pub fn state(&self) -> SwapchainState {
+        self.state
+    }
+    /// Marks the swapchain stale, e.g. after a window resize.
+    pub fn invalidate(&mut self) {
+        if self.state != SwapchainState::SurfaceLost {
+            self.state = SwapchainState::OutOfDate;
+        }
+    }
+    fn handle_error(&mut self, error: VulkanError) -> Result<(), VulkanError> {
+        match error {
+            VulkanError::OutOfDate => {
+                self.state = SwapchainState::OutOfDate;
+                Ok(())
+            }
+            VulkanError::SurfaceLost => {
+                self.state = SwapchainState::SurfaceLost;
+                Ok(())
+            }
+            error => Err(error)
+        }
+    }
+    /// Brings the swapchain to `Ready` if possible. Returns `false` when the
+    /// surface is zero-sized and the frame has to be skipped.
+    pub fn prepare<B: SwapchainBackend>(&mut self, backend: &mut B, window_extent: [u32; 2]) -> Result<bool, VulkanError> {
+        if self.state == SwapchainState::SurfaceLost {
+            backend.recreate_surface()?;
+            self.state = SwapchainState::OutOfDate;
+        }
+        if self.state == SwapchainState::Ready {
+            return Ok(true);
+        }
+        let extent = backend.surface_extent().unwrap_or(window_extent);
+        if extent[0] == 0 || extent[1] == 0 {
+            self.state = SwapchainState::Minimized;
+            return Ok(false);
+        }
+        match backend.recreate_swapchain(extent) {
+            Ok(()) => {
+                self.state = SwapchainState::Ready;
+                Ok(true)
+            }
+            Err(error) => self.handle_error(error).map(|_| false)
+        }
+    }
+    /// Acquires the next image, recreating the swapchain first when needed
+    /// and retrying once if the acquire itself reports out-of-date.
+    pub fn acquire<B: SwapchainBackend>(&mut self, backend: &mut B, window_extent: [u32; 2]) -> Result<Option<B::Acquired>, VulkanError> {
+        for _ in 0..2 {
+            if !self.prepare(backend, window_extent)? {
+                return Ok(None);
+            }
+            match backend.acquire() {
+                Ok((acquired, suboptimal)) => {
+                    if suboptimal {
+                        self.state = SwapchainState::OutOfDate;
+                    }
+                    return Ok(Some(acquired));
+                }
+                Err(error) => self.handle_error(error)?
+            }
+        }
+        Ok(None)
+    }
+    /// Feeds back the outcome of presenting a frame.
+    pub fn presented(&mut self, result: Result<(), VulkanError>) -> Result<(), VulkanError> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) => self.handle_error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_count_is_clamped_to_capabilities() {
+        assert_eq!(clamp_image_count(3, 2, Some(8)), 3);
+        assert_eq!(clamp_image_count(1, 2, Some(8)), 2);
+        assert_eq!(clamp_image_count(10, 2, Some(8)), 8);
+        assert_eq!(clamp_image_count(10, 2, None), 10);
+    }
+
+    #[test]
+    fn present_mode_follows_preferences() {
+        let config = SwapchainConfig { vsync: false, ..Default::default() };
+        let available = [PresentMode::Fifo, PresentMode::Immediate];
+        assert_eq!(config.select_present_mode(&available), Some(PresentMode::Immediate));
+        let available = [PresentMode::Fifo, PresentMode::Mailbox, PresentMode::Immediate];
+        assert_eq!(config.select_present_mode(&available), Some(PresentMode::Mailbox));
+    }
+
+    #[test]
+    fn vsync_forces_fifo() {
+        let config = SwapchainConfig { vsync: true, ..Default::default() };
+        let available = [PresentMode::Mailbox, PresentMode::Fifo];
+        assert_eq!(config.select_present_mode(&available), Some(PresentMode::Fifo));
+    }
+
+    #[test]
+    fn surface_formats_parse_with_an_optional_color_space() {
+        assert_eq!(
+            SwapchainConfig::parse_surface_format(" R8G8B8A8_SRGB "),
+            Some((Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear))
+        );
+        assert_eq!(
+            SwapchainConfig::parse_surface_format("r16g16b16a16_sfloat:extended_srgb_linear"),
+            Some((Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear))
+        );
+        assert_eq!(SwapchainConfig::parse_surface_format("r8g8b8a8_srgb:bogus"), None);
+        assert_eq!(SwapchainConfig::parse_surface_format("d32_sfloat"), None);
+    }
+
+    #[test]
+    fn surface_format_falls_back_to_first_available() {
+        let config = SwapchainConfig::default();
+        let available = [
+            (Format::A2B10G10R10_UNORM_PACK32, ColorSpace::SrgbNonLinear),
+            (Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear)
+        ];
+        assert_eq!(config.select_surface_format(&available), Some(available[1]));
+        assert_eq!(config.select_surface_format(&available[..1]), Some(available[0]));
+        assert_eq!(config.select_surface_format(&[]), None);
+    }
+
+    struct MockSwapchain {
+        acquire_results: Vec<Result<(u32, bool), VulkanError>>,
+        surface_extent: Option<[u32; 2]>,
+        recreated_extents: Vec<[u32; 2]>,
+        surface_recreations: u32
+    }
+
+    impl MockSwapchain {
+        fn new(acquire_results: Vec<Result<(u32, bool), VulkanError>>) -> Self {
+            MockSwapchain {
+                acquire_results,
+                surface_extent: Some([800, 600]),
+                recreated_extents: Vec::new(),
+                surface_recreations: 0
+            }
+        }
+    }
+
+    impl SwapchainBackend for MockSwapchain {
+        type Acquired = u32;
+
+        fn acquire(&mut self) -> Result<(u32, bool), VulkanError> {
+            self.acquire_results.remove(0)
+        }
+        fn surface_extent(&self) -> Option<[u32; 2]> {
+            self.surface_extent
+        }
+        fn recreate_swapchain(&mut self, extent: [u32; 2]) -> Result<(), VulkanError> {
+            self.recreated_extents.push(extent);
+            Ok(())
+        }
+        fn recreate_surface(&mut self) -> Result<(), VulkanError> {
+            self.surface_recreations += 1;
+            Ok(())
+        }
+    }
+
+    const WINDOW_EXTENT: [u32; 2] = [1024, 768];
+
+    #[test]
+    fn ready_swapchain_acquires_without_recreation() {
+        let mut backend = MockSwapchain::new(vec![Ok((1, false))]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(1)));
+        assert_eq!(lifecycle.state(), SwapchainState::Ready);
+        assert!(backend.recreated_extents.is_empty());
+    }
+
+    #[test]
+    fn suboptimal_frame_is_rendered_and_recreation_scheduled() {
+        let mut backend = MockSwapchain::new(vec![Ok((0, true)), Ok((1, false))]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(0)));
+        assert_eq!(lifecycle.state(), SwapchainState::OutOfDate);
+        assert!(backend.recreated_extents.is_empty());
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(1)));
+        assert_eq!(backend.recreated_extents, vec![[800, 600]]);
+        assert_eq!(lifecycle.state(), SwapchainState::Ready);
+    }
+
+    #[test]
+    fn out_of_date_acquire_recreates_and_retries() {
+        let mut backend = MockSwapchain::new(vec![Err(VulkanError::OutOfDate), Ok((2, false))]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(2)));
+        assert_eq!(backend.recreated_extents.len(), 1);
+        assert_eq!(lifecycle.state(), SwapchainState::Ready);
+    }
+
+    #[test]
+    fn out_of_date_present_recreates_on_next_acquire() {
+        let mut backend = MockSwapchain::new(vec![Ok((0, false))]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.presented(Err(VulkanError::OutOfDate)), Ok(()));
+        assert_eq!(lifecycle.state(), SwapchainState::OutOfDate);
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(0)));
+        assert_eq!(backend.recreated_extents.len(), 1);
+    }
+
+    #[test]
+    fn undefined_surface_extent_uses_window_size() {
+        let mut backend = MockSwapchain::new(vec![Ok((0, false))]);
+        backend.surface_extent = None;
+        let mut lifecycle = SwapchainLifecycle::default();
+        lifecycle.invalidate();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(0)));
+        assert_eq!(backend.recreated_extents, vec![WINDOW_EXTENT]);
+    }
+
+    #[test]
+    fn zero_extent_minimizes_until_restored() {
+        let mut backend = MockSwapchain::new(vec![Ok((0, false))]);
+        backend.surface_extent = Some([0, 0]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        lifecycle.invalidate();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(None));
+        assert_eq!(lifecycle.state(), SwapchainState::Minimized);
+        backend.surface_extent = Some([640, 480]);
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(0)));
+        assert_eq!(backend.recreated_extents, vec![[640, 480]]);
+        assert_eq!(lifecycle.state(), SwapchainState::Ready);
+    }
+
+    #[test]
+    fn surface_lost_rebuilds_surface_and_swapchain() {
+        let mut backend = MockSwapchain::new(vec![Err(VulkanError::SurfaceLost), Ok((3, false))]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(3)));
+        assert_eq!(backend.surface_recreations, 1);
+        assert_eq!(backend.recreated_extents.len(), 1);
+    }
+
+    #[test]
+    fn surface_lost_on_present_survives_invalidate() {
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.presented(Err(VulkanError::SurfaceLost)), Ok(()));
+        lifecycle.invalidate();
+        assert_eq!(lifecycle.state(), SwapchainState::SurfaceLost);
+    }
+
+    #[test]
+    fn other_errors_are_propagated() {
+        let mut backend = MockSwapchain::new(vec![Err(VulkanError::DeviceLost)]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Err(VulkanError::DeviceLost));
+        assert_eq!(lifecycle.presented(Err(VulkanError::DeviceLost)), Err(VulkanError::DeviceLost));
+    }
+
+    #[test]
+    fn device_lost_is_reported_for_recovery() {
+        use vulkano::Validated;
+        use crate::framework::{check_device_lost, DeviceLost};
+
+        let mut backend = MockSwapchain::new(vec![Err(VulkanError::DeviceLost)]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        let acquired = lifecycle.acquire(&mut backend, WINDOW_EXTENT).map_err(Validated::Error);
+        assert_eq!(check_device_lost(acquired, "acquire"), Err(DeviceLost));
+        let presented = lifecycle.presented(Err(VulkanError::DeviceLost)).map_err(Validated::Error);
+        assert_eq!(check_device_lost(presented, "present"), Err(DeviceLost));
+        // Recovery starts over with a fresh lifecycle on the new device.
+        let mut backend = MockSwapchain::new(vec![Ok((0, false))]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(Some(0)));
+        assert!(backend.recreated_extents.is_empty());
+    }
+
+    #[test]
+    fn repeated_out_of_date_gives_up_for_the_frame() {
+        let mut backend = MockSwapchain::new(vec![Err(VulkanError::OutOfDate), Err(VulkanError::OutOfDate)]);
+        let mut lifecycle = SwapchainLifecycle::default();
+        assert_eq!(lifecycle.acquire(&mut backend, WINDOW_EXTENT), Ok(None));
+        assert_eq!(lifecycle.state(), SwapchainState::OutOfDate);
+    }
+
+    #[test]
+    fn extent_falls_back_to_clamped_window_size() {
+        assert_eq!(resolve_extent(Some([800, 600]), [1024, 768], [1, 1], [4096, 4096]), [800, 600]);
+        assert_eq!(resolve_extent(None, [1024, 768], [1, 1], [4096, 4096]), [1024, 768]);
+        assert_eq!(resolve_extent(None, [8000, 0], [1, 1], [4096, 4096]), [4096, 1]);
+    }
+}
@@ -0,0 +1,32 @@
+//! Helpers for tests that need a GPU, run with `cargo test -- --ignored`.
+
+use std::sync::Arc;
+
+use vulkano::{
+    VulkanLibrary,
+    instance::{Instance, InstanceCreateInfo},
+    device::{Device, DeviceCreateInfo, Queue, QueueCreateInfo, QueueFlags}
+};
+
+/// A device without a window and its graphics and compute queue.
+pub fn headless_device() -> (Arc<Device>, Arc<Queue>) {
+    try_headless_device().expect("No Vulkan device available for a GPU test.")
+}
+
+fn try_headless_device() -> Option<(Arc<Device>, Arc<Queue>)> {
+    let library = VulkanLibrary::new().ok()?;
+    let instance = Instance::new(library, InstanceCreateInfo::default()).ok()?;
+    let (physical_device, queue_family_index) = instance.enumerate_physical_devices().ok()?
+        .find_map(|physical_device| {
+            let index = physical_device.queue_family_properties()
+                .iter()
+                .position(|properties| properties.queue_flags.contains(QueueFlags::GRAPHICS | QueueFlags::COMPUTE))?;
+            Some((physical_device, index as u32))
+        })?;
+    let create_info = DeviceCreateInfo {
+        queue_create_infos: vec![QueueCreateInfo { queue_family_index, ..Default::default() }],
+        ..Default::default()
+    };
+    let (device, mut queues) = Device::new(physical_device, create_info).ok()?;
+    Some((device, queues.next()?))
+}
@@ -0,0 +1,621 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc
+};
+
+use half::f16;
+
+use vulkano::{
+    DeviceSize,
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, BufferImageCopy, CommandBufferUsage, CopyBufferToImageInfo,
+        CopyImageToBufferInfo, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract
+    },
+    device::Queue,
+    format::Format,
+    image::{
+        Image, ImageCreateFlags, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage,
+        view::{ImageView, ImageViewCreateInfo, ImageViewType}
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    sync::GpuFuture
+};
+
+use crate::{
+    debug,
+    allocator::Allocator
+};
+
+/// Pixels of an image file, as tightly packed RGBA8.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureData {
+    pub extent: [u32; 2],
+    pub rgba: Vec<u8>
+}
+
+impl TextureData {
+    /// A single texel, e.g. the fallback of an empty material slot.
+    pub fn solid(rgba: [u8; 4]) -> Self {
+        TextureData { extent: [1, 1], rgba: rgba.to_vec() }
+    }
+}
+
+/// Decodes a PNG of any color type and bit depth into RGBA8.
+pub fn load_png(path: &Path) -> Result<TextureData, png::DecodingError> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![0; reader.output_buffer_size().unwrap_or(0)];
+    let info = reader.next_frame(&mut pixels)?;
+    pixels.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels,
+        png::ColorType::Rgb => pixels.chunks_exact(3)
+            .flat_map(|texel| [texel[0], texel[1], texel[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2)
+            .flat_map(|texel| [texel[0], texel[0], texel[0], texel[1]])
+            .collect(),
+        png::ColorType::Grayscale | png::ColorType::Indexed => pixels.iter()
+            .flat_map(|value| [*value, *value, *value, 255])
+            .collect()
+    };
+    Ok(TextureData { extent: [info.width, info.height], rgba })
+}
+
+/// Linear RGB pixels of a Radiance `.hdr` image, top row first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrData {
+    pub extent: [u32; 2],
+    pub rgb: Vec<[f32; 3]>
+}
+
+impl HdrData {
+    /// Texels for an `R16G16B16A16_SFLOAT` image, with an alpha of 1.0.
+    pub fn to_rgba16f(&self) -> Vec<u8> {
+        self.rgb.iter()
+            .flat_map(|[r, g, b]| [*r, *g, *b, 1.0])
+            .flat_map(|channel| f16::from_f32(channel).to_le_bytes())
+            .collect()
+    }
+}
+
+fn invalid_hdr(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid HDR image: {message}."))
+}
+
+fn read_hdr_line<'a>(bytes: &'a [u8], cursor: &mut usize) -> io::Result<&'a str> {
+    let rest = &bytes[*cursor..];
+    let end = rest.iter().position(|byte| *byte == b'\n').ok_or_else(|| invalid_hdr("truncated header"))?;
+    *cursor += end + 1;
+    std::str::from_utf8(&rest[..end]).map_err(|_| invalid_hdr("header is not text"))
+}
+
+/// Decodes one scanline, either run-length encoded or flat.
+fn read_hdr_scanline(bytes: &[u8], cursor: &mut usize, row: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = row.len();
+    let truncated = || invalid_hdr("truncated pixel data");
+    let data = &bytes[*cursor..];
+    let run_length_encoded = (8..32768).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2 && data[1] == 2 && data[2] & 0x80 == 0;
+    if !run_length_encoded {
+        let texels = data.get(..width * 4).ok_or_else(truncated)?;
+        for (texel, value) in row.iter_mut().zip(texels.chunks_exact(4)) {
+            texel.copy_from_slice(value);
+        }
+        *cursor += width * 4;
+        return Ok(());
+    }
+    if ((data[2] as usize) << 8 | data[3] as usize) != width {
+        return Err(invalid_hdr("scanline width mismatch"));
+    }
+    *cursor += 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(*cursor).ok_or_else(truncated)? as usize;
+            *cursor += 1;
+            if count > 128 {
+                let run = count - 128;
+                let value = *bytes.get(*cursor).ok_or_else(truncated)?;
+                *cursor += 1;
+                let texels = row.get_mut(x..x + run).ok_or_else(|| invalid_hdr("run overflows scanline"))?;
+                texels.iter_mut().for_each(|texel| texel[channel] = value);
+                x += run;
+            } else {
+                let values = bytes.get(*cursor..*cursor + count).ok_or_else(truncated)?;
+                let texels = row.get_mut(x..x + count)
+                    .filter(|_| count > 0)
+                    .ok_or_else(|| invalid_hdr("bad literal run"))?;
+                texels.iter_mut().zip(values).for_each(|(texel, value)| texel[channel] = *value);
+                *cursor += count;
+                x += count;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a Radiance RGBE image.
+pub fn parse_hdr(bytes: &[u8]) -> io::Result<HdrData> {
+    let mut cursor = 0;
+    if !read_hdr_line(bytes, &mut cursor)?.starts_with("#?") {
+        return Err(invalid_hdr("missing signature"));
+    }
+    loop {
+        let line = read_hdr_line(bytes, &mut cursor)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_hdr("only RGBE is supported"));
+        }
+    }
+    let resolution: Vec<&str> = read_hdr_line(bytes, &mut cursor)?.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => (height.parse::<u32>(), width.parse::<u32>()),
+        _ => return Err(invalid_hdr("unsupported orientation"))
+    };
+    let (Ok(height), Ok(width)) = (height, width) else {
+        return Err(invalid_hdr("bad resolution"));
+    };
+
+    let mut rgbe = vec![[0u8; 4]; width as usize * height as usize];
+    for row in rgbe.chunks_exact_mut(width.max(1) as usize) {
+        read_hdr_scanline(bytes, &mut cursor, row)?;
+    }
+    let rgb = rgbe.iter()
+        .map(|[r, g, b, exponent]| {
+            if *exponent == 0 {
+                return [0.0; 3];
+            }
+            let scale = 2f32.powi(*exponent as i32 - 136);
+            [*r as f32 * scale, *g as f32 * scale, *b as f32 * scale]
+        })
+        .collect();
+    Ok(HdrData { extent: [width, height], rgb })
+}
+
+pub fn load_hdr(path: &Path) -> io::Result<HdrData> {
+    parse_hdr(&fs::read(path)?)
+}
+
+/// File stems of the faces of a cube, in the layer order of Vulkan.
+pub const CUBE_FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// Texels of a square cube image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CubeData {
+    pub size: u32,
+    pub format: Format,
+    pub mip_levels: u32,
+    pub bytes: Vec<u8>
+}
+
+fn invalid_cube(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid cube map: {message}."))
+}
+
+/// Loads a cube from six PNG or `.hdr` images in the order of
+/// [`CUBE_FACE_NAMES`].
+pub fn load_cube_faces(paths: &[PathBuf; 6]) -> io::Result<CubeData> {
+    let mut faces = Vec::with_capacity(6);
+    for path in paths {
+        let is_hdr = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+        let face = if is_hdr {
+            let hdr = load_hdr(path)?;
+            (Format::R16G16B16A16_SFLOAT, hdr.extent, hdr.to_rgba16f())
+        }
+        else {
+            let data = load_png(path).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            (Format::R8G8B8A8_SRGB, data.extent, data.rgba)
+        };
+        faces.push(face);
+    }
+    let (format, extent, _) = faces[0];
+    if extent[0] != extent[1] {
+        return Err(invalid_cube("faces are not square"));
+    }
+    if faces.iter().any(|(face_format, face_extent, _)| *face_format != format || *face_extent != extent) {
+        return Err(invalid_cube("faces differ in size or format"));
+    }
+    let bytes = faces.into_iter().flat_map(|(_, _, bytes)| bytes).collect();
+    Ok(CubeData { size: extent[0], format, mip_levels: 1, bytes })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n'];
+const KTX2_HEADER_SIZE: usize = 80;
+
+/// Parses an uncompressed RGBA KTX2 cube map with its mip levels.
+pub fn parse_ktx2(bytes: &[u8]) -> io::Result<CubeData> {
+    if bytes.len() < KTX2_HEADER_SIZE || bytes[..12] != KTX2_IDENTIFIER {
+        return Err(invalid_cube("not a KTX2 file"));
+    }
+    let format = match read_u32(bytes, 12) {
+        37 => Format::R8G8B8A8_UNORM,
+        43 => Format::R8G8B8A8_SRGB,
+        97 => Format::R16G16B16A16_SFLOAT,
+        109 => Format::R32G32B32A32_SFLOAT,
+        other => return Err(invalid_cube(&format!("unsupported VkFormat {other}")))
+    };
+    let [width, height, depth, layers, faces, levels, supercompression] = std::array::from_fn(|i| read_u32(bytes, 20 + 4 * i));
+    if width != height || depth != 0 || layers != 0 || faces != 6 {
+        return Err(invalid_cube("not a single square cube"));
+    }
+    if supercompression != 0 {
+        return Err(invalid_cube("supercompression is not supported"));
+    }
+    if width == 0 {
+        return Err(invalid_cube("empty cube"));
+    }
+    let mip_levels = levels.max(1);
+    if mip_levels > width.ilog2() + 1 {
+        return Err(invalid_cube("too many mip levels"));
+    }
+    let index_end = KTX2_HEADER_SIZE + 24 * mip_levels as usize;
+    if bytes.len() < index_end {
+        return Err(invalid_cube("truncated level index"));
+    }
+    let mut levels = Vec::with_capacity(mip_levels as usize);
+    for mip_level in 0..mip_levels {
+        let entry = KTX2_HEADER_SIZE + 24 * mip_level as usize;
+        let offset = read_u64(bytes, entry) as usize;
+        let length = read_u64(bytes, entry + 8) as usize;
+        let level = offset.checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| invalid_cube("truncated level data"))?;
+        let expected = texel_count(mip_extent([width; 2], mip_level)).checked_mul(6 * format.block_size());
+        if expected != Some(length as DeviceSize) {
+            return Err(invalid_cube("level size does not match its extent"));
+        }
+        levels.push(level);
+    }
+    Ok(CubeData { size: width, format, mip_levels, bytes: levels.concat() })
+}
+
+pub fn load_ktx2(path: &Path) -> io::Result<CubeData> {
+    parse_ktx2(&fs::read(path)?)
+}
+
+/// Loads a `.ktx2` cube file, or a directory holding one image per face
+/// named after [`CUBE_FACE_NAMES`], e.g. `px.png` or `nz.hdr`.
+pub fn load_cube(path: &Path) -> io::Result<CubeData> {
+    if !path.is_dir() {
+        return load_ktx2(path);
+    }
+    let extension = ["png", "hdr"].into_iter()
+        .find(|extension| path.join(format!("{}.{extension}", CUBE_FACE_NAMES[0])).is_file())
+        .ok_or_else(|| invalid_cube("no px.png or px.hdr face"))?;
+    load_cube_faces(&CUBE_FACE_NAMES.map(|face| path.join(format!("{face}.{extension}"))))
+}
+
+/// Size in bytes of every mip level and layer of an image of `format`,
+/// tightly packed mip by mip.
+pub fn image_byte_size(format: Format, extent: [u32; 2], array_layers: u32, mip_levels: u32) -> DeviceSize {
+    (0..mip_levels)
+        .map(|mip_level| texel_count(mip_extent(extent, mip_level)) * array_layers as DeviceSize * format.block_size())
+        .sum()
+}
+
+/// Extent of `mip_level` of an image of `extent`.
+pub fn mip_extent(extent: [u32; 2], mip_level: u32) -> [u32; 2] {
+    extent.map(|size| (size >> mip_level).max(1))
+}
+
+fn texel_count(extent: [u32; 2]) -> DeviceSize {
+    extent[0] as DeviceSize * extent[1] as DeviceSize
+}
+
+fn mip_copies(format: Format, extent: [u32; 2], array_layers: u32, mip_levels: u32) -> Vec<BufferImageCopy> {
+    let mut buffer_offset = 0;
+    (0..mip_levels)
+        .map(|mip_level| {
+            let level_extent = mip_extent(extent, mip_level);
+            let copy = BufferImageCopy {
+                buffer_offset,
+                image_subresource: ImageSubresourceLayers {
+                    mip_level,
+                    array_layers: 0..array_layers,
+                    ..ImageSubresourceLayers::from_parameters(format, array_layers)
+                },
+                image_extent: [level_extent[0], level_extent[1], 1],
+                ..Default::default()
+            };
+            buffer_offset += texel_count(level_extent) * array_layers as DeviceSize * format.block_size();
+            copy
+        })
+        .collect()
+}
+
+/// Records the upload of `bytes` into every mip level and layer of a new
+/// image, shared between queue families as [`Allocator::sharing`] says.
+pub fn record_image_upload(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    allocator: &Allocator,
+    create_info: ImageCreateInfo,
+    bytes: &[u8],
+    name: &str
+) -> Arc<Image> {
+    let create_info = ImageCreateInfo {
+        usage: create_info.usage | ImageUsage::TRANSFER_DST,
+        sharing: allocator.sharing.clone(),
+        ..create_info
+    };
+    let extent = [create_info.extent[0], create_info.extent[1]];
+    let regions = mip_copies(create_info.format, extent, create_info.array_layers, create_info.mip_levels);
+    let allocation_info = AllocationCreateInfo {
+        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+        ..Default::default()
+    };
+    let image = Image::new(allocator.memory_allocator.clone(), create_info, allocation_info)
+        .expect("Fail to allocate texture image.");
+    debug::set_object_name(&*image, name);
+
+    let staging_buffer = Buffer::from_iter(
+        allocator.memory_allocator.clone(),
+        BufferCreateInfo { usage: BufferUsage::TRANSFER_SRC, ..Default::default() },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        bytes.iter().copied()
+    ).expect("Fail to allocate texture staging buffer.");
+    builder
+    .copy_buffer_to_image(CopyBufferToImageInfo {
+        regions: regions.into(),
+        ..CopyBufferToImageInfo::buffer_image(staging_buffer, image.clone())
+    })
+    .expect("Fail to copy texture staging buffer.");
+    image
+}
+
+/// Records a copy of every mip level and layer of `image`, which needs
+/// `TRANSFER_SRC` usage, into a new host-visible buffer.
+pub fn record_image_download(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    allocator: &Allocator,
+    image: Arc<Image>
+) -> Subbuffer<[u8]> {
+    let extent = [image.extent()[0], image.extent()[1]];
+    let regions = mip_copies(image.format(), extent, image.array_layers(), image.mip_levels());
+    let size = image_byte_size(image.format(), extent, image.array_layers(), image.mip_levels());
+    let buffer = Buffer::new_slice::<u8>(
+        allocator.memory_allocator.clone(),
+        BufferCreateInfo { usage: BufferUsage::TRANSFER_DST, ..Default::default() },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        size
+    ).expect("Fail to allocate image download buffer.");
+    builder
+    .copy_image_to_buffer(CopyImageToBufferInfo {
+        regions: regions.into(),
+        ..CopyImageToBufferInfo::image_buffer(image, buffer.clone())
+    })
+    .expect("Fail to copy image to download buffer.");
+    buffer
+}
+
+/// Records the upload of `data` into a new sampled image of a 4-byte RGBA
+/// `format`.
+pub fn record_upload(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    allocator: &Allocator,
+    data: &TextureData,
+    format: Format,
+    name: &str
+) -> Arc<ImageView> {
+    let create_info = ImageCreateInfo {
+        image_type: ImageType::Dim2d,
+        format,
+        extent: [data.extent[0], data.extent[1], 1],
+        usage: ImageUsage::SAMPLED,
+        ..Default::default()
+    };
+    let image = record_image_upload(builder, allocator, create_info, &data.rgba, name);
+    ImageView::new_default(image).expect("Fail to create texture image view.")
+}
+
+/// Records the upload of `data` into a new cube image and returns its cube
+/// view.
+pub fn record_cube_upload(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    allocator: &Allocator,
+    data: &CubeData,
+    name: &str
+) -> Arc<ImageView> {
+    let create_info = ImageCreateInfo {
+        flags: ImageCreateFlags::CUBE_COMPATIBLE,
+        image_type: ImageType::Dim2d,
+        format: data.format,
+        extent: [data.size, data.size, 1],
+        array_layers: 6,
+        mip_levels: data.mip_levels,
+        usage: ImageUsage::SAMPLED,
+        ..Default::default()
+    };
+    let image = record_image_upload(builder, allocator, create_info, &data.bytes, name);
+    new_full_view(image)
+}
+
+/// The view type covering every layer of `image`.
+pub fn full_view_type(image: &Image) -> ImageViewType {
+    match image.array_layers() {
+        1 => ImageViewType::Dim2d,
+        6 if image.flags().intersects(ImageCreateFlags::CUBE_COMPATIBLE) => ImageViewType::Cube,
+        _ => ImageViewType::Dim2dArray
+    }
+}
+
+/// A view of every mip level and layer of `image`.
+pub fn new_full_view(image: Arc<Image>) -> Arc<ImageView> {
+    let create_info = ImageViewCreateInfo {
+        view_type: full_view_type(&image),
+        subresource_range: image.subresource_range(),
+        ..ImageViewCreateInfo::from_image(&image)
+    };
+    ImageView::new(image, create_info).expect("Fail to create image view.")
+}
+
+/// Uploads textures with a one-off command buffer on `queue`, usually
+/// `Framework::transfer_queue`, and waits for them.
+pub fn upload_now<R>(
+    allocator: &Allocator,
+    queue: &Arc<Queue>,
+    record: impl FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) -> R
+) -> R {
+    let mut builder = allocator.alloc_primary_builder(queue.queue_family_index(), CommandBufferUsage::OneTimeSubmit);
+    let uploaded = record(&mut builder);
+    builder.build().expect("Fail to build texture upload command buffer.")
+        .execute(queue.clone()).expect("Fail to execute texture upload.")
+        .then_signal_fence_and_flush().expect("Fail to flush texture upload.")
+        .wait(None).expect("Fail to wait for texture upload.");
+    uploaded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capture;
+
+    use super::*;
+
+    #[test]
+    fn parses_flat_and_run_length_encoded_hdr() {
+        let mut flat = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        flat.extend([128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = parse_hdr(&flat).unwrap();
+        assert_eq!(image.extent, [2, 1]);
+        assert_eq!(image.rgb, vec![[1.0, 0.5, 0.0], [0.0; 3]]);
+
+        // Eight texels: a run of red, literal green values, runs for blue
+        // and the shared exponent.
+        let mut encoded = b"#?RGBE\n\n-Y 1 +X 8\n".to_vec();
+        encoded.extend([2, 2, 0, 8]);
+        encoded.extend([128 + 8, 64]);
+        encoded.extend([8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        encoded.extend([128 + 8, 0]);
+        encoded.extend([128 + 8, 129]);
+        let image = parse_hdr(&encoded).unwrap();
+        assert_eq!(image.extent, [8, 1]);
+        assert_eq!(image.rgb[0], [0.5, 0.0, 0.0]);
+        assert_eq!(image.rgb[7], [0.5, 0.875, 0.0]);
+
+        assert!(parse_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(parse_hdr(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn mip_chain_sizes() {
+        assert_eq!(mip_extent([8, 2], 2), [2, 1]);
+        assert_eq!(mip_extent([8, 2], 5), [1, 1]);
+        // 4x4 + 2x2 + 1x1 texels, six layers of 8 bytes.
+        assert_eq!(image_byte_size(Format::R16G16B16A16_SFLOAT, [4, 4], 6, 3), 21 * 6 * 8);
+    }
+
+    /// A KTX2 cube whose texels hold their mip level and face.
+    fn ktx2_cube(size: u32, mip_levels: u32) -> Vec<u8> {
+        let mut header = KTX2_IDENTIFIER.to_vec();
+        for value in [43, 1, size, size, 0, 0, 6, mip_levels, 0] {
+            header.extend(u32::to_le_bytes(value));
+        }
+        header.resize(KTX2_HEADER_SIZE, 0);
+        let mut levels = Vec::new();
+        let mut data = Vec::new();
+        let data_start = KTX2_HEADER_SIZE + 24 * mip_levels as usize;
+        for mip_level in 0..mip_levels {
+            let texels = texel_count(mip_extent([size; 2], mip_level)) as usize;
+            let level: Vec<u8> = (0..6u8)
+                .flat_map(|face| std::iter::repeat_n([mip_level as u8, face, 0, 255], texels).flatten())
+                .collect();
+            levels.push(((data_start + data.len()) as u64, level.len() as u64));
+            data.extend(level);
+        }
+        for (offset, length) in levels {
+            header.extend(offset.to_le_bytes());
+            header.extend(length.to_le_bytes());
+            header.extend(length.to_le_bytes());
+        }
+        header.extend(data);
+        header
+    }
+
+    #[test]
+    fn parses_ktx2_cube_with_mips() {
+        let cube = parse_ktx2(&ktx2_cube(4, 3)).unwrap();
+        assert_eq!((cube.size, cube.format, cube.mip_levels), (4, Format::R8G8B8A8_SRGB, 3));
+        assert_eq!(cube.bytes.len() as DeviceSize, image_byte_size(cube.format, [4, 4], 6, 3));
+        // The last face of the first level, then the first texel of the
+        // second level.
+        assert_eq!(cube.bytes[5 * 16 * 4..5 * 16 * 4 + 4], [0, 5, 0, 255]);
+        assert_eq!(cube.bytes[6 * 16 * 4..6 * 16 * 4 + 4], [1, 0, 0, 255]);
+
+        let mut not_cube = ktx2_cube(4, 1);
+        not_cube[44..48].copy_from_slice(&1u32.to_le_bytes());
+        assert!(parse_ktx2(&not_cube).is_err());
+        let truncated = ktx2_cube(4, 2);
+        assert!(parse_ktx2(&truncated[..truncated.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_ktx2_cube() {
+        let mut empty = ktx2_cube(1, 1);
+        empty[20..28].fill(0);
+        let error = parse_ktx2(&empty).unwrap_err();
+        assert!(error.to_string().contains("empty cube"), "{error}");
+    }
+
+    #[test]
+    fn rejects_truncated_ktx2_cube_declaring_a_huge_extent() {
+        let mut huge = ktx2_cube(1, 1);
+        huge[20..28].copy_from_slice(&[u32::MAX.to_le_bytes(); 2].concat());
+        assert!(parse_ktx2(&huge).is_err());
+        let level_length = 6 * 4 * (1u64 << 40);
+        huge[20..28].copy_from_slice(&[(1u32 << 20).to_le_bytes(); 2].concat());
+        huge[KTX2_HEADER_SIZE + 8..KTX2_HEADER_SIZE + 16].copy_from_slice(&level_length.to_le_bytes());
+        let error = parse_ktx2(&huge).unwrap_err();
+        assert!(error.to_string().contains("truncated level data"), "{error}");
+    }
+
+    #[test]
+    fn loads_cube_faces_from_directory() {
+        let directory = std::env::temp_dir().join(format!("learn-vulkano-cube-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (i, face) in CUBE_FACE_NAMES.iter().enumerate() {
+            capture::write_png(&directory.join(format!("{face}.png")), [1, 1], &[i as u8, 0, 0, 255]).unwrap();
+        }
+        let cube = load_cube(&directory);
+        capture::write_png(&directory.join("nz.png"), [2, 1], &[0; 8]).unwrap();
+        let mismatched = load_cube(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let cube = cube.unwrap();
+        assert_eq!((cube.size, cube.format, cube.mip_levels), (1, Format::R8G8B8A8_SRGB, 1));
+        assert_eq!(cube.bytes.chunks_exact(4).map(|texel| texel[0]).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+        assert!(mismatched.is_err());
+    }
+
+    #[test]
+    fn loads_png_as_rgba8() {
+        let path = std::env::temp_dir().join(format!("learn-vulkano-texture-{}.png", std::process::id()));
+        let rgba = vec![10, 20, 30, 255, 40, 50, 60, 128];
+        capture::write_png(&path, [2, 1], &rgba).unwrap();
+        let data = load_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data, TextureData { extent: [2, 1], rgba });
+    }
+}